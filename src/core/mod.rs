@@ -3,5 +3,5 @@ mod token_type;
 mod tokens;
 
 pub use text_span::TextSpan;
-pub use token_type::TokenKind;
+pub use token_type::{TokenKind, TOKEN_KINDS};
 pub use tokens::Token;
@@ -6,6 +6,10 @@ pub struct TextSpan {
     pub to_line: usize,
     pub from_col: usize,
     pub to_col: usize,
+    /// Byte offsets of the span in the source, end exclusive. These are `u32`
+    /// to keep tokens, and the errors that carry them, small.
+    pub from_offset: u32,
+    pub to_offset: u32,
     pub lexeme: String,
 }
 
@@ -22,9 +26,17 @@ impl TextSpan {
             to_line,
             from_col,
             to_col,
+            from_offset: 0,
+            to_offset: 0,
             lexeme: lexeme.to_string(),
         }
     }
+
+    pub fn with_offsets(mut self, from_offset: u32, to_offset: u32) -> TextSpan {
+        self.from_offset = from_offset;
+        self.to_offset = to_offset;
+        self
+    }
 }
 
 impl Display for TextSpan {
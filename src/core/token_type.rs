@@ -1,5 +1,19 @@
-#[derive(Debug, PartialEq, Hash, Eq, Clone, Copy)]
-pub enum TokenKind {
+/// Declares [`TokenKind`] along with [`TOKEN_KINDS`], so that the list of
+/// every kind can't fall out of date.
+macro_rules! token_kinds {
+    ($($kind:ident,)*) => {
+        #[derive(Debug, PartialEq, Hash, Eq, Clone, Copy)]
+        pub enum TokenKind {
+            $($kind,)*
+        }
+
+        /// Every token kind, in declaration order.
+        pub const TOKEN_KINDS: [TokenKind; [$(TokenKind::$kind),*].len()] =
+            [$(TokenKind::$kind),*];
+    };
+}
+
+token_kinds! {
     Colon,
     Comma,
     Comment,
@@ -16,14 +30,21 @@ pub enum TokenKind {
     RParen,
     Semicolon,
     Whitespace,
+    Dot,
+    DotDot,
+    FatArrow,
+    Underscore,
+    Question,
     // Keywords
     As,
+    Const,
     Delay,
     Else,
     For,
     Function,
     If,
     Let,
+    Match,
     PadClear,
     PadHeight,
     PadRandI,
@@ -33,6 +54,8 @@ pub enum TokenKind {
     PadWriteBox,
     Print,
     Return,
+    Rgb,
+    Struct,
     While,
     // Binary operators
     And,
@@ -43,6 +66,14 @@ pub enum TokenKind {
     Not,
     Mod,
     Plus,
+    // Compound assignment and increment operators
+    PlusEquals,
+    MinusEquals,
+    MultiplyEquals,
+    DivideEquals,
+    ModEquals,
+    PlusPlus,
+    MinusMinus,
     // Relational operators
     EqEq,
     GreaterThan,
@@ -55,6 +86,22 @@ pub enum TokenKind {
     ColourLiteral,
     FloatLiteral,
     IntLiteral,
+    StringLiteral,
     Type,
     Arrow,
 }
+
+impl TokenKind {
+    /// The arithmetic operator applied by a compound assignment or an
+    /// increment/decrement, e.g. `Plus` for both `+=` and `++`.
+    pub fn arithmetic_operator(&self) -> Option<TokenKind> {
+        match self {
+            TokenKind::PlusEquals | TokenKind::PlusPlus => Some(TokenKind::Plus),
+            TokenKind::MinusEquals | TokenKind::MinusMinus => Some(TokenKind::Minus),
+            TokenKind::MultiplyEquals => Some(TokenKind::Multiply),
+            TokenKind::DivideEquals => Some(TokenKind::Divide),
+            TokenKind::ModEquals => Some(TokenKind::Mod),
+            _ => None,
+        }
+    }
+}
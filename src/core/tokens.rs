@@ -14,6 +14,50 @@ impl Token {
     pub fn new(kind: TokenKind, span: TextSpan) -> Token {
         Token { kind, span }
     }
+
+    /// The text of a string literal with its quotes stripped and escapes
+    /// resolved, or `None` if it contains an unknown escape.
+    pub fn string_value(&self) -> Option<String> {
+        let lexeme = &self.span.lexeme;
+        let mut value = String::new();
+        let mut chars = lexeme[1..lexeme.len() - 1].chars();
+
+        while let Some(c) = chars.next() {
+            value.push(match c {
+                '\\' => match chars.next()? {
+                    'n' => '\n',
+                    't' => '\t',
+                    'r' => '\r',
+                    '0' => '\0',
+                    '\\' => '\\',
+                    '"' => '"',
+                    _ => return None,
+                },
+                c => c,
+            });
+        }
+
+        Some(value)
+    }
+
+    /// The value of an int literal, which may be written in hex (`0x1F`) or
+    /// binary (`0b1010`) with `_` between digits, or `None` if it doesn't fit
+    /// in an `i64`.
+    pub fn int_value(&self) -> Option<i64> {
+        let digits = self.span.lexeme.replace('_', "");
+        match digits.get(..2) {
+            Some("0x" | "0X") => i64::from_str_radix(&digits[2..], 16).ok(),
+            Some("0b" | "0B") => i64::from_str_radix(&digits[2..], 2).ok(),
+            _ => digits.parse().ok(),
+        }
+    }
+
+    /// The value of a float literal, or `None` if it's too large to be a
+    /// finite `f32`.
+    pub fn float_value(&self) -> Option<f32> {
+        let value: f32 = self.span.lexeme.replace('_', "").parse().ok()?;
+        value.is_finite().then_some(value)
+    }
 }
 
 impl Display for Token {
@@ -1,8 +1,8 @@
-use std::fmt::Display;
+use std::{fmt::Display, ops::Range};
 
 use crate::{core::Token, semantics::utils::MemLoc};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Program {
     pub instructions: Vec<Instruction>,
     pub functions: Vec<Instruction>,
@@ -20,10 +20,167 @@ impl Display for Program {
     }
 }
 
+impl Program {
+    /// Each function's instructions after its label, with `main` last.
+    pub fn bodies(&self) -> Vec<(&str, &[Instruction])> {
+        let mut bodies = vec![];
+        for instructions in [&self.functions, &self.instructions] {
+            let starts: Vec<usize> = (0..instructions.len())
+                .filter(|i| matches!(instructions[*i], Instruction::FunctionLabel(_)))
+                .chain([instructions.len()])
+                .collect();
+
+            for bounds in starts.windows(2) {
+                if let Instruction::FunctionLabel(name) = &instructions[bounds[0]] {
+                    bodies.push((name.as_str(), &instructions[bounds[0] + 1..bounds[1]]));
+                }
+            }
+        }
+        bodies
+    }
+
+    /// The control-flow graph of every function as a Graphviz digraph, with
+    /// a cluster per function and a node per basic block. Blocks are labelled
+    /// with their instructions, each numbered from the start of the function.
+    pub fn to_cfg_dot(&self) -> String {
+        let mut dot = String::from("digraph cfg {\n    node [shape=box, fontname=monospace];\n");
+
+        for (f, (name, body)) in self.bodies().into_iter().enumerate() {
+            dot.push_str(&format!(
+                "    subgraph cluster_{f} {{\n        label=\".{name}\";\n"
+            ));
+
+            let blocks = basic_blocks(body);
+            for (b, block) in blocks.iter().enumerate() {
+                // Each line is escaped and then left-justified with `\l`
+                let label: String = block
+                    .instructions
+                    .clone()
+                    .map(|i| {
+                        let text = body[i].to_string();
+                        let text = text.trim_end().replace('\\', "\\\\").replace('"', "\\\"");
+                        format!("{i}: {text}\\l")
+                    })
+                    .collect();
+                dot.push_str(&format!("        f{f}b{b} [label=\"{label}\"];\n"));
+            }
+
+            for (b, block) in blocks.iter().enumerate() {
+                for (to, edge) in &block.successors {
+                    let label = match edge {
+                        Edge::Jump | Edge::Next => String::new(),
+                        Edge::True => " [label=\"true\"]".to_string(),
+                        Edge::False => " [label=\"false\"]".to_string(),
+                    };
+                    dot.push_str(&format!("        f{f}b{b} -> f{f}b{to}{label};\n"));
+                }
+            }
+
+            dot.push_str("    }\n");
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// How control gets from one basic block to another.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Edge {
+    /// Falling through to the next instruction
+    Next,
+    /// A `jmp`
+    Jump,
+    /// A `cjmp` that's taken
+    True,
+    /// A `cjmp` that isn't taken
+    False,
+}
+
+/// A run of instructions that's only entered at the first and only left
+/// after the last.
+#[derive(Debug, PartialEq)]
+pub struct BasicBlock {
+    pub instructions: Range<usize>,
+    /// The indices of the blocks control can go to next
+    pub successors: Vec<(usize, Edge)>,
+}
+
+/// Where the jump at `i` goes, when its address is pushed just before it
+/// relative to the program counter, as it always is in generated code.
+fn jump_target(body: &[Instruction], i: usize) -> Option<usize> {
+    match body.get(i.checked_sub(1)?)? {
+        Instruction::PushOffset(offset) => (i - 1).checked_add_signed(*offset as isize),
+        _ => None,
+    }
+}
+
+/// Splits a function's body into basic blocks, which start at jump targets
+/// and after jumps, returns and halts.
+pub fn basic_blocks(body: &[Instruction]) -> Vec<BasicBlock> {
+    let ends_block = |i: usize| {
+        matches!(
+            body[i],
+            Instruction::Jump
+                | Instruction::JumpIfNotZero
+                | Instruction::Return
+                | Instruction::Halt
+        )
+    };
+
+    let mut starts = vec![false; body.len() + 1];
+    starts[0] = true;
+    for i in 0..body.len() {
+        if ends_block(i) {
+            starts[i + 1] = true;
+        }
+        if let Instruction::Jump | Instruction::JumpIfNotZero = body[i] {
+            match jump_target(body, i) {
+                Some(target) if target < body.len() => starts[target] = true,
+                _ => {}
+            }
+        }
+    }
+
+    let leaders: Vec<usize> = (0..body.len()).filter(|i| starts[*i]).collect();
+    let block_of = |i: usize| leaders.partition_point(|start| *start <= i) - 1;
+
+    leaders
+        .iter()
+        .enumerate()
+        .map(|(b, start)| {
+            let end = leaders.get(b + 1).copied().unwrap_or(body.len());
+            let last = end - 1;
+            let target = jump_target(body, last)
+                .filter(|target| *target < body.len())
+                .map(block_of);
+            let next = (end < body.len()).then_some(b + 1);
+
+            let successors = match body[last] {
+                Instruction::Jump => target.map(|to| vec![(to, Edge::Jump)]).unwrap_or_default(),
+                Instruction::JumpIfNotZero => target
+                    .map(|to| (to, Edge::True))
+                    .into_iter()
+                    .chain(next.map(|to| (to, Edge::False)))
+                    .collect(),
+                Instruction::Return | Instruction::Halt => vec![],
+                _ => next.map(|to| vec![(to, Edge::Next)]).unwrap_or_default(),
+            };
+
+            BasicBlock {
+                instructions: *start..end,
+                successors,
+            }
+        })
+        .collect()
+}
+
 #[derive(Debug, Clone)]
 pub enum Instruction {
     FunctionLabel(String),
     PushValue(usize),
+    PushFloat(f32),
+    PushString(String),
     PushFunction(Token),
     PushOffset(i32),
     PushFromStack(MemLoc),
@@ -73,6 +230,8 @@ impl Display for Instruction {
             Instruction::Read => writeln!(f, "read"),
             Instruction::FunctionLabel(name) => writeln!(f, ".{}", name),
             Instruction::PushValue(value) => writeln!(f, "push {}", value),
+            Instruction::PushFloat(value) => writeln!(f, "push {}", value),
+            Instruction::PushString(value) => writeln!(f, "push {:?}", value),
             Instruction::PushFunction(name) => writeln!(f, "push .{}", name),
             Instruction::PushOffset(offset) => {
                 write!(f, "push ")?;
@@ -125,3 +284,115 @@ impl Display for Instruction {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::{
+        generation::visitors::parir_writer::PArIRWriter, lexing::Lexer, parsing::Parser,
+        semantics::visitors::SemAnalyzer, utils::Utf8Buffer,
+    };
+
+    use super::*;
+    use rstest::rstest;
+
+    const SOURCE: &str = r#"
+        fun f(x: int) -> int {
+            if (x > 0) { return 1; }
+            return 2;
+        }
+        let i: int = 0;
+        while (i < 3) { i = i + f(i); }
+        __print i;
+    "#;
+
+    fn compile(input: &str) -> Program {
+        let tokens = Lexer::<Utf8Buffer>::new(input, None).lex().unwrap();
+        let mut parser = Parser::new(&tokens, Path::new("test"));
+        let ast = parser.parse().unwrap();
+
+        let mut analyzer = SemAnalyzer::new();
+        analyzer.analyze(ast);
+
+        PArIRWriter::new().generate(analyzer.hir().unwrap()).clone()
+    }
+
+    fn edges(blocks: &[BasicBlock]) -> Vec<Vec<(usize, Edge)>> {
+        blocks.iter().map(|b| b.successors.clone()).collect()
+    }
+
+    #[rstest]
+    fn test_bodies() {
+        let program = compile(SOURCE);
+        let names: Vec<&str> = program.bodies().iter().map(|(name, _)| *name).collect();
+
+        assert_eq!(names, vec!["f", "main"]);
+    }
+
+    #[rstest]
+    fn test_basic_blocks() {
+        let program = compile(SOURCE);
+        let bodies = program.bodies();
+
+        let f = basic_blocks(bodies[0].1);
+        assert_eq!(f.len(), 4);
+        assert_eq!(
+            edges(&f),
+            vec![
+                vec![(2, Edge::True), (1, Edge::False)],
+                vec![(3, Edge::Jump)],
+                vec![],
+                vec![],
+            ]
+        );
+
+        let main = basic_blocks(bodies[1].1);
+        assert_eq!(
+            edges(&main),
+            vec![
+                vec![(1, Edge::Next)],
+                vec![(3, Edge::True), (2, Edge::False)],
+                vec![(1, Edge::Jump)],
+                vec![],
+            ]
+        );
+
+        // The blocks cover the whole body without overlapping
+        for blocks in [&f, &main] {
+            for pair in blocks.windows(2) {
+                assert_eq!(pair[0].instructions.end, pair[1].instructions.start);
+            }
+        }
+        assert_eq!(main.last().unwrap().instructions.end, bodies[1].1.len());
+    }
+
+    #[rstest]
+    #[case::straight_line("let x: int = 1; __print x;", 1)]
+    #[case::if_else("if (true) { __print 1; } else { __print 2; }", 4)]
+    fn test_block_count(#[case] input: &str, #[case] expected: usize) {
+        let program = compile(input);
+        let bodies = program.bodies();
+
+        assert_eq!(basic_blocks(bodies[0].1).len(), expected);
+    }
+
+    #[rstest]
+    fn test_cfg_dot() {
+        let dot = compile(SOURCE).to_cfg_dot();
+
+        assert!(dot.starts_with("digraph cfg {\n"));
+        assert!(dot.contains("label=\".f\";"));
+        assert!(dot.contains("label=\".main\";"));
+        assert!(dot.contains("f0b0 -> f0b2 [label=\"true\"];"));
+        assert!(dot.contains("f1b2 -> f1b1;"));
+        assert!(dot.ends_with("}\n"));
+    }
+
+    #[rstest]
+    fn test_cfg_dot_escapes_strings() {
+        let dot = compile(r#"__print "a\"b";"#).to_cfg_dot();
+
+        assert!(dot.contains(r#"push \"a\\\"b\"\l"#));
+    }
+}
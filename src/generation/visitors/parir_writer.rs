@@ -1,16 +1,22 @@
-use crate::generation::instructions::{Instruction, Program};
-use crate::semantics::utils::{Signature, Symbol, SymbolTable, SymbolType};
+use std::collections::{HashMap, HashSet};
 
-use crate::{
-    core::Token,
-    parsing::ast::{AstNode, Visitor},
-};
-use crate::{core::TokenKind, semantics::utils::MemLoc};
+use crate::core::TokenKind;
+use crate::generation::instructions::{Instruction, Program};
+use crate::semantics::const_eval::ConstValue;
+use crate::semantics::hir::{Hir, Node, NodeKind, Place};
+use crate::semantics::symbol_table::{SymbolId, SymbolTable};
+use crate::semantics::utils::{MemLoc, SymbolType, Type};
 
 #[derive(Debug)]
 pub struct PArIRWriter {
-    /// Stack of symbol tables, each representing a scope
-    symbol_table: Vec<SymbolTable>,
+    /// The symbols of the program being written
+    symbols: SymbolTable,
+    /// The fields of every struct, in declaration order
+    structs: HashMap<String, Vec<(String, Type)>>,
+    /// Where each variable is stored, with an absolute stack level
+    locations: HashMap<SymbolId, MemLoc>,
+    /// Stack of the slots taken in each scope that opens a frame
+    scope_slots: Vec<usize>,
     /// String containing the program's contents
     program: Program,
     /// Pointer to the current instruction
@@ -19,12 +25,21 @@ pub struct PArIRWriter {
     stack_level: usize,
     /// The current stack offset
     frame_index: usize,
+    /// The variables declared in the main frame, in declaration order
+    globals_declared: Vec<SymbolId>,
+    /// The globals each function receives a copy of, in frame order
+    function_globals: HashMap<SymbolId, Vec<SymbolId>>,
+    /// The globals the current function has copies of
+    current_globals: Vec<SymbolId>,
 }
 
 impl PArIRWriter {
     pub fn new() -> Self {
         PArIRWriter {
-            symbol_table: Vec::new(),
+            symbols: SymbolTable::new(),
+            structs: HashMap::new(),
+            locations: HashMap::new(),
+            scope_slots: Vec::new(),
             program: Program {
                 instructions: Vec::new(),
                 functions: Vec::new(),
@@ -32,12 +47,37 @@ impl PArIRWriter {
             instr_ptr: 0,
             stack_level: 0,
             frame_index: 0,
+            globals_declared: Vec::new(),
+            function_globals: HashMap::new(),
+            current_globals: Vec::new(),
         }
     }
 
-    pub fn get_program(&mut self, ast: &AstNode) -> String {
-        self.visit(ast);
-        format!("{}", self.program)
+    pub fn get_program(&mut self, hir: &Hir) -> String {
+        format!("{}", self.generate(hir))
+    }
+
+    pub fn generate(&mut self, hir: &Hir) -> &Program {
+        self.symbols = hir.symbols.clone();
+        self.structs = hir.structs.clone();
+
+        self.scope_slots.push(0);
+        self.add_instruction(Instruction::FunctionLabel("main".to_string()));
+
+        let var_count_push = self.add_instruction(Instruction::PushValue(0));
+        self.add_instruction(Instruction::NewFrame);
+
+        for statement in &hir.statements {
+            self.visit(statement);
+        }
+
+        self.program.instructions[var_count_push] =
+            Instruction::PushValue(self.scope_slots.pop().unwrap());
+
+        self.add_instruction(Instruction::PopFrame);
+        self.add_instruction(Instruction::Halt);
+
+        &self.program
     }
 
     fn add_instruction(&mut self, instruction: Instruction) -> usize {
@@ -46,110 +86,335 @@ impl PArIRWriter {
         self.instr_ptr - 1
     }
 
-    fn get_scope_var_count(&self) -> usize {
-        self.current_scope()
-            .symbols
-            .iter()
-            .filter(|s| matches!(s.symbol_type, SymbolType::Variable(_)))
-            .count()
+    fn symbol_type(&self, id: SymbolId) -> Type {
+        self.symbols.symbol(id).value_type()
     }
 
-    fn find_symbol(&self, symbol: &Token) -> Option<&Symbol> {
-        self.symbol_table
-            .iter()
-            .rev()
-            .find_map(|table| table.find_symbol(&symbol.span.lexeme))
+    /// Number of frame slots taken by a value of `value_type`. Structs and
+    /// tuples are flattened into one slot per scalar field, in order.
+    fn slot_count(&self, value_type: &Type) -> usize {
+        match value_type {
+            Type::Void => 0,
+            Type::Struct(name) => self.structs[name]
+                .iter()
+                .map(|(_, field_type)| self.slot_count(field_type))
+                .sum(),
+            Type::Tuple(types) => types.iter().map(|t| self.slot_count(t)).sum(),
+            _ => 1,
+        }
+    }
+
+    /// The slot offset and type of `fields` within a value of type `base`.
+    fn get_field_slot(&self, base: Type, fields: &[String]) -> (usize, Type) {
+        let mut offset = 0;
+        let mut current = base;
+
+        for field in fields {
+            let Type::Struct(name) = &current else {
+                unreachable!()
+            };
+
+            for (field_name, field_type) in &self.structs[name] {
+                if field_name == field {
+                    current = field_type.clone();
+                    break;
+                }
+                offset += self.slot_count(field_type);
+            }
+        }
+
+        (offset, current)
     }
 
-    fn current_scope(&self) -> &SymbolTable {
-        self.symbol_table.last().unwrap()
+    /// Reserves `count` slots in the current frame for `variable`, or finds
+    /// the ones it already has if it's being redeclared. Returns where the
+    /// value goes relative to the current frame.
+    fn declare(&mut self, variable: SymbolId, count: usize) -> MemLoc {
+        if let Some(mem_loc) = self.get_memory_location(variable) {
+            return mem_loc;
+        }
+
+        self.locations.insert(
+            variable,
+            MemLoc {
+                stack_level: self.stack_level,
+                frame_index: self.frame_index,
+            },
+        );
+        if self.scope_slots.len() == 1 {
+            self.globals_declared.push(variable);
+        }
+
+        let mem_loc = MemLoc {
+            stack_level: 0,
+            frame_index: self.frame_index,
+        };
+        self.frame_index += count;
+        *self.scope_slots.last_mut().unwrap() += count;
+
+        mem_loc
     }
 
-    fn mut_current_scope(&mut self) -> &mut SymbolTable {
-        self.symbol_table.last_mut().unwrap()
+    fn get_memory_location(&self, variable: SymbolId) -> Option<MemLoc> {
+        self.locations.get(&variable).map(|mem_loc| MemLoc {
+            stack_level: self.stack_level - mem_loc.stack_level,
+            frame_index: mem_loc.frame_index,
+        })
     }
 
-    fn add_symbol(&mut self, symbol: &Token, symbol_type: &SymbolType, mem_loc: Option<MemLoc>) {
-        self.mut_current_scope()
-            .add_symbol(&symbol.span.lexeme, symbol_type, mem_loc);
+    /// Where the value assigned to `place` is, with the slots it takes.
+    fn get_place_location(&self, place: &Place) -> Option<(MemLoc, usize)> {
+        let mem_loc = self.get_memory_location(place.variable)?;
+        let (offset, field_type) =
+            self.get_field_slot(self.symbol_type(place.variable), &place.fields);
+
+        Some((
+            MemLoc {
+                stack_level: mem_loc.stack_level,
+                frame_index: mem_loc.frame_index + offset,
+            },
+            self.slot_count(&field_type),
+        ))
     }
 
-    fn check_scope(&self, symbol: &Token) -> bool {
-        self.current_scope()
-            .find_symbol(&symbol.span.lexeme)
-            .is_some()
+    /// Pushes `count` consecutive slots starting at `mem_loc`, leaving the
+    /// first one on top of the stack.
+    fn push_slots(&mut self, mem_loc: &MemLoc, count: usize) {
+        for offset in (0..count).rev() {
+            self.add_instruction(Instruction::PushFromStack(MemLoc {
+                stack_level: mem_loc.stack_level,
+                frame_index: mem_loc.frame_index + offset,
+            }));
+        }
     }
 
-    fn get_memory_location(&self, symbol: &Token) -> Option<MemLoc> {
-        self.find_symbol(symbol).and_then(|s| {
-            let relative_mem_loc = s.memory_location.clone();
-            relative_mem_loc.map(|mem_loc| MemLoc {
-                stack_level: self.stack_level - mem_loc.stack_level,
-                frame_index: mem_loc.frame_index,
-            })
-        })
+    /// Pops `count` values into consecutive slots starting at `mem_loc`, the
+    /// value on top of the stack going into the first one.
+    fn store_slots(&mut self, mem_loc: &MemLoc, count: usize) {
+        for offset in 0..count {
+            self.add_instruction(Instruction::PushValue(mem_loc.frame_index + offset));
+            self.add_instruction(Instruction::PushValue(mem_loc.stack_level));
+            self.add_instruction(Instruction::Store);
+        }
     }
 
-    fn push_scope(&mut self) {
-        self.symbol_table.push(SymbolTable::new());
+    /// Where the current code sees a global: the variable itself in the main
+    /// frame, or the current function's copy of it. Also returns the number
+    /// of slots it takes.
+    fn get_global_location(&self, global: SymbolId) -> (MemLoc, usize) {
+        let mem_loc = self.get_memory_location(global).unwrap();
+        let count = self.slot_count(&self.symbol_type(global));
+
+        (mem_loc, count)
     }
 
-    fn pop_scope(&mut self) {
-        self.symbol_table.pop();
+    /// Pushes the current function's copies of its globals so that the
+    /// caller can store them back, leaving the first one on top.
+    fn push_globals_for_return(&mut self) {
+        for global in self.current_globals.clone().into_iter().rev() {
+            let (mem_loc, count) = self.get_global_location(global);
+            self.push_slots(&mem_loc, count);
+        }
     }
 
-    fn visit_unscoped_block(&mut self, block_node: &AstNode) -> usize {
-        match block_node {
-            AstNode::Block { statements } => {
-                for statement in statements {
-                    self.visit(statement);
-                }
-                self.instr_ptr
+    /// Pushes a folded constant. Negative ints are pushed as `0 - n`, since
+    /// `push` only takes unsigned values.
+    fn push_constant(&mut self, value: ConstValue) {
+        match value {
+            ConstValue::Int(value) if value < 0 => {
+                self.add_instruction(Instruction::PushValue(value.unsigned_abs() as usize));
+                self.add_instruction(Instruction::PushValue(0));
+                self.add_instruction(Instruction::Sub);
+            }
+            ConstValue::Int(value) => {
+                self.add_instruction(Instruction::PushValue(value as usize));
+            }
+            ConstValue::Float(value) => {
+                self.add_instruction(Instruction::PushFloat(value));
+            }
+            ConstValue::Bool(value) => {
+                self.add_instruction(Instruction::PushValue(value as usize));
+            }
+            ConstValue::Colour(value) => {
+                self.add_instruction(Instruction::PushValue(value as usize));
             }
-            _ => unreachable!(),
         }
     }
-}
 
-impl Visitor<usize> for PArIRWriter {
-    fn visit(&mut self, node: &AstNode) -> usize {
-        match node {
-            AstNode::Program { statements } => {
-                self.push_scope();
-                self.add_instruction(Instruction::FunctionLabel("main".to_string()));
+    /// Leaves the channel of the colour at `mem_loc` with place value `scale`
+    /// on the stack, as `colour / scale % 256`.
+    fn push_channel(&mut self, mem_loc: &MemLoc, scale: u32) {
+        if scale != 0x10000 {
+            self.add_instruction(Instruction::PushValue(256));
+        }
+        if scale != 1 {
+            self.add_instruction(Instruction::PushValue(scale as usize));
+        }
+        self.add_instruction(Instruction::PushFromStack(mem_loc.clone()));
+        if scale != 1 {
+            self.add_instruction(Instruction::Div);
+        }
+        if scale != 0x10000 {
+            self.add_instruction(Instruction::Mod);
+        }
+    }
 
-                let var_count_push = self.add_instruction(Instruction::PushValue(0));
-                self.add_instruction(Instruction::NewFrame);
+    /// Clamps the value on top of the stack to `0..=upper`.
+    fn clamp(&mut self, upper: usize) {
+        self.add_instruction(Instruction::PushValue(0));
+        self.add_instruction(Instruction::Max);
+        self.add_instruction(Instruction::PushValue(upper));
+        self.add_instruction(Instruction::Min);
+    }
 
-                for statement in statements {
-                    self.visit(statement);
-                }
+    /// Moves a channel value on top of the stack to its place in a packed
+    /// colour, adding it to the channels already combined below it.
+    fn combine_channel(&mut self, scale: u32) {
+        if scale != 1 {
+            self.add_instruction(Instruction::PushValue(scale as usize));
+            self.add_instruction(Instruction::Mul);
+        }
+        if scale != 0x10000 {
+            self.add_instruction(Instruction::Add);
+        }
+    }
 
-                self.program.instructions[var_count_push] =
-                    Instruction::PushValue(self.get_scope_var_count());
+    /// Applies an arithmetic operator to the two colours on top of the stack,
+    /// the left operand on top, one channel at a time. Each channel saturates
+    /// to `0..=255`, and dividing by a zero channel divides by 1 instead.
+    fn write_colour_bin_op(&mut self, operator: TokenKind) {
+        let left = self.store_in_hidden_slot();
+        let right = self.store_in_hidden_slot();
+
+        for scale in [0x10000, 0x100, 1] {
+            self.push_channel(&right, scale);
+            if operator == TokenKind::Divide {
+                self.add_instruction(Instruction::PushValue(1));
+                self.add_instruction(Instruction::Max);
+            }
+            self.push_channel(&left, scale);
+            self.add_instruction(bin_op_instruction(operator));
+            self.clamp(255);
+            self.combine_channel(scale);
+        }
+    }
 
-                self.add_instruction(Instruction::PopFrame);
-                self.add_instruction(Instruction::Halt);
-                self.pop_scope();
+    /// Opens a frame for a scope, returning the placeholder for its size.
+    fn push_frame(&mut self) -> usize {
+        self.scope_slots.push(0);
+        let var_count_push = self.add_instruction(Instruction::PushValue(0));
+        self.add_instruction(Instruction::NewFrame);
+        self.stack_level += 1;
+
+        var_count_push
+    }
+
+    /// Reserves an unnamed slot in the current frame and stores the value on
+    /// top of the stack in it. Returns where it is in the current frame.
+    fn store_in_hidden_slot(&mut self) -> MemLoc {
+        let mem_loc = MemLoc {
+            stack_level: 0,
+            frame_index: self.frame_index,
+        };
+
+        self.add_instruction(Instruction::PushValue(self.frame_index));
+        self.add_instruction(Instruction::PushValue(0));
+        self.add_instruction(Instruction::Store);
+        self.frame_index += 1;
+        *self.scope_slots.last_mut().unwrap() += 1;
+
+        mem_loc
+    }
+
+    /// Emits `target <op>= expression`, or `target++`/`target--` when there is
+    /// no expression, for the scalar of type `target_type` at `mem_loc`.
+    fn write_compound_assignment(
+        &mut self,
+        mem_loc: &MemLoc,
+        target_type: &Type,
+        operator: TokenKind,
+        expression: &Option<Box<Node>>,
+    ) {
+        let steps_by_one = match expression.as_deref() {
+            None => true,
+            Some(expression) => expression.kind == NodeKind::Constant(ConstValue::Int(1)),
+        };
+
+        // Stepping by one uses `inc`/`dec` instead of `push 1` and `add`/`sub`
+        match (operator, steps_by_one) {
+            _ if *target_type == Type::Colour => {
+                if let Some(expression) = expression {
+                    self.visit(expression);
+                }
+                self.add_instruction(Instruction::PushFromStack(mem_loc.clone()));
+                self.write_colour_bin_op(operator);
             }
+            (TokenKind::Plus, true) => {
+                self.add_instruction(Instruction::PushFromStack(mem_loc.clone()));
+                self.add_instruction(Instruction::Inc);
+            }
+            (TokenKind::Minus, true) => {
+                self.add_instruction(Instruction::PushFromStack(mem_loc.clone()));
+                self.add_instruction(Instruction::Dec);
+            }
+            _ => {
+                if let Some(expression) = expression {
+                    self.visit(expression);
+                }
+                self.add_instruction(Instruction::PushFromStack(mem_loc.clone()));
+                self.add_instruction(bin_op_instruction(operator));
+            }
+        }
 
-            AstNode::Block { statements } => {
-                self.push_scope();
-                let var_dec_count = self.add_instruction(Instruction::PushValue(0));
-                self.add_instruction(Instruction::NewFrame);
-                self.stack_level += 1;
-                self.frame_index = 0;
+        self.store_slots(mem_loc, 1);
+    }
+
+    fn visit_unscoped_block(&mut self, statements: &[Node]) -> usize {
+        for statement in statements {
+            self.visit(statement);
+        }
+        self.instr_ptr
+    }
+}
+
+fn bin_op_instruction(operator: TokenKind) -> Instruction {
+    match operator {
+        TokenKind::Plus => Instruction::Add,
+        TokenKind::Minus => Instruction::Sub,
+        TokenKind::Multiply => Instruction::Mul,
+        TokenKind::Mod => Instruction::Mod,
+        TokenKind::Divide => Instruction::Div,
+        TokenKind::EqEq => Instruction::Equal,
+        TokenKind::LessThan => Instruction::LessThan,
+        TokenKind::LessThanEqual => Instruction::LessThanOrEqual,
+        TokenKind::GreaterThan => Instruction::GreaterThan,
+        TokenKind::GreaterThanEqual => Instruction::GreaterThanOrEqual,
+        TokenKind::And => Instruction::And,
+        TokenKind::Or => Instruction::Or,
+        _ => Instruction::NoOperation,
+    }
+}
+
+impl PArIRWriter {
+    fn visit(&mut self, node: &Node) -> usize {
+        match &node.kind {
+            NodeKind::Block(statements) => {
+                let var_dec_count = self.push_frame();
+                let outer_frame_index = std::mem::replace(&mut self.frame_index, 0);
                 for statement in statements {
                     // if the statement is a return statement, we don't need to
                     // check the rest of the block
-                    if let AstNode::Return { expression } = statement {
-                        self.visit(expression);
+                    if let NodeKind::Return(expression) = &statement.kind {
+                        if let Some(expression) = expression {
+                            self.visit(expression);
+                        }
                         self.program.instructions[var_dec_count] =
-                            Instruction::PushValue(self.get_scope_var_count());
+                            Instruction::PushValue(self.scope_slots.pop().unwrap());
+                        self.push_globals_for_return();
                         self.add_instruction(Instruction::Return);
                         self.add_instruction(Instruction::PopFrame);
                         self.stack_level -= 1;
-                        self.pop_scope();
+                        self.frame_index = outer_frame_index;
                         return self.instr_ptr;
                     } else {
                         self.visit(statement);
@@ -157,53 +422,82 @@ impl Visitor<usize> for PArIRWriter {
                 }
 
                 self.program.instructions[var_dec_count] =
-                    Instruction::PushValue(self.get_scope_var_count());
+                    Instruction::PushValue(self.scope_slots.pop().unwrap());
 
                 self.add_instruction(Instruction::PopFrame);
                 self.stack_level -= 1;
-                self.pop_scope();
+                self.frame_index = outer_frame_index;
             }
 
-            AstNode::FunctionDecl {
-                identifier,
+            NodeKind::FunctionDecl {
+                function,
                 params,
-                return_type,
-                block,
+                body,
             } => {
-                self.add_symbol(
-                    identifier,
-                    &SymbolType::Function(Signature::new(
-                        self.current_scope().token_to_type(&return_type.span.lexeme),
-                    )),
-                    None,
-                );
-
-                self.push_scope();
+                // Frames opened by calls aren't nested in the main frame at
+                // a fixed depth, so functions can't address globals directly.
+                // Instead, they receive a copy of every global declared so
+                // far that they use, directly or through the functions they
+                // call, after their parameters, and hand the copies back on
+                // return for the caller to store.
+                let mut used = HashSet::new();
+                node.collect_symbols(&mut used);
+                for id in used.clone() {
+                    if let Some(callee_globals) = self.function_globals.get(&id) {
+                        used.extend(callee_globals.iter().copied());
+                    }
+                }
+
+                let globals: Vec<SymbolId> = self
+                    .globals_declared
+                    .iter()
+                    .copied()
+                    .filter(|global| used.contains(global))
+                    .collect();
+
+                self.function_globals.insert(*function, globals.clone());
+
+                let outer_stack_level = self.stack_level;
+                let outer_frame_index = std::mem::replace(&mut self.frame_index, 0);
                 self.stack_level += 1;
-                self.frame_index = 0;
+                self.scope_slots.push(0);
 
-                // Add the parameter symbols to the symbol table in this scope
                 for param in params {
-                    self.visit(param);
+                    let count = self.slot_count(&self.symbol_type(*param));
+                    self.declare(*param, count);
                 }
 
-                // all the parameters are added to the symbol table
-                // now we add them to the function signature
-                let mut signature =
-                    Signature::new(self.current_scope().token_to_type(&return_type.span.lexeme));
+                // The copies shadow the globals until the function ends
+                let mut outer_locations = vec![];
+                for global in &globals {
+                    let mem_loc = MemLoc {
+                        stack_level: self.stack_level,
+                        frame_index: self.frame_index,
+                    };
+                    outer_locations.push(self.locations.insert(*global, mem_loc));
+                    self.frame_index += self.slot_count(&self.symbol_type(*global));
+                }
+                let outer_globals = std::mem::replace(&mut self.current_globals, globals.clone());
 
-                for param in self.current_scope().all_symbols() {
-                    if let SymbolType::Variable(t) = param.symbol_type {
-                        signature.parameters.push((t, param.lexeme.clone()));
+                let start = self.instr_ptr;
+                self.visit_unscoped_block(body);
+
+                // Void functions may fall off the end of their body
+                if let SymbolType::Function(signature) = &self.symbols.symbol(*function).symbol_type
+                {
+                    if signature.return_type == Type::Void {
+                        self.push_globals_for_return();
+                        self.add_instruction(Instruction::Return);
                     }
                 }
 
-                let start = self.instr_ptr;
-                let end = self.visit_unscoped_block(block);
-                let var_count = self.get_scope_var_count();
+                let end = self.instr_ptr;
+                let var_count = self.scope_slots.pop().unwrap();
 
                 self.program.functions.extend([
-                    Instruction::FunctionLabel(identifier.span.lexeme.clone()),
+                    Instruction::FunctionLabel(
+                        self.symbols.symbol(*function).token.span.lexeme.clone(),
+                    ),
                     Instruction::PushValue(var_count),
                     Instruction::Alloc,
                 ]);
@@ -212,96 +506,206 @@ impl Visitor<usize> for PArIRWriter {
                     .functions
                     .extend(self.program.instructions.drain(start..end));
 
-                self.pop_scope();
-                self.stack_level = 0;
+                for (global, mem_loc) in globals.iter().zip(outer_locations) {
+                    if let Some(mem_loc) = mem_loc {
+                        self.locations.insert(*global, mem_loc);
+                    }
+                }
+                self.current_globals = outer_globals;
+                self.stack_level = outer_stack_level;
                 self.instr_ptr -= end - start;
-                self.frame_index = 0;
+                self.frame_index = outer_frame_index;
             }
 
-            AstNode::FunctionCall { identifier, args } => {
+            // Functions used as values don't use globals, so only the
+            // arguments are passed
+            NodeKind::IndirectCall { variable, args } => {
                 for arg in args.iter().rev() {
                     self.visit(arg);
                 }
-                self.add_instruction(Instruction::PushValue(args.len()));
-                self.add_instruction(Instruction::PushFunction(identifier.clone()));
+
+                let slot_count = args.iter().map(|arg| self.slot_count(&arg.ty)).sum();
+
+                self.add_instruction(Instruction::PushValue(slot_count));
+                if let Some(mem_loc) = self.get_memory_location(*variable) {
+                    self.push_slots(&mem_loc, 1);
+                }
                 self.add_instruction(Instruction::Call);
             }
 
-            AstNode::Identifier { token } => {
-                if let Some(mem_loc) = self.get_memory_location(token) {
-                    return self.add_instruction(Instruction::PushFromStack(mem_loc));
+            NodeKind::Call { function, args } => {
+                let globals = self
+                    .function_globals
+                    .get(function)
+                    .cloned()
+                    .unwrap_or_default();
+
+                let mut slot_count = 0;
+                for global in globals.iter().rev() {
+                    let (mem_loc, count) = self.get_global_location(*global);
+                    self.push_slots(&mem_loc, count);
+                    slot_count += count;
+                }
+                for arg in args.iter().rev() {
+                    self.visit(arg);
+                }
+                slot_count += args
+                    .iter()
+                    .map(|arg| self.slot_count(&arg.ty))
+                    .sum::<usize>();
+
+                self.add_instruction(Instruction::PushValue(slot_count));
+                self.add_instruction(Instruction::PushFunction(
+                    self.symbols.symbol(*function).token.clone(),
+                ));
+                self.add_instruction(Instruction::Call);
+
+                // Store back the globals the function may have changed
+                for global in &globals {
+                    let (mem_loc, count) = self.get_global_location(*global);
+                    self.store_slots(&mem_loc, count);
                 }
             }
 
-            AstNode::VarDec {
-                identifier,
-                r#type,
-                expression,
-            } => {
-                self.visit(expression);
+            NodeKind::CallStatement(call) => {
+                self.visit(call);
 
-                if !self.check_scope(identifier) {
-                    self.add_symbol(
-                        identifier,
-                        &SymbolType::Variable(
-                            self.current_scope().token_to_type(&r#type.span.lexeme),
-                        ),
-                        Some(MemLoc {
-                            stack_level: self.stack_level,
-                            frame_index: self.frame_index,
-                        }),
-                    );
+                // Nothing is left on the stack by a void function
+                for _ in 0..self.slot_count(&call.ty) {
+                    self.add_instruction(Instruction::Drop);
                 }
+            }
 
-                self.add_instruction(Instruction::PushValue(self.frame_index));
-                self.add_instruction(Instruction::PushValue(0));
-                self.frame_index += 1;
-                self.add_instruction(Instruction::Store);
+            NodeKind::Constant(value) => self.push_constant(*value),
+
+            NodeKind::Function(function) => {
+                self.add_instruction(Instruction::PushFunction(
+                    self.symbols.symbol(*function).token.clone(),
+                ));
+            }
+
+            NodeKind::Variable(variable) => {
+                if let Some(mem_loc) = self.get_memory_location(*variable) {
+                    self.push_slots(&mem_loc, self.slot_count(&node.ty));
+                }
             }
 
-            AstNode::FormalParam {
-                identifier,
-                param_type,
+            NodeKind::VarDec {
+                variable,
+                expression,
             } => {
-                self.add_symbol(
-                    identifier,
-                    &SymbolType::Variable(
-                        self.current_scope().token_to_type(&param_type.span.lexeme),
-                    ),
-                    Some(MemLoc {
-                        stack_level: self.stack_level,
-                        frame_index: self.frame_index,
-                    }),
-                );
-                self.frame_index += 1;
+                self.visit(expression);
+
+                let count = self.slot_count(&self.symbol_type(*variable));
+                let mem_loc = self.declare(*variable, count);
+                self.store_slots(&mem_loc, count);
             }
 
-            AstNode::Expression {
-                casted_type: _,
-                expr,
+            NodeKind::TupleDec {
+                variables,
+                expression,
             } => {
-                self.visit(expr);
+                self.visit(expression);
+
+                let Type::Tuple(element_types) = &expression.ty else {
+                    unreachable!()
+                };
+
+                // The first element is on top, so the values are stored or
+                // dropped in order
+                for (variable, element_type) in variables.iter().zip(element_types.iter()) {
+                    let count = self.slot_count(element_type);
+
+                    match variable {
+                        Some(variable) => {
+                            let mem_loc = self.declare(*variable, count);
+                            self.store_slots(&mem_loc, count);
+                        }
+                        None => {
+                            for _ in 0..count {
+                                self.add_instruction(Instruction::Drop);
+                            }
+                        }
+                    }
+                }
             }
 
-            AstNode::SubExpression { bin_op } => {
-                self.visit(bin_op);
+            // Like struct literals, the first element ends up on top
+            NodeKind::Tuple(elements) => {
+                for element in elements.iter().rev() {
+                    self.visit(element);
+                }
+            }
+
+            // Ints are clamped into the range of colours, every other cast
+            // leaves the value as-is
+            NodeKind::Cast(expression) => {
+                self.visit(expression);
+
+                if node.ty == Type::Colour && expression.ty == Type::Int {
+                    self.clamp(0xFFFFFF);
+                }
+            }
+
+            NodeKind::Assignment { target, expression } => {
+                self.visit(expression);
+
+                if let Some((mem_loc, count)) = self.get_place_location(target) {
+                    self.store_slots(&mem_loc, count);
+                }
             }
 
-            AstNode::Assignment {
-                identifier,
+            NodeKind::CompoundAssignment {
+                target,
+                operator,
                 expression,
             } => {
-                self.visit(expression);
-                let mem_loc = self.get_memory_location(identifier);
+                let Some((mem_loc, _)) = self.get_place_location(target) else {
+                    return self.instr_ptr;
+                };
+
+                self.write_compound_assignment(&mem_loc, &target.ty, *operator, expression);
+            }
+
+            // Values are in declaration order and pushed last field first, so
+            // the first field ends up on top of the stack like any other
+            // struct value
+            NodeKind::StructLiteral(values) => {
+                for value in values.iter().rev() {
+                    self.visit(value);
+                }
+            }
+
+            NodeKind::Field { variable, fields } => {
+                if let Some(mem_loc) = self.get_memory_location(*variable) {
+                    let (offset, field_type) =
+                        self.get_field_slot(self.symbol_type(*variable), fields);
+                    let mem_loc = MemLoc {
+                        stack_level: mem_loc.stack_level,
+                        frame_index: mem_loc.frame_index + offset,
+                    };
+
+                    self.push_slots(&mem_loc, self.slot_count(&field_type));
+                }
+            }
 
-                if let Some(mem_loc) = mem_loc {
-                    self.add_instruction(Instruction::PushValue(mem_loc.frame_index));
-                    self.add_instruction(Instruction::PushValue(mem_loc.stack_level));
-                    self.add_instruction(Instruction::Store);
+            NodeKind::Channel {
+                variable,
+                fields,
+                scale,
+            } => {
+                if let Some(mem_loc) = self.get_memory_location(*variable) {
+                    let (offset, _) = self.get_field_slot(self.symbol_type(*variable), fields);
+                    let mem_loc = MemLoc {
+                        stack_level: mem_loc.stack_level,
+                        frame_index: mem_loc.frame_index + offset,
+                    };
+
+                    self.push_channel(&mem_loc, *scale);
                 }
             }
 
-            AstNode::BinOp {
+            NodeKind::BinOp {
                 left,
                 operator,
                 right,
@@ -309,95 +713,111 @@ impl Visitor<usize> for PArIRWriter {
                 self.visit(right);
                 self.visit(left);
 
-                self.add_instruction(match operator.kind {
-                    TokenKind::Plus => Instruction::Add,
-                    TokenKind::Minus => Instruction::Sub,
-                    TokenKind::Multiply => Instruction::Mul,
-                    TokenKind::Mod => Instruction::Mod,
-                    TokenKind::Divide => Instruction::Div,
-                    TokenKind::EqEq => Instruction::Equal,
-                    TokenKind::LessThan => Instruction::LessThan,
-                    TokenKind::LessThanEqual => Instruction::LessThanOrEqual,
-                    TokenKind::GreaterThan => Instruction::GreaterThan,
-                    TokenKind::GreaterThanEqual => Instruction::GreaterThanOrEqual,
-                    TokenKind::And => Instruction::And,
-                    TokenKind::Or => Instruction::Or,
-                    _ => Instruction::NoOperation,
-                });
-            }
-
-            AstNode::UnaryOp { operator, expr } => {
-                self.visit(expr);
-
-                match operator.kind {
-                    TokenKind::Minus => self.add_instruction(Instruction::Sub),
+                match operator {
+                    TokenKind::Plus
+                    | TokenKind::Minus
+                    | TokenKind::Multiply
+                    | TokenKind::Divide
+                        if left.ty == Type::Colour =>
+                    {
+                        self.write_colour_bin_op(*operator)
+                    }
+                    _ => {
+                        self.add_instruction(bin_op_instruction(*operator));
+                    }
+                }
+            }
+
+            // Only the chosen branch is evaluated, like an `if` with an `else`
+            NodeKind::Conditional {
+                condition,
+                if_true,
+                if_false,
+            } => {
+                self.visit(condition);
+
+                let jump_to_true = self.add_instruction(Instruction::PushOffset(0));
+                self.add_instruction(Instruction::JumpIfNotZero);
+
+                self.visit(if_false);
+
+                let jump_to_end = self.add_instruction(Instruction::PushOffset(0));
+                self.add_instruction(Instruction::Jump);
+
+                self.program.instructions[jump_to_true] =
+                    Instruction::PushOffset(self.instr_ptr as i32 - jump_to_true as i32);
+
+                self.visit(if_true);
+
+                self.program.instructions[jump_to_end] =
+                    Instruction::PushOffset(self.instr_ptr as i32 - jump_to_end as i32);
+            }
+
+            NodeKind::Rgb { red, green, blue } => {
+                for (channel, scale) in [(red, 0x10000), (green, 0x100), (blue, 1)] {
+                    self.visit(channel);
+                    self.clamp(255);
+                    self.combine_channel(scale);
+                }
+            }
+
+            NodeKind::UnaryOp {
+                operator,
+                expression,
+            } => {
+                self.visit(expression);
+
+                match operator {
+                    TokenKind::Minus => {
+                        // `sub` takes the top of the stack as its left operand
+                        self.add_instruction(Instruction::PushValue(0));
+                        self.add_instruction(Instruction::Sub)
+                    }
                     TokenKind::Not => self.add_instruction(Instruction::Not),
                     _ => unreachable!(),
                 };
             }
 
-            AstNode::PadWidth => {
+            NodeKind::PadWidth => {
                 self.add_instruction(Instruction::Width);
             }
 
-            AstNode::PadRandI { upper_bound } => {
+            NodeKind::PadRandI(upper_bound) => {
                 self.visit(upper_bound);
 
                 self.add_instruction(Instruction::RandInt);
             }
 
-            AstNode::PadHeight => {
+            NodeKind::PadHeight => {
                 self.add_instruction(Instruction::Height);
             }
 
-            AstNode::PadRead { x, y } => {
+            NodeKind::PadRead { x, y } => {
                 self.visit(y);
                 self.visit(x);
 
                 self.add_instruction(Instruction::Read);
             }
 
-            AstNode::IntLiteral(l) => {
-                self.add_instruction(Instruction::PushValue(l.span.lexeme.parse().unwrap()));
+            NodeKind::String(value) => {
+                self.add_instruction(Instruction::PushString(value.clone()));
             }
 
-            AstNode::FloatLiteral(l) => {
-                // TODO: Fix into string
-                self.add_instruction(Instruction::PushValue(l.span.lexeme.parse().unwrap()));
-            }
-
-            AstNode::BoolLiteral(l) => {
-                match l.span.lexeme.as_str() {
-                    "true" => self.add_instruction(Instruction::PushValue(1)),
-                    "false" => self.add_instruction(Instruction::PushValue(0)),
-                    _ => unreachable!(),
-                };
-            }
-
-            AstNode::ColourLiteral(l) => {
-                let colour = u32::from_str_radix(&l.span.lexeme[1..], 16).unwrap();
-
-                self.add_instruction(Instruction::PushValue(colour as usize));
-            }
-
-            AstNode::ActualParams { params } => {
-                for param in params {
-                    self.visit(param);
-                }
-            }
-
-            AstNode::Delay { expression } => {
+            NodeKind::Delay(expression) => {
                 self.visit(expression);
                 self.add_instruction(Instruction::Delay);
             }
 
-            AstNode::Return { expression } => {
-                self.visit(expression);
+            NodeKind::Return(expression) => {
+                if let Some(expression) = expression {
+                    self.visit(expression);
+                }
+                self.push_globals_for_return();
 
                 self.add_instruction(Instruction::Return);
             }
 
-            AstNode::PadWriteBox {
+            NodeKind::PadWriteBox {
                 loc_x,
                 loc_y,
                 width,
@@ -413,7 +833,7 @@ impl Visitor<usize> for PArIRWriter {
                 self.add_instruction(Instruction::WriteBox);
             }
 
-            AstNode::PadWrite {
+            NodeKind::PadWrite {
                 loc_x,
                 loc_y,
                 colour,
@@ -425,7 +845,7 @@ impl Visitor<usize> for PArIRWriter {
                 self.add_instruction(Instruction::Write);
             }
 
-            AstNode::If {
+            NodeKind::If {
                 condition,
                 if_true,
                 if_false,
@@ -454,17 +874,14 @@ impl Visitor<usize> for PArIRWriter {
                     Instruction::PushOffset(self.instr_ptr as i32 - jump_to_end as i32);
             }
 
-            AstNode::For {
+            NodeKind::For {
                 initializer,
                 condition,
                 increment,
                 body,
             } => {
-                self.push_scope();
-                let push_var_count_placeholder = self.add_instruction(Instruction::PushValue(0));
-                self.add_instruction(Instruction::NewFrame);
-                self.stack_level += 1;
-                self.frame_index = 0;
+                let push_var_count_placeholder = self.push_frame();
+                let outer_frame_index = std::mem::replace(&mut self.frame_index, 0);
 
                 if let Some(initializer) = initializer {
                     self.visit(initializer);
@@ -488,22 +905,18 @@ impl Visitor<usize> for PArIRWriter {
                 self.add_instruction(Instruction::Jump);
 
                 self.program.instructions[push_var_count_placeholder] =
-                    Instruction::PushValue(self.get_scope_var_count());
+                    Instruction::PushValue(self.scope_slots.pop().unwrap());
 
                 let pop = self.add_instruction(Instruction::PopFrame);
                 self.program.instructions[jump_to_end_placeholder] =
                     Instruction::PushOffset(pop as i32 - jump_to_end_placeholder as i32);
-                self.pop_scope();
                 self.stack_level -= 1;
+                self.frame_index = outer_frame_index;
             }
 
-            AstNode::While { condition, body } => {
-                self.push_scope();
-                self.stack_level += 1;
-                self.frame_index = 0;
-
-                let var_count_push = self.add_instruction(Instruction::PushValue(0));
-                self.add_instruction(Instruction::NewFrame);
+            NodeKind::While { condition, body } => {
+                let var_count_push = self.push_frame();
+                let outer_frame_index = std::mem::replace(&mut self.frame_index, 0);
 
                 let before_condition = self.instr_ptr;
                 self.visit(condition);
@@ -518,27 +931,85 @@ impl Visitor<usize> for PArIRWriter {
                 self.add_instruction(Instruction::Jump);
 
                 self.program.instructions[var_count_push] =
-                    Instruction::PushValue(self.get_scope_var_count());
+                    Instruction::PushValue(self.scope_slots.pop().unwrap());
 
                 self.stack_level -= 1;
                 let pop = self.add_instruction(Instruction::PopFrame);
                 self.program.instructions[jump_to_end] =
                     Instruction::PushOffset(pop as i32 - jump_to_end as i32);
 
-                self.pop_scope();
+                self.frame_index = outer_frame_index;
             }
 
-            AstNode::Print { expression } => {
+            NodeKind::Match {
+                expression,
+                arms,
+                default,
+            } => {
+                // Lowered to a compare chain; the scrutinee is evaluated once
+                // and kept in a hidden slot so each arm can re-read it.
                 self.visit(expression);
-                self.add_instruction(Instruction::Print);
+                let slot = self.store_in_hidden_slot();
+                let scrutinee = Instruction::PushFromStack(slot);
+
+                let mut jumps_to_end = vec![];
+
+                for arm in arms {
+                    match arm.to {
+                        Some(to) => {
+                            self.add_instruction(Instruction::PushValue(arm.from));
+                            self.add_instruction(scrutinee.clone());
+                            self.add_instruction(Instruction::GreaterThanOrEqual);
+                            self.add_instruction(Instruction::PushValue(to));
+                            self.add_instruction(scrutinee.clone());
+                            self.add_instruction(Instruction::LessThanOrEqual);
+                            self.add_instruction(Instruction::And);
+                        }
+                        None => {
+                            self.add_instruction(Instruction::PushValue(arm.from));
+                            self.add_instruction(scrutinee.clone());
+                            self.add_instruction(Instruction::Equal);
+                        }
+                    }
+
+                    self.add_instruction(Instruction::Not);
+                    let jump_to_next = self.add_instruction(Instruction::PushOffset(0));
+                    self.add_instruction(Instruction::JumpIfNotZero);
+
+                    self.visit_unscoped_block(&arm.body);
+                    jumps_to_end.push(self.add_instruction(Instruction::PushOffset(0)));
+                    self.add_instruction(Instruction::Jump);
+
+                    self.program.instructions[jump_to_next] =
+                        Instruction::PushOffset(self.instr_ptr as i32 - jump_to_next as i32);
+                }
+
+                if let Some(default) = default {
+                    self.visit_unscoped_block(default);
+                }
+
+                for jump in jumps_to_end {
+                    self.program.instructions[jump] =
+                        Instruction::PushOffset(self.instr_ptr as i32 - jump as i32);
+                }
+            }
+
+            NodeKind::Print(expressions) => {
+                // Each argument is printed on its own, in order
+                for expression in expressions {
+                    self.visit(expression);
+                    self.add_instruction(Instruction::Print);
+                }
             }
 
-            AstNode::PadClear { expr } => {
-                self.visit(expr);
+            NodeKind::PadClear(colour) => {
+                self.visit(colour);
                 self.add_instruction(Instruction::Clear);
             }
 
-            AstNode::EndOfFile => {}
+            // Errors never reach the writer, and declarations of constants and
+            // structs need no code
+            NodeKind::Empty | NodeKind::Error => {}
         }
         self.instr_ptr
     }
@@ -547,53 +1018,24 @@ impl Visitor<usize> for PArIRWriter {
 #[cfg(test)]
 mod tests {
     use crate::{
-        lexing::Lexer,
-        parsing::Parser,
-        semantics::utils::{SymbolType, Type},
-        utils::{errors::Error, SimpleBuffer},
+        lexing::Lexer, parsing::Parser, semantics::visitors::SemAnalyzer, utils::Utf8Buffer,
     };
 
     use super::*;
-    use assert_matches::assert_matches;
     use rstest::rstest;
     use std::path::Path;
 
-    fn run_scope_checker(input: &str) -> Result<(), Error> {
-        let mut lexer: Lexer<SimpleBuffer> = Lexer::new(input, Path::new(""), None);
-
+    fn get_program(input: &str) -> String {
+        let mut lexer: Lexer<Utf8Buffer> = Lexer::new(input, None);
         let tokens = lexer.lex().unwrap();
 
         let mut parser = Parser::new(&tokens, Path::new(""));
-        let ast = parser.parse()?;
-
-        let mut scope_checker = PArIRWriter::new();
-        scope_checker.visit(ast);
-
-        Ok(())
-    }
-
-    #[rstest]
-    fn test_symbol_table() {
-        let mut symbol_table = SymbolTable::new();
+        let ast = parser.parse().unwrap();
 
-        symbol_table.add_symbol("x", &SymbolType::Variable(Type::Int), None);
-        symbol_table.add_symbol("y", &SymbolType::Variable(Type::Float), None);
-        symbol_table.add_symbol("z", &SymbolType::Variable(Type::Bool), None);
+        let mut analyzer = SemAnalyzer::new();
+        assert!(!analyzer.analyze(ast).has_errors());
 
-        assert_matches!(
-            symbol_table.find_symbol("x").unwrap().symbol_type,
-            SymbolType::Variable(Type::Int)
-        );
-
-        assert_matches!(
-            symbol_table.find_symbol("y").unwrap().symbol_type,
-            SymbolType::Variable(Type::Float)
-        );
-
-        assert_matches!(
-            symbol_table.find_symbol("z").unwrap().symbol_type,
-            SymbolType::Variable(Type::Bool)
-        );
+        PArIRWriter::new().get_program(analyzer.hir().unwrap())
     }
 
     #[rstest]
@@ -614,6 +1056,247 @@ mod tests {
             let b: float = x + y;
         "#;
 
-        assert!(run_scope_checker(input).is_ok());
+        assert!(get_program(input).starts_with(".foo\npush 4\nalloc\n"));
+    }
+
+    #[rstest]
+    fn test_match_lowering() {
+        let input = r#"
+            let x: int = 3;
+            match (x) {
+                1 => { __print 1; }
+                2..4 => { __print 2; }
+                _ => { __print 0; }
+            }
+        "#;
+
+        let program = get_program(input);
+
+        // x and the hidden scrutinee slot
+        assert!(program.starts_with(".main\npush 2\noframe\n"));
+
+        // A literal arm skips to the next arm unless the scrutinee equals it,
+        // and jumps past the rest of the match once its block has run
+        assert!(program.contains(concat!(
+            "push [0:0]\npush 1\npush 0\nst\n",
+            "push 1\npush [1:0]\neq\nnot\npush #PC+6\ncjmp\n",
+            "push 1\nprint\npush #PC+18\njmp\n",
+        )));
+
+        // A range arm checks both inclusive bounds, and the default arm runs
+        // when every other arm was skipped
+        assert!(program.ends_with(concat!(
+            "push 2\npush [1:0]\nge\npush 4\npush [1:0]\nle\nand\nnot\npush #PC+6\ncjmp\n",
+            "push 2\nprint\npush #PC+4\njmp\n",
+            "push 0\nprint\n",
+            "cframe\nhalt\n",
+        )));
+    }
+
+    #[rstest]
+    fn test_call_statements() {
+        let input = r#"
+            fun draw() {
+                __write 1, 1, #ff0000;
+            }
+
+            fun one() -> int {
+                return 1;
+            }
+
+            draw();
+            one();
+        "#;
+
+        let program = get_program(input);
+
+        // `draw` gets an implicit return and only `one`'s result is dropped
+        assert!(program.contains("write\nret\n"));
+        assert!(
+            program.ends_with("push .draw\ncall\npush 0\npush .one\ncall\ndrop\ncframe\nhalt\n")
+        );
+    }
+
+    #[rstest]
+    fn test_compound_assignment() {
+        let input = r#"
+            let x: int = 0;
+            x++;
+            x -= 1;
+            x *= 3;
+        "#;
+
+        let program = get_program(input);
+
+        assert!(program.contains("push [0:0]\ninc\npush 0\npush 0\nst\n"));
+        assert!(program.contains("push [0:0]\ndec\npush 0\npush 0\nst\n"));
+        assert!(program.contains("push 3\npush [0:0]\nmul\npush 0\npush 0\nst\n"));
+    }
+
+    #[rstest]
+    fn test_global_copies() {
+        let input = r#"
+            let count: int = 0;
+
+            fun bump() {
+                count++;
+            }
+
+            while (count < 10) {
+                bump();
+            }
+        "#;
+
+        let program = get_program(input);
+
+        // `bump` works on its own copy of `count` and returns it...
+        assert!(program.starts_with(
+            ".bump\npush 0\nalloc\npush [0:0]\ninc\npush 0\npush 0\nst\npush [0:0]\nret\n"
+        ));
+        // ...which the loop stores back one frame down
+        assert!(program.contains("push [0:1]\npush 1\npush .bump\ncall\npush 0\npush 1\nst\n"));
+    }
+
+    #[rstest]
+    fn test_frame_index_restored_after_scope() {
+        let input = r#"
+            let a: int = 1;
+            while (a < 2) {
+                let b: int = 2;
+                let c: int = 3;
+                a = a + 1;
+            }
+            let d: int = 4;
+        "#;
+
+        let program = get_program(input);
+
+        assert!(program.ends_with("push 4\npush 1\npush 0\nst\ncframe\nhalt\n"));
+    }
+
+    #[rstest]
+    fn test_consts_are_inlined() {
+        let input = r#"
+            const size: int = 4 * 2;
+            const offset: int = 1 - size;
+            let x: int = size + offset;
+        "#;
+
+        let program = get_program(input);
+
+        // Only `x` gets a slot, and the folded values replace the constants
+        assert_eq!(
+            program,
+            ".main\npush 1\noframe\npush 7\npush 0\nsub\npush 8\nadd\npush 0\npush 0\nst\ncframe\nhalt\n"
+        );
+    }
+
+    #[rstest]
+    fn test_struct_layout() {
+        let input = r#"
+            struct Point { x: int, y: int }
+            struct Line { from: Point, to: Point }
+
+            let l: Line = Line { from: Point { x: 1, y: 2 }, to: Point { x: 3, y: 4 } };
+            l.to.x = l.from.y;
+        "#;
+
+        let program = get_program(input);
+
+        // The literal leaves `from.x` on top, which goes in the first slot...
+        assert!(program.starts_with(
+            ".main\npush 4\noframe\npush 4\npush 3\npush 2\npush 1\npush 0\npush 0\nst\n"
+        ));
+        // ...and fields are addressed by their offset in the flattened layout
+        assert!(program.ends_with("push [1:0]\npush 2\npush 0\nst\ncframe\nhalt\n"));
+    }
+
+    #[rstest]
+    fn test_print_arguments() {
+        let input = r#"__print "x = ", 4, "\t\"done\"";"#;
+
+        let program = get_program(input);
+
+        assert_eq!(
+            program,
+            ".main\npush 0\noframe\npush \"x = \"\nprint\npush 4\nprint\npush \"\\t\\\"done\\\"\"\nprint\ncframe\nhalt\n"
+        );
+    }
+
+    #[rstest]
+    fn test_colour_channels() {
+        let input = r#"
+            const base: colour = rgb(300, 128, -4);
+            let c: colour = rgb(1, 2, 3);
+            let g: int = c.g + base.r;
+            c = c * base;
+        "#;
+
+        let program = get_program(input);
+
+        // Each channel is clamped before being moved into place
+        assert!(program.starts_with(
+            ".main\npush 4\noframe\npush 1\npush 0\nmax\npush 255\nmin\npush 65536\nmul\n"
+        ));
+        // A channel of a variable is extracted at runtime, one of a constant
+        // is folded
+        assert!(program.contains(
+            "push 255\npush 256\npush 256\npush [0:0]\ndiv\nmod\nadd\npush 1\npush 0\nst\n"
+        ));
+        // Colour arithmetic works on each channel of the operands, which are
+        // kept in hidden slots, and saturates
+        assert!(program.contains(
+            "push 16744448\npush [0:0]\npush 2\npush 0\nst\npush 3\npush 0\nst\npush 65536\npush [3:0]\ndiv\npush 65536\npush [2:0]\ndiv\nmul\npush 0\nmax\npush 255\nmin\n"
+        ));
+        assert!(program.ends_with("push 0\npush 0\nst\ncframe\nhalt\n"));
+    }
+
+    #[rstest]
+    fn test_conditional_lowering() {
+        let input = r#"
+            let x: int = 3;
+            let c: colour = x > 2 ? #ff0000 : #0000ff;
+        "#;
+
+        let program = get_program(input);
+
+        // The false branch falls through and jumps over the true branch
+        assert!(program.contains(
+            "push 2\npush [0:0]\ngt\npush #PC+5\ncjmp\npush 255\npush #PC+3\njmp\npush 16711680\npush 1\npush 0\nst\n"
+        ));
+    }
+
+    #[rstest]
+    fn test_tuple_destructuring() {
+        let input = r#"
+            fun pair() -> (int, int) { return (1, 2); }
+            let (x, _) = pair();
+        "#;
+
+        let program = get_program(input);
+
+        // Both values are left on the stack, the first one on top
+        assert_eq!(
+            program,
+            ".pair\npush 0\nalloc\npush 2\npush 1\nret\n.main\npush 1\noframe\npush 0\npush .pair\ncall\npush 0\npush 0\nst\ndrop\ncframe\nhalt\n"
+        );
+    }
+
+    #[rstest]
+    fn test_indirect_calls() {
+        let input = r#"
+            fun double(v: int) -> int { return v * 2; }
+            fun apply(f: fun(int) -> int, v: int) -> int { return f(v); }
+            let x: int = apply(double, 4);
+        "#;
+
+        let program = get_program(input);
+
+        // The function value is pushed like any other argument, and called
+        // in place of a label
+        assert!(
+            program.contains(".apply\npush 2\nalloc\npush [1:0]\npush 1\npush [0:0]\ncall\nret\n")
+        );
+        assert!(program.contains("push 4\npush .double\npush 2\npush .apply\ncall\n"));
     }
 }
@@ -2,7 +2,10 @@ use std::{cmp::max, collections::HashMap};
 
 use crate::core::TokenKind;
 
-use super::transition::Transition;
+use super::{
+    regex::{Nfa, Regex},
+    transition::Transition,
+};
 
 #[derive(Debug, PartialEq, Hash, Eq, Clone, Copy)]
 pub enum Category {
@@ -35,28 +38,115 @@ pub enum Category {
     Any,
     Exclamation,
     HexAndLetter,
+    Quote,
+    Backslash,
+    Question,
+    Zero,
+    One,
+    HexPrefix,
+    BinaryPrefix,
+    Exponent,
+    /// Any alphabetic character outside of ASCII, so that identifiers can be
+    /// written in other scripts.
+    UnicodeLetter,
 }
 
-#[derive(Debug)]
+/// Every category a letter can fall in. A few letters get their own category
+/// so that number literals can pick out prefixes and exponents.
+const LETTERS: [Category; 6] = [
+    Category::Letter,
+    Category::UnicodeLetter,
+    Category::HexAndLetter,
+    Category::HexPrefix,
+    Category::BinaryPrefix,
+    Category::Exponent,
+];
+
+const DIGITS: [Category; 3] = [Category::Zero, Category::One, Category::Digit];
+
+/// Every category, in declaration order so that `CATEGORIES[c as usize] == c`.
+pub const CATEGORIES: [Category; 38] = [
+    Category::Whitespace,
+    Category::Letter,
+    Category::Digit,
+    Category::Underscore,
+    Category::Newline,
+    Category::RBrace,
+    Category::LBrace,
+    Category::RParen,
+    Category::LParen,
+    Category::RBracket,
+    Category::LBracket,
+    Category::Period,
+    Category::Semicolon,
+    Category::Asterisk,
+    Category::Colon,
+    Category::Equals,
+    Category::Slash,
+    Category::Plus,
+    Category::Percent,
+    Category::LessThan,
+    Category::GreaterThan,
+    Category::Minus,
+    Category::Eof,
+    Category::Other,
+    Category::Comma,
+    Category::Hashtag,
+    Category::Any,
+    Category::Exclamation,
+    Category::HexAndLetter,
+    Category::Quote,
+    Category::Backslash,
+    Category::Question,
+    Category::Zero,
+    Category::One,
+    Category::HexPrefix,
+    Category::BinaryPrefix,
+    Category::Exponent,
+    Category::UnicodeLetter,
+];
+
+const CATEGORY_COUNT: usize = CATEGORIES.len();
+
+/// A DFSA stored as dense tables, so that lexing a character costs two array
+/// lookups. Transitions on `Category::Any` are folded into every row when it's
+/// built.
+#[derive(Debug, Clone)]
 pub struct Dfsa {
-    accepted_states: Vec<i32>,
+    /// The category of each ASCII character, indexed by the character.
+    ascii_categories: [Category; 128],
+    /// The categories of any other characters that have one.
     character_table: HashMap<char, Category>,
-    transition_table: HashMap<(i32, Category), i32>,
-    state_to_token: HashMap<i32, TokenKind>,
+    /// The next state, indexed by `state * CATEGORY_COUNT + category`.
+    transition_table: Vec<i32>,
+    /// The token each state accepts, if it's accepting.
+    state_to_token: Vec<Option<TokenKind>>,
 }
 
 impl Dfsa {
-    fn new(
-        accepted_states: Vec<i32>,
-        character_table: HashMap<char, Category>,
-        transition_table: HashMap<(i32, Category), i32>,
-        state_to_token: HashMap<i32, TokenKind>,
-    ) -> Self {
+    fn new(builder: &DfsaBuilder) -> Self {
+        let mut ascii_categories = [Category::Other; 128];
+        for (c, category) in &builder.character_table {
+            if c.is_ascii() {
+                ascii_categories[*c as usize] = *category;
+            }
+        }
+
+        let mut tokens = vec![None; builder.state_count()];
+        for state in &builder.accepted_states {
+            tokens[*state as usize] = Some(builder.token_kind(*state));
+        }
+
         Dfsa {
-            accepted_states,
-            character_table,
-            transition_table,
-            state_to_token,
+            ascii_categories,
+            character_table: builder
+                .character_table
+                .iter()
+                .filter(|(c, _)| !c.is_ascii())
+                .map(|(c, category)| (*c, *category))
+                .collect(),
+            transition_table: builder.dense_table(),
+            state_to_token: tokens,
         }
     }
 
@@ -72,34 +162,101 @@ impl Dfsa {
         -1
     }
 
+    fn token(&self, state: i32) -> Option<TokenKind> {
+        usize::try_from(state)
+            .ok()
+            .and_then(|state| self.state_to_token.get(state).copied().flatten())
+    }
+
     pub fn get_token_kind(&self, state: i32) -> TokenKind {
-        *self
-            .state_to_token
-            .get(&state)
-            .unwrap_or(&TokenKind::Invalid)
+        self.token(state).unwrap_or(TokenKind::Invalid)
     }
 
     pub fn is_accepting(&self, state: &i32) -> bool {
-        self.accepted_states.contains(state)
+        self.token(*state).is_some()
     }
 
     pub fn get_category(&self, c: char) -> Category {
-        *self.character_table.get(&c).unwrap_or(&Category::Other)
+        if c.is_ascii() {
+            return self.ascii_categories[c as usize];
+        }
+
+        match self.character_table.get(&c) {
+            Some(category) => *category,
+            None if c.is_alphabetic() => Category::UnicodeLetter,
+            None => Category::Other,
+        }
     }
 
     pub fn delta(&self, state: i32, category: Category) -> i32 {
-        let next_state = self.transition_table.get(&(state, category));
+        match usize::try_from(state) {
+            Ok(state) => self.transition_table[state * CATEGORY_COUNT + category as usize],
+            Err(_) => -2,
+        }
+    }
 
-        match next_state {
-            Some(next_state) => *next_state,
-            None => match self.transition_table.get(&(state, Category::Any)) {
-                Some(next_state) => *next_state,
-                None => -2,
-            },
+    /// Renders the automaton as a Graphviz digraph. Transitions into the
+    /// error state are left out, and categories that share a target are
+    /// drawn as one edge.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from(
+            "digraph dfsa {\n    rankdir=LR;\n    node [shape=circle];\n    start [shape=point];\n    start -> 0;\n",
+        );
+
+        let state_count = self.state_to_token.len();
+        for (state, token) in self.state_to_token.iter().enumerate() {
+            if let Some(token) = token {
+                dot.push_str(&format!(
+                    "    {state} [shape=doublecircle, label=\"{state}\\n{token:?}\"];\n"
+                ));
+            }
         }
+
+        let mut rejects = false;
+        for state in 0..state_count {
+            let row = &self.transition_table[state * CATEGORY_COUNT..(state + 1) * CATEGORY_COUNT];
+            let mut targets: Vec<i32> = row.iter().copied().filter(|to| *to != -2).collect();
+            targets.sort();
+            targets.dedup();
+
+            for to in targets {
+                let (on, off): (Vec<Category>, Vec<Category>) = CATEGORIES
+                    .into_iter()
+                    .filter(|c| *c != Category::Any)
+                    .partition(|c| row[*c as usize] == to);
+
+                let label = match on.len() > off.len() {
+                    true => format!("any but {}", category_list(&off)),
+                    false => category_list(&on),
+                };
+                let target = match to {
+                    -1 => {
+                        rejects = true;
+                        "reject".to_string()
+                    }
+                    _ => to.to_string(),
+                };
+                dot.push_str(&format!("    {state} -> {target} [label=\"{label}\"];\n"));
+            }
+        }
+
+        if rejects {
+            dot.push_str("    reject [shape=box];\n");
+        }
+
+        dot.push_str("}\n");
+        dot
     }
 }
 
+fn category_list(categories: &[Category]) -> String {
+    categories
+        .iter()
+        .map(|c| format!("{c:?}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 #[derive(Debug, Clone)]
 pub struct DfsaBuilder {
     pub max_state: i32,
@@ -107,6 +264,7 @@ pub struct DfsaBuilder {
     pub character_table: HashMap<char, Category>,
     pub transition_table: HashMap<(i32, Category), i32>,
     pub state_to_token: HashMap<i32, TokenKind>,
+    pub minimise: bool,
 }
 
 impl DfsaBuilder {
@@ -118,7 +276,182 @@ impl DfsaBuilder {
             character_table: HashMap::new(),
             transition_table: HashMap::new(),
             state_to_token: HashMap::new(),
+            minimise: false,
+        }
+    }
+
+    /// Makes `build` minimise the automaton first.
+    pub fn minimised(&mut self) -> &mut Self {
+        self.minimise = true;
+        self
+    }
+
+    fn state_count(&self) -> usize {
+        self.transition_table
+            .iter()
+            .flat_map(|((from, _), to)| [*from, *to])
+            .chain(self.accepted_states.iter().copied())
+            .max()
+            .map_or(1, |max| max as usize + 1)
+    }
+
+    fn token_kind(&self, state: i32) -> TokenKind {
+        *self
+            .state_to_token
+            .get(&state)
+            .unwrap_or(&TokenKind::Invalid)
+    }
+
+    /// The transitions as a `state * CATEGORY_COUNT + category` table, with
+    /// `Category::Any` folded into every category without a transition of
+    /// its own.
+    fn dense_table(&self) -> Vec<i32> {
+        let mut table = vec![-2; self.state_count() * CATEGORY_COUNT];
+
+        for ((state, category), next_state) in &self.transition_table {
+            if *category == Category::Any {
+                let row = *state as usize * CATEGORY_COUNT;
+                table[row..row + CATEGORY_COUNT].fill(*next_state);
+            }
+        }
+
+        for ((state, category), next_state) in &self.transition_table {
+            if *category != Category::Any {
+                table[*state as usize * CATEGORY_COUNT + *category as usize] = *next_state;
+            }
         }
+
+        table
+    }
+
+    /// Merges equivalent states with Hopcroft's algorithm. Accepting states
+    /// are only merged if they accept the same token, and the bad and error
+    /// states are kept apart from the rest, so tokenisation is unchanged.
+    fn minimise_states(&self) -> DfsaBuilder {
+        let table = self.dense_table();
+        let real_states = self.state_count();
+        // The bad state, -1, and the error state, -2, become the last two
+        // states so that every state has a transition on every category
+        let (bad, error) = (real_states, real_states + 1);
+        let state_count = real_states + 2;
+        let delta = |state: usize, category: usize| -> usize {
+            match state {
+                _ if state >= real_states => error,
+                _ => match table[state * CATEGORY_COUNT + category] {
+                    -1 => bad,
+                    -2 => error,
+                    next_state => next_state as usize,
+                },
+            }
+        };
+
+        // Incoming transitions for each category and target state
+        let mut incoming = vec![vec![Vec::new(); state_count]; CATEGORY_COUNT];
+        for state in 0..state_count {
+            for (category, incoming) in incoming.iter_mut().enumerate() {
+                incoming[delta(state, category)].push(state);
+            }
+        }
+
+        // Start from the states grouped by what they accept
+        let mut initial: HashMap<Option<TokenKind>, Vec<usize>> = HashMap::new();
+        for state in 0..real_states {
+            let token = self
+                .accepted_states
+                .contains(&(state as i32))
+                .then(|| self.token_kind(state as i32));
+            initial.entry(token).or_default().push(state);
+        }
+        let mut blocks: Vec<Vec<usize>> = initial.into_values().collect();
+        blocks.sort();
+        blocks.push(vec![bad]);
+        blocks.push(vec![error]);
+
+        let mut block_of = vec![0; state_count];
+        for (i, block) in blocks.iter().enumerate() {
+            for state in block {
+                block_of[*state] = i;
+            }
+        }
+
+        let mut waiting: Vec<usize> = (0..blocks.len()).collect();
+        while let Some(splitter) = waiting.pop() {
+            let splitter = blocks[splitter].clone();
+            for incoming in &incoming {
+                let sources: Vec<usize> = splitter
+                    .iter()
+                    .flat_map(|target| incoming[*target].iter().copied())
+                    .collect();
+
+                let mut touched: Vec<usize> = sources.iter().map(|s| block_of[*s]).collect();
+                touched.sort();
+                touched.dedup();
+
+                for block in touched {
+                    let (inside, outside): (Vec<usize>, Vec<usize>) = blocks[block]
+                        .iter()
+                        .partition(|state| sources.contains(state));
+                    if outside.is_empty() {
+                        continue;
+                    }
+
+                    let new_block = blocks.len();
+                    for state in &outside {
+                        block_of[*state] = new_block;
+                    }
+
+                    // Only the smaller half needs to split further blocks,
+                    // unless the block was already waiting to
+                    let smaller = match inside.len() <= outside.len() {
+                        true => block,
+                        false => new_block,
+                    };
+                    blocks[block] = inside;
+                    blocks.push(outside);
+                    match waiting.contains(&block) {
+                        true => waiting.push(new_block),
+                        false => waiting.push(smaller),
+                    }
+                }
+            }
+        }
+
+        // Number the blocks with the start state's first, and keep -1 and -2
+        let mut numbers = vec![None; blocks.len()];
+        numbers[block_of[bad]] = Some(-1);
+        numbers[block_of[error]] = Some(-2);
+        let mut next_number = 0;
+        for state in 0..real_states {
+            if numbers[block_of[state]].is_none() {
+                numbers[block_of[state]] = Some(next_number);
+                next_number += 1;
+            }
+        }
+        let number = |state: usize| numbers[block_of[state]].unwrap();
+
+        let mut minimised = DfsaBuilder::new();
+        minimised.character_table = self.character_table.clone();
+        minimised.max_state = next_number - 1;
+        for block in blocks.iter().filter(|block| block[0] < real_states) {
+            let state = block[0];
+            for category in CATEGORIES.into_iter().filter(|c| *c != Category::Any) {
+                let next_state = number(delta(state, category as usize));
+                if next_state != -2 {
+                    minimised
+                        .transition_table
+                        .insert((number(state), category), next_state);
+                }
+            }
+
+            if self.accepted_states.contains(&(state as i32)) {
+                minimised.accepted_states.push(number(state));
+                minimised
+                    .state_to_token
+                    .insert(number(state), self.token_kind(state as i32));
+            }
+        }
+
+        minimised
     }
 
     /// Add a range of characters to the character table, mapping to the same
@@ -166,7 +499,58 @@ impl DfsaBuilder {
         next_state
     }
 
-    pub fn transition(&mut self) -> Transition {
+    /// Adds a token defined by a regular expression, e.g.
+    /// `add_token("[0-9]+", TokenKind::IntLiteral)`, to the transitions so
+    /// far. Its characters need to have categories already. The lexer takes
+    /// the longest match, and where two tokens match the same text, the one
+    /// added first wins.
+    ///
+    /// # Panics
+    ///
+    /// If `pattern` isn't a valid [`Regex`], or only matches some of the
+    /// characters in a category.
+    pub fn add_token(&mut self, pattern: &str, token_kind: TokenKind) -> &mut Self {
+        let regex = Regex::parse(pattern).unwrap_or_else(|e| panic!("{e}"));
+        let table = self.dense_table();
+        let state_count = self.state_count();
+
+        // The states so far become the first states of the NFA, then the
+        // regex is started alongside the start state
+        let mut nfa = Nfa::default();
+        for _ in 0..state_count {
+            nfa.add_state();
+        }
+        let bad = nfa.add_state();
+
+        for state in 0..state_count {
+            for category in CATEGORIES.into_iter().filter(|c| *c != Category::Any) {
+                match table[state * CATEGORY_COUNT + category as usize] {
+                    -2 => {}
+                    -1 => nfa.add_edge(state, category, bad),
+                    next_state => nfa.add_edge(state, category, next_state as usize),
+                }
+            }
+
+            if self.accepted_states.contains(&(state as i32)) {
+                nfa.set_token(state, 0, self.token_kind(state as i32));
+            }
+        }
+
+        let (start, end) = nfa
+            .add_regex(&regex, &self.character_table, pattern)
+            .unwrap_or_else(|e| panic!("{e}"));
+        nfa.set_token(end, 1, token_kind);
+
+        let (transitions, tokens) = nfa.to_dfsa(&[0, start], bad);
+        self.transition_table = transitions;
+        self.accepted_states = tokens.iter().map(|(state, _)| *state).collect();
+        self.state_to_token = tokens.into_iter().collect();
+        self.max_state = self.state_count() as i32 - 1;
+
+        self
+    }
+
+    pub fn transition(&mut self) -> Transition<'_> {
         self.max_state += 1;
         Transition::new(self)
     }
@@ -233,6 +617,27 @@ impl DfsaBuilder {
             Some(TokenKind::Comment),
         );
 
+        // Anything else after the '*' is still part of the comment, and more
+        // '*'s could still be the one before the slash
+        self.auto_add_transition(
+            multiline_comment_end_asterisk_state,
+            Category::Any,
+            Some(in_multiline_comment_state),
+            None,
+        );
+        self.auto_add_transition(
+            multiline_comment_end_asterisk_state,
+            Category::Asterisk,
+            Some(multiline_comment_end_asterisk_state),
+            None,
+        );
+        self.auto_add_transition(
+            multiline_comment_end_asterisk_state,
+            Category::Eof,
+            Some(-1),
+            None,
+        );
+
         // If we see a single slash after the first slash, then we're in a single line comment
         let single_line_comment_state =
             self.auto_add_transition(slash_state, Category::Slash, None, None);
@@ -253,24 +658,24 @@ impl DfsaBuilder {
             Some(TokenKind::Comment),
         );
 
+        // The end of the file also ends the comment, but isn't part of it
+        self.accepted_states.push(single_line_comment_state);
+        self.state_to_token
+            .insert(single_line_comment_state, TokenKind::Comment);
+        self.auto_add_transition(single_line_comment_state, Category::Eof, Some(-2), None);
+
         self
     }
 
     pub fn add_identifier_logic(&mut self) -> &mut Self {
         self.add_category(['_'], Category::Underscore)
             .transition()
-            .to([
-                Category::Letter,
-                Category::HexAndLetter,
-                Category::Underscore,
-            ])
+            .to(LETTERS.into_iter().chain([Category::Underscore]))
             .goes_to(TokenKind::Identifier)
-            .to([
-                Category::Letter,
-                Category::HexAndLetter,
-                Category::Underscore,
-                Category::Digit,
-            ])
+            .to(LETTERS
+                .into_iter()
+                .chain(DIGITS)
+                .chain([Category::Underscore]))
             .repeated()
             .goes_to(TokenKind::Identifier)
             .done();
@@ -280,27 +685,67 @@ impl DfsaBuilder {
 
     pub fn add_number_logic(&mut self) -> &mut Self {
         self.add_category(['.'], Category::Period)
-            .transition()
-            .to([Category::Digit])
-            .repeated()
-            .goes_to(TokenKind::IntLiteral)
+            .add_category(['0'], Category::Zero)
+            .add_category(['1'], Category::One)
+            .add_category(['x', 'X'], Category::HexPrefix)
+            .add_category(['b', 'B'], Category::BinaryPrefix)
+            .add_category(['e', 'E'], Category::Exponent)
+            .add_category(['#'], Category::Hashtag);
+
+        // Any two digits may be separated by an underscore, e.g. `1_000`.
+        // Floats need a digit after the period, so that `1..5` is still lexed
+        // as a range.
+        let digits = "[0-9](_?[0-9])*";
+        let exponent = format!("[eE][+-]?{digits}");
+        self.add_token(digits, TokenKind::IntLiteral)
+            .add_token("0[xX][0-9a-fA-F](_?[0-9a-fA-F])*", TokenKind::IntLiteral)
+            .add_token("0[bB][01](_?[01])*", TokenKind::IntLiteral)
+            .add_token(
+                &format!("{digits}(\\.{digits}({exponent})?|{exponent})"),
+                TokenKind::FloatLiteral,
+            )
+            .add_token(
+                &format!("#{}", "[0-9a-fA-F]".repeat(6)),
+                TokenKind::ColourLiteral,
+            );
+
+        // Field access, e.g. `rect.x`, and the range operator used by match
+        // arms, e.g. `1..5`
+        self.transition()
             .to([Category::Period])
-            .to([Category::Digit])
-            .repeated()
-            .goes_to(TokenKind::FloatLiteral)
+            .goes_to(TokenKind::Dot)
+            .to([Category::Period])
+            .goes_to(TokenKind::DotDot)
             .done();
 
-        self.add_category(['#'], Category::Hashtag)
-            .transition()
-            .to([Category::Hashtag])
-            .to([Category::Digit, Category::HexAndLetter])
-            .to([Category::Digit, Category::HexAndLetter])
-            .to([Category::Digit, Category::HexAndLetter])
-            .to([Category::Digit, Category::HexAndLetter])
-            .to([Category::Digit, Category::HexAndLetter])
-            .to([Category::Digit, Category::HexAndLetter])
-            .goes_to(TokenKind::ColourLiteral)
-            .done();
+        self
+    }
+
+    pub fn add_string_logic(&mut self) -> &mut Self {
+        self.add_category(['"'], Category::Quote)
+            .add_category(['\\'], Category::Backslash);
+
+        // An opening quote starts the string, which takes any character
+        let in_string_state = self.auto_add_transition(0, Category::Quote, None, None);
+        self.auto_add_transition(in_string_state, Category::Any, Some(in_string_state), None);
+
+        // Other than a newline or the end of the file, as strings can't span
+        // multiple lines and must be closed
+        self.auto_add_transition(in_string_state, Category::Newline, Some(-1), None);
+        self.auto_add_transition(in_string_state, Category::Eof, Some(-1), None);
+
+        // A backslash escapes whatever comes after it, including a quote
+        let escape_state =
+            self.auto_add_transition(in_string_state, Category::Backslash, None, None);
+        self.auto_add_transition(escape_state, Category::Any, Some(in_string_state), None);
+
+        // The closing quote returns a final state
+        self.auto_add_transition(
+            in_string_state,
+            Category::Quote,
+            None,
+            Some(TokenKind::StringLiteral),
+        );
 
         self
     }
@@ -351,22 +796,53 @@ impl DfsaBuilder {
             .goes_to(TokenKind::EqEq)
             .done();
 
+        // `=>` branches off the state reached after a single `=`
+        let equals_state = self.transition_table[&(0, Category::Equals)];
+        self.auto_add_transition(
+            equals_state,
+            Category::GreaterThan,
+            None,
+            Some(TokenKind::FatArrow),
+        );
+
         self.transition()
             .to([Category::Exclamation])
             .to([Category::Equals])
             .goes_to(TokenKind::NotEqual)
             .done();
 
+        // Compound assignments and increments extend the state reached by
+        // their arithmetic operator, so those have to be added first
+        for (category, next, token_kind) in [
+            (Category::Plus, Category::Equals, TokenKind::PlusEquals),
+            (Category::Minus, Category::Equals, TokenKind::MinusEquals),
+            (
+                Category::Asterisk,
+                Category::Equals,
+                TokenKind::MultiplyEquals,
+            ),
+            (Category::Slash, Category::Equals, TokenKind::DivideEquals),
+            (Category::Percent, Category::Equals, TokenKind::ModEquals),
+            (Category::Plus, Category::Plus, TokenKind::PlusPlus),
+            (Category::Minus, Category::Minus, TokenKind::MinusMinus),
+        ] {
+            let state = *self
+                .transition_table
+                .get(&(0, category))
+                .unwrap_or_else(|| {
+                    panic!("{:?} has to be added before {:?}", category, token_kind)
+                });
+            self.auto_add_transition(state, next, None, Some(token_kind));
+        }
+
         self
     }
 
-    pub fn build(&mut self) -> Dfsa {
-        Dfsa::new(
-            self.accepted_states.clone(),
-            self.character_table.clone(),
-            self.transition_table.clone(),
-            self.state_to_token.clone(),
-        )
+    pub fn build(&self) -> Dfsa {
+        match self.minimise {
+            true => Dfsa::new(&self.minimise_states()),
+            false => Dfsa::new(self),
+        }
     }
 }
 
@@ -381,7 +857,7 @@ impl std::fmt::Display for DfsaBuilder {
             .map(|((a, b), c)| (*a, *b, *c))
             .collect();
 
-        sorted_transitions.sort_by(|a, b| a.2.cmp(&b.2));
+        sorted_transitions.sort_by_key(|a| a.2);
 
         for (a, b, c) in sorted_transitions {
             writeln!(f, "({}, {:?}) -> {}", a, b, c)?;
@@ -394,7 +870,13 @@ impl std::fmt::Display for DfsaBuilder {
 
 #[cfg(test)]
 mod tests {
-    use crate::{lexing::Lexer, utils::SimpleBuffer};
+    use std::time::Instant;
+
+    use crate::{
+        core::TextSpan,
+        lexing::{lexer::dfsa_builder, Lexer},
+        utils::Utf8Buffer,
+    };
 
     use super::*;
 
@@ -402,7 +884,7 @@ mod tests {
 
     #[rstest]
     fn test_dfsa_builder() {
-        let mut dfsa_builder = DfsaBuilder::new();
+        let dfsa_builder = DfsaBuilder::new();
 
         let _dfsa = dfsa_builder.build();
     }
@@ -427,8 +909,10 @@ mod tests {
 
         dfsa_builder
             .add_category(['.'], Category::Period)
-            .add_category('a'..='z', Category::Letter)
-            .add_category('A'..='Z', Category::Letter)
+            .add_category('a'..='f', Category::HexAndLetter)
+            .add_category('A'..='F', Category::HexAndLetter)
+            .add_category('g'..='z', Category::Letter)
+            .add_category('G'..='Z', Category::Letter)
             .add_category('0'..='9', Category::Digit)
             .add_category(['_'], Category::Underscore)
             .add_multiple_single_final_character_symbols(vec![
@@ -446,6 +930,7 @@ mod tests {
                 ('*', Category::Asterisk, TokenKind::Multiply),
                 (',', Category::Comma, TokenKind::Comma),
                 ('\0', Category::Eof, TokenKind::EndOfFile),
+                ('%', Category::Percent, TokenKind::Mod),
             ])
             .add_whitespace_logic()
             .add_comment_functionality()
@@ -459,8 +944,7 @@ mod tests {
     }
 
     fn test_parse(string: &str, dfsa: Option<Dfsa>) {
-        let fake_file = std::path::PathBuf::from("fake_file");
-        let mut lexer: Lexer<SimpleBuffer> = Lexer::new(string, &fake_file, dfsa);
+        let mut lexer: Lexer<Utf8Buffer> = Lexer::new(string, dfsa);
 
         match lexer.lex() {
             Ok(tokens) => {
@@ -475,4 +959,191 @@ mod tests {
             }
         }
     }
+
+    /// The transition the builder's hash tables give, which is how the DFSA
+    /// used to be stored.
+    fn hashed_delta(builder: &DfsaBuilder, state: i32, c: char) -> i32 {
+        let category = match builder.character_table.get(&c) {
+            Some(category) => *category,
+            None if !c.is_ascii() && c.is_alphabetic() => Category::UnicodeLetter,
+            None => Category::Other,
+        };
+
+        match builder.transition_table.get(&(state, category)) {
+            Some(next_state) => *next_state,
+            None => *builder
+                .transition_table
+                .get(&(state, Category::Any))
+                .unwrap_or(&-2),
+        }
+    }
+
+    /// Runs the DFSA over `input` with `delta`, starting again after each
+    /// error, and returns how many times it accepted.
+    fn run(
+        input: &str,
+        mut delta: impl FnMut(i32, char) -> i32,
+        accepting: impl Fn(i32) -> bool,
+    ) -> usize {
+        let mut state = 0;
+        let mut accepted = 0;
+
+        for c in input.chars() {
+            state = match delta(state, c) {
+                next_state if next_state < 0 => delta(0, c),
+                next_state => next_state,
+            };
+            accepted += accepting(state) as usize;
+        }
+
+        accepted
+    }
+
+    fn generate_input(functions: usize) -> String {
+        (0..functions)
+            .map(|i| {
+                format!(
+                    "/* f{i} */ fun f{i}(x: int) -> float {{\n    let y = {i}.5e-3 * 0x1F_{i};\n    \
+                     if x >= 0b101 and y != 1_000.0 {{ return y; }} // done\n    \
+                     __write x, {i}, #ff00aa;\n    return \"str\\n\" as float;\n}}\n"
+                )
+            })
+            .collect()
+    }
+
+    #[rstest]
+    fn test_dense_table_matches_builder() {
+        let builder = dfsa_builder();
+        let dfsa = builder.build();
+        let chars = (0..128u8).map(char::from).chain(['é', '€', '\u{feff}']);
+
+        for state in -2..=builder.max_state {
+            for c in chars.clone() {
+                assert_eq!(
+                    dfsa.delta(state, dfsa.get_category(c)),
+                    hashed_delta(&builder, state, c),
+                    "state {state} on {c:?}"
+                );
+            }
+            assert_eq!(
+                dfsa.is_accepting(&state),
+                builder.accepted_states.contains(&state)
+            );
+        }
+    }
+
+    /// Compares the dense table with the builder's hash tables on a large
+    /// generated program. Run with
+    /// `cargo test --release bench_transition_table -- --ignored --nocapture`.
+    #[rstest]
+    #[ignore = "benchmark"]
+    fn bench_transition_table() {
+        let builder = dfsa_builder();
+        let dfsa = builder.build();
+        let input = generate_input(20_000);
+        let megabytes = input.len() as f64 / 1_000_000.0;
+
+        let start = Instant::now();
+        let expected = run(
+            &input,
+            |state, c| hashed_delta(&builder, state, c),
+            |state| builder.accepted_states.contains(&state),
+        );
+        let elapsed = start.elapsed().as_secs_f64();
+        println!("Hash tables: {:.1} MB/s", megabytes / elapsed);
+
+        let start = Instant::now();
+        let accepted = run(
+            &input,
+            |state, c| dfsa.delta(state, dfsa.get_category(c)),
+            |state| dfsa.is_accepting(&state),
+        );
+        let elapsed = start.elapsed().as_secs_f64();
+        println!("Dense table: {:.1} MB/s", megabytes / elapsed);
+
+        assert_eq!(accepted, expected);
+
+        let start = Instant::now();
+        let tokens = Lexer::<Utf8Buffer>::new(&input, Some(dfsa)).lex().unwrap();
+        let elapsed = start.elapsed().as_secs_f64();
+        println!(
+            "Lexer: {:.1} MB/s, {} tokens",
+            megabytes / elapsed,
+            tokens.len()
+        );
+    }
+
+    fn lex_with(input: &str, dfsa: Dfsa) -> Result<Vec<(TokenKind, TextSpan)>, Vec<String>> {
+        Lexer::<Utf8Buffer>::new(input, Some(dfsa))
+            .lex()
+            .map(|tokens| tokens.into_iter().map(|t| (t.kind, t.span)).collect())
+            .map_err(|errors| errors.iter().map(|e| e.to_string()).collect())
+    }
+
+    #[rstest]
+    #[case(&generate_input(20))]
+    #[case(include_str!("../../samples/race.parl"))]
+    #[case("let x = 0x; /* unclosed")]
+    #[case("\"bad \\q\" 1e 0b2 1..2 größe @")]
+    fn test_minimised_tokenisation(#[case] input: &str) {
+        let builder = dfsa_builder();
+        let minimised = builder.minimise_states();
+
+        assert!(minimised.state_count() < builder.state_count());
+        assert_eq!(
+            lex_with(input, minimised.build()),
+            lex_with(input, builder.build())
+        );
+    }
+
+    #[rstest]
+    fn test_to_dot() {
+        let dot = dfsa_builder().minimised().build().to_dot();
+
+        assert!(dot.starts_with("digraph dfsa {"));
+        assert!(dot.contains("start -> 0;"));
+        assert!(dot.contains("\\nIdentifier\"];"));
+        assert!(dot.contains("-> reject"));
+    }
+
+    #[rstest]
+    fn test_regex_tokens() {
+        let dfsa = DfsaBuilder::new()
+            .add_category('a'..='z', Category::Letter)
+            .add_category(['0'], Category::Zero)
+            .add_category(['1'], Category::One)
+            .add_single_final_character_symbol('\0', Category::Eof, TokenKind::EndOfFile)
+            .add_whitespace_logic()
+            .add_token("[a-z]+", TokenKind::Identifier)
+            .add_token("[01]+", TokenKind::IntLiteral)
+            .add_token("0[a-z]*", TokenKind::StringLiteral)
+            .minimised()
+            .build();
+        let tokens = lex_with("ab 01 0ab 0", dfsa).unwrap();
+
+        assert_eq!(
+            tokens.iter().map(|(kind, _)| *kind).collect::<Vec<_>>(),
+            vec![
+                TokenKind::Identifier,
+                TokenKind::IntLiteral,
+                TokenKind::StringLiteral,
+                TokenKind::IntLiteral,
+                TokenKind::EndOfFile,
+            ]
+        );
+    }
+
+    #[rstest]
+    #[should_panic(expected = "matches only some of the characters in Letter")]
+    fn test_regex_splitting_category() {
+        DfsaBuilder::new()
+            .add_category('a'..='z', Category::Letter)
+            .add_token("[a-f]+", TokenKind::Identifier);
+    }
+
+    #[rstest]
+    #[should_panic(expected = "Plus has to be added before PlusEquals")]
+    fn test_compound_operators_need_their_operator() {
+        DfsaBuilder::new().add_multi_char_rel_ops();
+    }
 }
@@ -1,5 +1,3 @@
-use std::path::Path;
-
 use crate::{
     core::{TextSpan, Token, TokenKind},
     utils::{
@@ -10,56 +8,58 @@ use crate::{
 
 use super::dfsa::{Category, Dfsa, DfsaBuilder};
 
-pub struct Lexer<B: Stream + Clone> {
+/// The builder for the DFSA that lexes PArL.
+pub fn dfsa_builder() -> DfsaBuilder {
+    let mut builder = DfsaBuilder::new();
+    builder
+        .add_category('a'..='f', Category::HexAndLetter)
+        .add_category('A'..='F', Category::HexAndLetter)
+        .add_category('g'..='z', Category::Letter)
+        .add_category('G'..='Z', Category::Letter)
+        .add_category('0'..='9', Category::Digit)
+        .add_multiple_single_final_character_symbols(vec![
+            ('\n', Category::Newline, TokenKind::Newline),
+            ('{', Category::LBrace, TokenKind::LBrace),
+            ('}', Category::RBrace, TokenKind::RBrace),
+            ('(', Category::LParen, TokenKind::LParen),
+            (')', Category::RParen, TokenKind::RParen),
+            ('[', Category::LBracket, TokenKind::LBracket),
+            (']', Category::RBracket, TokenKind::RBracket),
+            (';', Category::Semicolon, TokenKind::Semicolon),
+            (':', Category::Colon, TokenKind::Colon),
+            ('+', Category::Plus, TokenKind::Plus),
+            ('*', Category::Asterisk, TokenKind::Multiply),
+            (',', Category::Comma, TokenKind::Comma),
+            ('\0', Category::Eof, TokenKind::EndOfFile),
+            ('%', Category::Percent, TokenKind::Mod),
+            ('?', Category::Question, TokenKind::Question),
+        ])
+        .add_whitespace_logic()
+        .add_comment_functionality()
+        .add_multi_char_rel_ops()
+        .add_identifier_logic()
+        .add_number_logic()
+        .add_string_logic();
+    builder
+}
+
+pub struct Lexer<B: Stream> {
     buffer: B,
     dfsa: Dfsa,
 }
 
-impl<B: Stream + Clone> Lexer<B> {
-    pub fn new(input: &str, file: &Path, dfsa: Option<Dfsa>) -> Self {
-        let mut dfsa_builder = DfsaBuilder::new();
-
-        match dfsa {
-            Some(dfsa) => Lexer {
-                buffer: B::new(input, file),
-                dfsa,
-            },
-            None => {
-                let dfsa = dfsa_builder
-                    .add_category('a'..='f', Category::HexAndLetter)
-                    .add_category('A'..='F', Category::HexAndLetter)
-                    .add_category('g'..='z', Category::Letter)
-                    .add_category('G'..='Z', Category::Letter)
-                    .add_category('0'..='9', Category::Digit)
-                    .add_multiple_single_final_character_symbols(vec![
-                        ('\n', Category::Newline, TokenKind::Newline),
-                        ('{', Category::LBrace, TokenKind::LBrace),
-                        ('}', Category::RBrace, TokenKind::RBrace),
-                        ('(', Category::LParen, TokenKind::LParen),
-                        (')', Category::RParen, TokenKind::RParen),
-                        ('[', Category::LBracket, TokenKind::LBracket),
-                        (']', Category::RBracket, TokenKind::RBracket),
-                        (';', Category::Semicolon, TokenKind::Semicolon),
-                        (':', Category::Colon, TokenKind::Colon),
-                        ('+', Category::Plus, TokenKind::Plus),
-                        ('*', Category::Asterisk, TokenKind::Multiply),
-                        (',', Category::Comma, TokenKind::Comma),
-                        ('\0', Category::Eof, TokenKind::EndOfFile),
-                        ('%', Category::Percent, TokenKind::Mod),
-                    ])
-                    .add_whitespace_logic()
-                    .add_comment_functionality()
-                    .add_multi_char_rel_ops()
-                    .add_identifier_logic()
-                    .add_number_logic()
-                    .build();
-
-                Lexer {
-                    buffer: B::new(input, file),
-                    dfsa,
-                }
-            }
-        }
+impl<B: Stream> Lexer<B> {
+    #[cfg(test)]
+    pub fn new(input: &str, dfsa: Option<Dfsa>) -> Self {
+        Lexer::from_stream(B::new(input), dfsa)
+    }
+
+    /// Creates a lexer over a stream that has already been set up, e.g. a
+    /// [`ReadBuffer`](crate::utils::ReadBuffer) reading from a file.
+    pub fn from_stream(buffer: B, dfsa: Option<Dfsa>) -> Self {
+        let dfsa = dfsa.unwrap_or_else(|| dfsa_builder().minimised().build());
+
+        Lexer { buffer, dfsa }
     }
 
     /// These are the reserved keywords in the language. Note that these must be
@@ -77,31 +77,64 @@ impl<B: Stream + Clone> Lexer<B> {
             "__write" => TokenKind::PadWrite,
             "and" => TokenKind::And,
             "as" => TokenKind::As,
+            "const" => TokenKind::Const,
             "else" => TokenKind::Else,
             "for" => TokenKind::For,
             "fun" => TokenKind::Function,
             "if" => TokenKind::If,
-            "int" | "float" | "bool" | "colour" => TokenKind::Type,
+            "int" | "float" | "bool" | "colour" | "void" => TokenKind::Type,
             "let" => TokenKind::Let,
+            "match" => TokenKind::Match,
             "not" => TokenKind::Not,
             "or" => TokenKind::Or,
             "return" => TokenKind::Return,
+            "rgb" => TokenKind::Rgb,
+            "struct" => TokenKind::Struct,
             "true" | "false" => TokenKind::BoolLiteral,
             "while" => TokenKind::While,
+            "_" => TokenKind::Underscore,
             _ => TokenKind::Identifier,
         }
     }
 
+    fn is_word_char(c: char) -> bool {
+        c.is_alphanumeric() || c == '_'
+    }
+
+    /// Reads characters for as long as they're part of a word, so that a
+    /// malformed literal like `#ff00ag` is reported as a whole.
+    fn skip_word(&mut self, lexeme: &mut String) {
+        loop {
+            match self.buffer.next_char() {
+                c if Self::is_word_char(c) => lexeme.push(c),
+                _ => {
+                    self.buffer.rollback();
+                    return;
+                }
+            }
+        }
+    }
+
     pub fn next_token(&mut self) -> Result<Token, Error> {
+        self.buffer.mark();
         let mut state = self.dfsa.start_state();
         let mut lexeme = String::new();
         let mut stack = vec![self.dfsa.bad_state()];
-        let mut prev_state = state;
 
         let (start_line, start_col) = (self.buffer.get_line(), self.buffer.get_col());
+        let start_offset = self.buffer.get_offset() as u32;
+        let span = |buffer: &B, lexeme: &str| {
+            TextSpan::new(
+                start_line,
+                buffer.get_line(),
+                start_col,
+                buffer.get_col(),
+                lexeme,
+            )
+            .with_offsets(start_offset, buffer.get_offset() as u32)
+        };
 
-        while state != self.dfsa.error_state() {
-            prev_state = state;
+        while state != self.dfsa.error_state() && state != self.dfsa.bad_state() {
             let c = self.buffer.next_char();
 
             lexeme.push(c);
@@ -116,6 +149,22 @@ impl<B: Stream + Clone> Lexer<B> {
             state = self.dfsa.delta(state, cat);
         }
 
+        // The DFSA gives up on comments and strings that run into the end of
+        // the file, or the end of the line for strings. The error ends before
+        // that, so lexing picks up from there.
+        if state == self.dfsa.bad_state() {
+            lexeme.pop();
+            self.buffer.rollback();
+
+            let text_span = span(&self.buffer, &lexeme);
+            return Err(Error::Lexical(match lexeme.starts_with("/*") {
+                true => LexicalError::UnterminatedComment(text_span),
+                false => LexicalError::UnterminatedString(text_span),
+            }));
+        }
+
+        let scanned = lexeme.clone();
+
         while !self.dfsa.is_accepting(&state) && state != self.dfsa.bad_state() {
             state = stack.pop().unwrap();
             if state != self.dfsa.bad_state() {
@@ -124,34 +173,93 @@ impl<B: Stream + Clone> Lexer<B> {
             }
         }
 
-        let (end_line, end_col) = (self.buffer.get_line(), self.buffer.get_col());
+        // The characters read past the longest match, other than the one
+        // that ended the scan. A number that isn't a range's start but was
+        // followed by them was cut short, like `1.` or `0x`, and one that
+        // runs straight into a letter or digit it can't take, like `0b102` or
+        // `12abc`, is malformed as a whole.
+        let rest = &scanned[lexeme.len()..];
+        let unmatched = &rest[..rest.len() - rest.chars().last().map_or(0, char::len_utf8)];
+        let kind = self.dfsa.get_token_kind(state);
+        if matches!(kind, TokenKind::IntLiteral | TokenKind::FloatLiteral)
+            && (!unmatched.is_empty() || rest.chars().next().is_some_and(Self::is_word_char))
+            && !rest.starts_with("..")
+        {
+            unmatched.chars().for_each(|_| {
+                self.buffer.next_char();
+            });
+            lexeme.push_str(unmatched);
+            self.skip_word(&mut lexeme);
 
-        let text_span = TextSpan::new(start_line, end_line, start_col, end_col, &lexeme);
+            return Err(Error::Lexical(LexicalError::MalformedNumber(span(
+                &self.buffer,
+                &lexeme,
+            ))));
+        }
+
+        let text_span = span(&self.buffer, &lexeme);
 
         match self.dfsa.is_accepting(&state) {
+            true if kind == TokenKind::StringLiteral
+                && Token::new(TokenKind::StringLiteral, text_span.clone())
+                    .string_value()
+                    .is_none() =>
+            {
+                Err(Error::Lexical(LexicalError::InvalidEscape(text_span)))
+            }
+            true if match kind {
+                TokenKind::IntLiteral => Token::new(TokenKind::IntLiteral, text_span.clone())
+                    .int_value()
+                    .is_none(),
+                TokenKind::FloatLiteral => Token::new(TokenKind::FloatLiteral, text_span.clone())
+                    .float_value()
+                    .is_none(),
+                _ => false,
+            } =>
+            {
+                Err(Error::Lexical(LexicalError::LiteralOutOfRange(text_span)))
+            }
             true => Ok(Token::new(
-                match self.dfsa.get_token_kind(state) {
+                match kind {
                     TokenKind::Identifier => self.handle_keyword(&lexeme),
-                    _ => self.dfsa.get_token_kind(state),
+                    _ => kind,
                 },
                 text_span,
             )),
+            // A colour literal with too few digits, or a stray `#`
+            false if unmatched.starts_with('#') => {
+                unmatched.chars().for_each(|_| {
+                    self.buffer.next_char();
+                });
+                lexeme.push_str(unmatched);
+                self.skip_word(&mut lexeme);
+
+                Err(Error::Lexical(LexicalError::MalformedColour(span(
+                    &self.buffer,
+                    &lexeme,
+                ))))
+            }
             false => {
-                let error = match prev_state {
-                    _ if self.dfsa.is_accepting(&prev_state) => LexicalError::InvalidCharacter(
-                        TextSpan::new(start_line, start_line, start_col, start_col, &lexeme),
-                    ),
-                    _ => LexicalError::InvalidCharacter(TextSpan::new(
-                        start_line, end_line, start_col, end_col, &lexeme,
-                    )),
-                };
-                self.buffer.next_char();
-                Err(Error::Lexical(error))
+                lexeme.push(self.buffer.next_char());
+                Err(Error::Lexical(LexicalError::InvalidCharacter(span(
+                    &self.buffer,
+                    &lexeme,
+                ))))
             }
         }
     }
 
     pub fn lex(&mut self) -> Result<Vec<Token>, Vec<Error>> {
+        self.lex_until(|_| false)
+    }
+
+    /// Lexes up to, but not including, the first token that `stop` returns
+    /// true for, or to the end of the input, where an `EndOfFile` token is
+    /// added. Whitespace, comments and newlines are never passed to `stop`.
+    pub fn lex_until(
+        &mut self,
+        mut stop: impl FnMut(&Token) -> bool,
+    ) -> Result<Vec<Token>, Vec<Error>> {
         let mut tokens = Vec::new();
         let mut errors = Vec::new();
 
@@ -164,6 +272,13 @@ impl<B: Stream + Clone> Lexer<B> {
                         && token.kind != TokenKind::Comment
                         && token.kind != TokenKind::Newline
                     {
+                        if stop(&token) {
+                            return match errors.is_empty() {
+                                true => Ok(tokens),
+                                false => Err(errors),
+                            };
+                        }
+
                         tokens.push(token);
                     }
                 }
@@ -179,6 +294,10 @@ impl<B: Stream + Clone> Lexer<B> {
                         self.buffer.get_col(),
                         self.buffer.get_col(),
                         "\0",
+                    )
+                    .with_offsets(
+                        self.buffer.get_offset() as u32,
+                        self.buffer.get_offset() as u32,
                     ),
                 ));
 
@@ -193,7 +312,9 @@ impl<B: Stream + Clone> Lexer<B> {
 
 #[cfg(test)]
 mod tests {
-    use crate::utils::SimpleBuffer;
+    use std::{io::Cursor, time::Instant};
+
+    use crate::utils::{ReadBuffer, Utf8Buffer};
 
     use super::*;
     use assert_matches::assert_matches;
@@ -202,10 +323,281 @@ mod tests {
     #[rstest]
     fn test_lex() {
         let input = "fn( bruh ) { return test; }";
-        let fake_path = Path::new("fake_path");
-        let mut lexer: Lexer<SimpleBuffer> = Lexer::new(input, fake_path, None);
+        let mut lexer: Lexer<Utf8Buffer> = Lexer::new(input, None);
         let tokens = lexer.lex();
 
         assert_matches!(tokens, Ok(tokens) => tokens);
     }
+
+    #[rstest]
+    fn test_lex_compound_assignment() {
+        let input = "x += 1; y -= 2; z *= 3; w /= 4; v %= 5; i++; j--; -> - / %";
+        let mut lexer: Lexer<Utf8Buffer> = Lexer::new(input, None);
+        let kinds: Vec<TokenKind> = lexer.lex().unwrap().iter().map(|t| t.kind).collect();
+
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::Identifier,
+                TokenKind::PlusEquals,
+                TokenKind::IntLiteral,
+                TokenKind::Semicolon,
+                TokenKind::Identifier,
+                TokenKind::MinusEquals,
+                TokenKind::IntLiteral,
+                TokenKind::Semicolon,
+                TokenKind::Identifier,
+                TokenKind::MultiplyEquals,
+                TokenKind::IntLiteral,
+                TokenKind::Semicolon,
+                TokenKind::Identifier,
+                TokenKind::DivideEquals,
+                TokenKind::IntLiteral,
+                TokenKind::Semicolon,
+                TokenKind::Identifier,
+                TokenKind::ModEquals,
+                TokenKind::IntLiteral,
+                TokenKind::Semicolon,
+                TokenKind::Identifier,
+                TokenKind::PlusPlus,
+                TokenKind::Semicolon,
+                TokenKind::Identifier,
+                TokenKind::MinusMinus,
+                TokenKind::Semicolon,
+                TokenKind::Arrow,
+                TokenKind::Minus,
+                TokenKind::Divide,
+                TokenKind::Mod,
+                TokenKind::EndOfFile,
+            ]
+        );
+    }
+
+    #[rstest]
+    fn test_lex_field_access() {
+        let input = "struct P { x: int } p.x = 1.5; 1..3";
+        let mut lexer: Lexer<Utf8Buffer> = Lexer::new(input, None);
+        let kinds: Vec<TokenKind> = lexer.lex().unwrap().iter().map(|t| t.kind).collect();
+
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::Struct,
+                TokenKind::Identifier,
+                TokenKind::LBrace,
+                TokenKind::Identifier,
+                TokenKind::Colon,
+                TokenKind::Type,
+                TokenKind::RBrace,
+                TokenKind::Identifier,
+                TokenKind::Dot,
+                TokenKind::Identifier,
+                TokenKind::Equals,
+                TokenKind::FloatLiteral,
+                TokenKind::Semicolon,
+                TokenKind::IntLiteral,
+                TokenKind::DotDot,
+                TokenKind::IntLiteral,
+                TokenKind::EndOfFile,
+            ]
+        );
+    }
+
+    #[rstest]
+    fn test_lex_string_literals() {
+        let input = r#"__print "x = ", "say \"hi\"\n";"#;
+        let mut lexer: Lexer<Utf8Buffer> = Lexer::new(input, None);
+        let tokens = lexer.lex().unwrap();
+
+        assert_eq!(tokens[1].kind, TokenKind::StringLiteral);
+        assert_eq!(tokens[1].string_value().unwrap(), "x = ");
+        assert_eq!(tokens[3].kind, TokenKind::StringLiteral);
+        assert_eq!(tokens[3].string_value().unwrap(), "say \"hi\"\n");
+        assert_eq!(tokens[4].kind, TokenKind::Semicolon);
+    }
+
+    #[rstest]
+    #[case(r#""bad \q""#)]
+    #[case("\"unclosed\n\"")]
+    #[case("\"unclosed")]
+    fn test_lex_invalid_string_literals(#[case] input: &str) {
+        let mut lexer: Lexer<Utf8Buffer> = Lexer::new(input, None);
+
+        assert!(lexer.lex().is_err());
+    }
+
+    #[rstest]
+    #[case("42", 42)]
+    #[case("1_000_000", 1_000_000)]
+    #[case("0", 0)]
+    #[case("0x1F", 0x1F)]
+    #[case("0XdEaD_bEeF", 0xDEAD_BEEF)]
+    #[case("0b1010", 0b1010)]
+    #[case("0b1111_0000", 0b1111_0000)]
+    fn test_lex_int_literals(#[case] input: &str, #[case] value: i64) {
+        let mut lexer: Lexer<Utf8Buffer> = Lexer::new(input, None);
+        let tokens = lexer.lex().unwrap();
+
+        assert_eq!(tokens[0].kind, TokenKind::IntLiteral);
+        assert_eq!(tokens[0].span.lexeme, input);
+        assert_eq!(tokens[0].int_value(), Some(value));
+    }
+
+    #[rstest]
+    #[case("1.5", 1.5)]
+    #[case("1_000.25", 1000.25)]
+    #[case("1.5e-3", 1.5e-3)]
+    #[case("2E8", 2e8)]
+    #[case("6.02e+2_3", 6.02e23)]
+    fn test_lex_float_literals(#[case] input: &str, #[case] value: f32) {
+        let mut lexer: Lexer<Utf8Buffer> = Lexer::new(input, None);
+        let tokens = lexer.lex().unwrap();
+
+        assert_eq!(tokens[0].kind, TokenKind::FloatLiteral);
+        assert_eq!(tokens[0].span.lexeme, input);
+        assert_eq!(tokens[0].float_value(), Some(value));
+    }
+
+    #[rstest]
+    #[case("0x8000_0000_0000_0000")]
+    #[case("99999999999999999999")]
+    #[case("1e39")]
+    fn test_lex_out_of_range_literals(#[case] input: &str) {
+        let mut lexer: Lexer<Utf8Buffer> = Lexer::new(input, None);
+
+        assert_matches!(
+            lexer.lex(),
+            Err(errors) => assert_matches!(errors[..], [Error::Lexical(LexicalError::LiteralOutOfRange(_))])
+        );
+    }
+
+    #[rstest]
+    fn test_lex_number_suffixes() {
+        let input = "x1 beef 1..3";
+        let mut lexer: Lexer<Utf8Buffer> = Lexer::new(input, None);
+        let tokens = lexer.lex().unwrap();
+        let lexemes: Vec<&str> = tokens.iter().map(|t| t.span.lexeme.as_str()).collect();
+
+        assert_eq!(lexemes, vec!["x1", "beef", "1", "..", "3", "\0"]);
+    }
+
+    #[rstest]
+    #[case("/* a * b */")]
+    #[case("/** a **/")]
+    #[case("/* * / */")]
+    #[case("// a\n")]
+    #[case("// at the end")]
+    #[case("//")]
+    fn test_lex_comments(#[case] input: &str) {
+        let mut lexer: Lexer<Utf8Buffer> = Lexer::new(input, None);
+        let token = lexer.next_token().unwrap();
+
+        assert_eq!(token.kind, TokenKind::Comment);
+        assert_eq!(token.span.lexeme, input);
+    }
+
+    #[rstest]
+    #[case(
+        "/* open\n*",
+        "UnterminatedComment",
+        "/* open\n*",
+        TokenKind::EndOfFile
+    )]
+    #[case("\"abc\nx", "UnterminatedString", "\"abc", TokenKind::Newline)]
+    #[case("\"abc", "UnterminatedString", "\"abc", TokenKind::EndOfFile)]
+    #[case("#ff00a;", "MalformedColour", "#ff00a", TokenKind::Semicolon)]
+    #[case("#ff00ag;", "MalformedColour", "#ff00ag", TokenKind::Semicolon)]
+    #[case("#;", "MalformedColour", "#", TokenKind::Semicolon)]
+    #[case("1.+2", "MalformedNumber", "1.", TokenKind::Plus)]
+    #[case("1.x;", "MalformedNumber", "1.x", TokenKind::Semicolon)]
+    #[case("1.5e+;", "MalformedNumber", "1.5e+", TokenKind::Semicolon)]
+    #[case("0x)", "MalformedNumber", "0x", TokenKind::RParen)]
+    #[case("1__0;", "MalformedNumber", "1__0", TokenKind::Semicolon)]
+    #[case("0b102;", "MalformedNumber", "0b102", TokenKind::Semicolon)]
+    #[case("12abc;", "MalformedNumber", "12abc", TokenKind::Semicolon)]
+    #[case("@;", "InvalidCharacter", "@", TokenKind::Semicolon)]
+    fn test_lex_diagnostics(
+        #[case] input: &str,
+        #[case] error: &str,
+        #[case] lexeme: &str,
+        #[case] next: TokenKind,
+    ) {
+        let mut lexer: Lexer<Utf8Buffer> = Lexer::new(input, None);
+
+        let span = match lexer.next_token() {
+            Err(Error::Lexical(e)) => {
+                assert!(format!("{e:?}").starts_with(error), "{e:?}");
+                match e {
+                    LexicalError::UnterminatedComment(span)
+                    | LexicalError::UnterminatedString(span)
+                    | LexicalError::MalformedColour(span)
+                    | LexicalError::MalformedNumber(span)
+                    | LexicalError::InvalidCharacter(span) => span,
+                    _ => unreachable!(),
+                }
+            }
+            result => panic!("expected {error}, got {result:?}"),
+        };
+        assert_eq!(span.lexeme, lexeme);
+        assert_eq!(span.to_offset as usize, lexeme.len());
+        assert_eq!(lexer.next_token().unwrap().kind, next);
+    }
+
+    #[rstest]
+    fn test_lex_unicode() {
+        let input = "\u{feff}// café\r\nlet größe: int = 1;";
+        let mut lexer: Lexer<Utf8Buffer> = Lexer::new(input, None);
+        let tokens = lexer.lex().unwrap();
+
+        assert_eq!(tokens[1].kind, TokenKind::Identifier);
+        assert_eq!(tokens[1].span.lexeme, "größe");
+        assert_eq!((tokens[1].span.from_line, tokens[1].span.from_col), (2, 5));
+        assert_eq!((tokens[1].span.to_line, tokens[1].span.to_col), (2, 10));
+        assert_eq!(
+            &input[tokens[1].span.from_offset as usize..tokens[1].span.to_offset as usize],
+            "größe"
+        );
+    }
+
+    #[rstest]
+    #[case(1)]
+    #[case(7)]
+    #[case(4096)]
+    fn test_lex_read_buffer(#[case] chunk_size: usize) {
+        let input = format!(
+            "/* größe */\r\n{}{}",
+            include_str!("../../samples/race.parl"),
+            include_str!("../../samples/max.parl")
+        );
+        let expected = Lexer::<Utf8Buffer>::new(&input, None).lex().unwrap();
+        let buffer = ReadBuffer::with_chunk_size(Cursor::new(input.into_bytes()), chunk_size);
+        let tokens = Lexer::from_stream(buffer, None).lex().unwrap();
+
+        assert_eq!(
+            tokens.iter().map(|t| (t.kind, &t.span)).collect::<Vec<_>>(),
+            expected
+                .iter()
+                .map(|t| (t.kind, &t.span))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    /// Compares lexing a large input through each buffer. Run with
+    /// `cargo test --release bench_buffers -- --ignored --nocapture`.
+    #[rstest]
+    #[ignore = "benchmark"]
+    fn bench_buffers() {
+        let input = include_str!("../../samples/race.parl").repeat(500);
+
+        let start = Instant::now();
+        let expected = Lexer::<Utf8Buffer>::new(&input, None).lex().unwrap();
+        println!("Utf8Buffer: {:?}", start.elapsed());
+
+        let start = Instant::now();
+        let buffer = ReadBuffer::from_reader(Cursor::new(input.into_bytes()));
+        let tokens = Lexer::from_stream(buffer, None).lex().unwrap();
+        println!("ReadBuffer: {:?}", start.elapsed());
+
+        assert_eq!(tokens.len(), expected.len());
+    }
 }
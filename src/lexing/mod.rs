@@ -1,5 +1,6 @@
-mod dfsa;
+pub mod dfsa;
 pub mod lexer;
+mod regex;
 mod transition;
 
 pub use lexer::Lexer;
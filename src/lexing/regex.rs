@@ -0,0 +1,425 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    iter::Peekable,
+    str::Chars,
+};
+
+use crate::{core::TokenKind, utils::errors::RegexError};
+
+use super::dfsa::{Category, CATEGORIES};
+
+/// A regular expression over characters, e.g. `[a-zA-Z_][a-zA-Z0-9_]*`.
+///
+/// Supports literals, `.` (anything but a newline), classes such as `[a-f0-9]`
+/// and `[^"\n]`, grouping, `|`, `*`, `+` and `?`. A backslash escapes any of
+/// these, and `\n`, `\t`, `\r` and `\0` are the usual control characters.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Regex {
+    Class {
+        ranges: Vec<(char, char)>,
+        negated: bool,
+    },
+    Concat(Vec<Regex>),
+    Alternation(Vec<Regex>),
+    Star(Box<Regex>),
+    Plus(Box<Regex>),
+    Optional(Box<Regex>),
+}
+
+impl Regex {
+    pub fn parse(pattern: &str) -> Result<Regex, RegexError> {
+        let mut parser = RegexParser {
+            pattern,
+            chars: pattern.chars().peekable(),
+        };
+        let regex = parser.parse_alternation()?;
+
+        match parser.chars.next() {
+            Some(c) => Err(RegexError::UnexpectedCharacter(pattern.to_string(), c)),
+            None => Ok(regex),
+        }
+    }
+}
+
+struct RegexParser<'a> {
+    pattern: &'a str,
+    chars: Peekable<Chars<'a>>,
+}
+
+impl RegexParser<'_> {
+    fn unexpected_end(&self) -> RegexError {
+        RegexError::UnexpectedEnd(self.pattern.to_string())
+    }
+
+    fn parse_alternation(&mut self) -> Result<Regex, RegexError> {
+        let mut options = vec![self.parse_concat()?];
+
+        while self.chars.next_if_eq(&'|').is_some() {
+            options.push(self.parse_concat()?);
+        }
+
+        Ok(match options.len() {
+            1 => options.pop().unwrap(),
+            _ => Regex::Alternation(options),
+        })
+    }
+
+    fn parse_concat(&mut self) -> Result<Regex, RegexError> {
+        let mut parts = vec![];
+
+        while !matches!(self.chars.peek(), None | Some('|' | ')')) {
+            parts.push(self.parse_repetition()?);
+        }
+
+        Ok(match parts.len() {
+            1 => parts.pop().unwrap(),
+            _ => Regex::Concat(parts),
+        })
+    }
+
+    fn parse_repetition(&mut self) -> Result<Regex, RegexError> {
+        let mut regex = self.parse_atom()?;
+
+        loop {
+            regex = match self.chars.peek() {
+                Some('*') => Regex::Star(Box::new(regex)),
+                Some('+') => Regex::Plus(Box::new(regex)),
+                Some('?') => Regex::Optional(Box::new(regex)),
+                _ => return Ok(regex),
+            };
+            self.chars.next();
+        }
+    }
+
+    fn parse_atom(&mut self) -> Result<Regex, RegexError> {
+        let c = self.chars.next().ok_or_else(|| self.unexpected_end())?;
+
+        match c {
+            '(' => {
+                let regex = self.parse_alternation()?;
+                match self.chars.next() {
+                    Some(')') => Ok(regex),
+                    _ => Err(self.unexpected_end()),
+                }
+            }
+            '[' => self.parse_class(),
+            '.' => Ok(Regex::Class {
+                ranges: vec![('\n', '\n')],
+                negated: true,
+            }),
+            '*' | '+' | '?' | ')' | ']' => {
+                Err(RegexError::UnexpectedCharacter(self.pattern.to_string(), c))
+            }
+            '\\' => {
+                let c = self.parse_escape()?;
+                Ok(Regex::Class {
+                    ranges: vec![(c, c)],
+                    negated: false,
+                })
+            }
+            c => Ok(Regex::Class {
+                ranges: vec![(c, c)],
+                negated: false,
+            }),
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<char, RegexError> {
+        Ok(
+            match self.chars.next().ok_or_else(|| self.unexpected_end())? {
+                'n' => '\n',
+                't' => '\t',
+                'r' => '\r',
+                '0' => '\0',
+                c => c,
+            },
+        )
+    }
+
+    fn parse_class(&mut self) -> Result<Regex, RegexError> {
+        let negated = self.chars.next_if_eq(&'^').is_some();
+        let mut ranges = vec![];
+
+        loop {
+            let from = match self.chars.next().ok_or_else(|| self.unexpected_end())? {
+                ']' if !ranges.is_empty() => break,
+                '\\' => self.parse_escape()?,
+                c => c,
+            };
+
+            let to = match self.chars.peek() {
+                Some('-') => {
+                    self.chars.next();
+                    match self.chars.next().ok_or_else(|| self.unexpected_end())? {
+                        '\\' => self.parse_escape()?,
+                        ']' => {
+                            // A trailing `-` is just a dash
+                            ranges.push((from, from));
+                            ranges.push(('-', '-'));
+                            break;
+                        }
+                        c => c,
+                    }
+                }
+                _ => from,
+            };
+
+            ranges.push((from, to));
+        }
+
+        Ok(Regex::Class { ranges, negated })
+    }
+}
+
+#[derive(Debug, Default)]
+struct NfaState {
+    epsilon: Vec<usize>,
+    edges: Vec<(Category, usize)>,
+    /// The token this state accepts, and its priority, lowest first.
+    token: Option<(usize, TokenKind)>,
+}
+
+/// A Thompson NFA over character categories.
+#[derive(Debug, Default)]
+pub struct Nfa {
+    states: Vec<NfaState>,
+}
+
+impl Nfa {
+    pub fn add_state(&mut self) -> usize {
+        self.states.push(NfaState::default());
+        self.states.len() - 1
+    }
+
+    pub fn add_edge(&mut self, from: usize, category: Category, to: usize) {
+        self.states[from].edges.push((category, to));
+    }
+
+    pub fn set_token(&mut self, state: usize, priority: usize, token: TokenKind) {
+        self.states[state].token = Some((priority, token));
+    }
+
+    /// Adds the states for `regex`, and returns its start and end states.
+    /// Classes are matched by category, so each one has to take in whole
+    /// categories from `character_table`.
+    pub fn add_regex(
+        &mut self,
+        regex: &Regex,
+        character_table: &HashMap<char, Category>,
+        pattern: &str,
+    ) -> Result<(usize, usize), RegexError> {
+        let (start, end) = (self.add_state(), self.add_state());
+
+        match regex {
+            Regex::Class { ranges, negated } => {
+                for category in class_categories(ranges, *negated, character_table, pattern)? {
+                    self.add_edge(start, category, end);
+                }
+            }
+            Regex::Concat(parts) => {
+                let mut last = start;
+                for part in parts {
+                    let (part_start, part_end) = self.add_regex(part, character_table, pattern)?;
+                    self.states[last].epsilon.push(part_start);
+                    last = part_end;
+                }
+                self.states[last].epsilon.push(end);
+            }
+            Regex::Alternation(options) => {
+                for option in options {
+                    let (option_start, option_end) =
+                        self.add_regex(option, character_table, pattern)?;
+                    self.states[start].epsilon.push(option_start);
+                    self.states[option_end].epsilon.push(end);
+                }
+            }
+            Regex::Star(inner) | Regex::Plus(inner) | Regex::Optional(inner) => {
+                let (inner_start, inner_end) = self.add_regex(inner, character_table, pattern)?;
+                self.states[start].epsilon.push(inner_start);
+                self.states[inner_end].epsilon.push(end);
+
+                if !matches!(regex, Regex::Plus(_)) {
+                    self.states[start].epsilon.push(end);
+                }
+                if !matches!(regex, Regex::Optional(_)) {
+                    self.states[inner_end].epsilon.push(inner_start);
+                }
+            }
+        }
+
+        Ok((start, end))
+    }
+
+    fn closure(&self, states: impl IntoIterator<Item = usize>) -> BTreeSet<usize> {
+        let mut closure = BTreeSet::new();
+        let mut stack: Vec<usize> = states.into_iter().collect();
+
+        while let Some(state) = stack.pop() {
+            if closure.insert(state) {
+                stack.extend(&self.states[state].epsilon);
+            }
+        }
+
+        closure
+    }
+
+    /// Builds a DFSA with subset construction, starting from `start`. DFSA
+    /// states are numbered from 0 for the start, and a set holding only
+    /// `bad` becomes -1. Where a set accepts several tokens, the one with the
+    /// lowest priority wins.
+    ///
+    /// Returns the transitions and the token each accepting state accepts.
+    #[allow(clippy::type_complexity)]
+    pub fn to_dfsa(
+        &self,
+        start: &[usize],
+        bad: usize,
+    ) -> (HashMap<(i32, Category), i32>, Vec<(i32, TokenKind)>) {
+        let mut numbers: HashMap<BTreeSet<usize>, i32> = HashMap::new();
+        let mut sets = vec![self.closure(start.iter().copied())];
+        numbers.insert(sets[0].clone(), 0);
+
+        let mut transitions = HashMap::new();
+        let mut tokens = vec![];
+        let mut next = 0;
+
+        while next < sets.len() {
+            let set = sets[next].clone();
+            let number = next as i32;
+            next += 1;
+
+            if let Some((_, token)) = set
+                .iter()
+                .filter_map(|s| self.states[*s].token)
+                .min_by_key(|(priority, _)| *priority)
+            {
+                tokens.push((number, token));
+            }
+
+            for category in CATEGORIES {
+                let mut targets = self.closure(set.iter().flat_map(|s| {
+                    self.states[*s]
+                        .edges
+                        .iter()
+                        .filter(move |(c, _)| *c == category)
+                        .map(|(_, to)| *to)
+                }));
+
+                // Getting stuck only matters if nothing else is still going
+                if targets.len() > 1 {
+                    targets.remove(&bad);
+                }
+
+                let target = match targets.len() {
+                    0 => continue,
+                    1 if targets.contains(&bad) => -1,
+                    _ => *numbers.entry(targets.clone()).or_insert_with(|| {
+                        sets.push(targets);
+                        sets.len() as i32 - 1
+                    }),
+                };
+
+                transitions.insert((number, category), target);
+            }
+        }
+
+        (transitions, tokens)
+    }
+}
+
+/// The categories a class matches. A class has to take in every character of
+/// a category or none of them, and can't name characters without a category
+/// of their own, since `Other` and `UnicodeLetter` have no end.
+fn class_categories(
+    ranges: &[(char, char)],
+    negated: bool,
+    character_table: &HashMap<char, Category>,
+    pattern: &str,
+) -> Result<Vec<Category>, RegexError> {
+    let in_class = |c: char| ranges.iter().any(|(from, to)| (*from..=*to).contains(&c));
+
+    let mut named = BTreeSet::new();
+    for c in ranges.iter().flat_map(|(from, to)| *from..=*to) {
+        let category = match character_table.get(&c) {
+            Some(category) => *category,
+            None if !c.is_ascii() && c.is_alphabetic() => Category::UnicodeLetter,
+            None => Category::Other,
+        };
+        named.insert(category as usize);
+    }
+
+    for category in &named {
+        let category = CATEGORIES[*category];
+        let whole = character_table
+            .iter()
+            .filter(|(_, c)| **c == category)
+            .all(|(c, _)| in_class(*c));
+
+        if !whole || matches!(category, Category::Other | Category::UnicodeLetter) {
+            return Err(RegexError::SplitsCategory(pattern.to_string(), category));
+        }
+    }
+
+    Ok(match negated {
+        true => CATEGORIES
+            .into_iter()
+            .filter(|c| !named.contains(&(*c as usize)))
+            .filter(|c| !matches!(c, Category::Any | Category::Eof))
+            .collect(),
+        false => named.into_iter().map(|c| CATEGORIES[c]).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_matches::assert_matches;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("[a-zA-Z_][a-zA-Z0-9_]*")]
+    #[case("0[xX][0-9a-fA-F](_?[0-9a-fA-F])*")]
+    #[case("\"([^\"\\\\\\n]|\\\\.)*\"")]
+    #[case("a|b|(cd)+?")]
+    #[case("[-+]")]
+    fn test_parse(#[case] pattern: &str) {
+        assert_matches!(Regex::parse(pattern), Ok(_));
+    }
+
+    #[rstest]
+    #[case("(ab")]
+    #[case("[ab")]
+    #[case("a\\")]
+    #[case("*a")]
+    #[case("a)")]
+    fn test_parse_errors(#[case] pattern: &str) {
+        assert_matches!(Regex::parse(pattern), Err(_));
+    }
+
+    #[rstest]
+    fn test_class_categories() {
+        let table = HashMap::from([
+            ('x', Category::HexPrefix),
+            ('X', Category::HexPrefix),
+            ('"', Category::Quote),
+        ]);
+
+        assert_eq!(
+            class_categories(&[('x', 'x'), ('X', 'X')], false, &table, ""),
+            Ok(vec![Category::HexPrefix])
+        );
+        assert_matches!(
+            class_categories(&[('x', 'x')], false, &table, ""),
+            Err(RegexError::SplitsCategory(_, Category::HexPrefix))
+        );
+        assert_matches!(
+            class_categories(&[('@', '@')], false, &table, ""),
+            Err(RegexError::SplitsCategory(_, Category::Other))
+        );
+        assert_matches!(
+            class_categories(&[('"', '"')], true, &table, ""),
+            Ok(categories) if !categories.contains(&Category::Quote)
+                && categories.contains(&Category::Other)
+        );
+    }
+}
@@ -5,15 +5,31 @@ mod parsing;
 mod semantics;
 mod utils;
 
-use clap::{command, Parser as ClapParser, Subcommand};
+use clap::{Parser as ClapParser, Subcommand, ValueEnum};
 use console::style;
-use std::{io::Write, path::PathBuf};
-use utils::SimpleBuffer;
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+use utils::ReadBuffer;
 
 use crate::{
-    lexing::Lexer,
-    parsing::{ast::Visitor, Parser},
-    semantics::visitors::{Formatter, SemAnalyzer, TreePrinter},
+    core::Token,
+    lexing::{lexer::dfsa_builder, Lexer},
+    parsing::{
+        ast::{AstNode, Visitor},
+        export,
+        incremental::{Document, Edit},
+        Parser,
+    },
+    semantics::{
+        passes::PassManager,
+        visitors::{Formatter, SemAnalyzer, TreePrinter},
+    },
+    utils::errors::AstImportError,
 };
 
 #[derive(ClapParser)]
@@ -29,8 +45,11 @@ enum Commands {
     #[clap(name = "lex")]
     Lexer {
         /// The file to lex.
-        #[clap(name = "file")]
-        in_file: PathBuf,
+        #[clap(name = "file", required_unless_present = "dump_dfsa")]
+        in_file: Option<PathBuf>,
+        /// Prints the lexer's automaton instead of lexing a file.
+        #[clap(long, value_enum)]
+        dump_dfsa: Option<DfsaFormat>,
     },
     #[clap(name = "parse")]
     /// Runs the PArL parser on the given file and prints the AST.
@@ -38,6 +57,9 @@ enum Commands {
         /// The file to print.
         #[clap(name = "file")]
         in_file: PathBuf,
+        /// How to print the AST.
+        #[clap(long, value_enum, default_value_t = AstFormat::Tree)]
+        format: AstFormat,
     },
     /// Runs the PArL formatter on the given file.
     #[clap(name = "fmt")]
@@ -52,6 +74,16 @@ enum Commands {
         /// The PArL source file to analyze.
         #[clap(name = "file")]
         in_file: PathBuf,
+        /// What the file holds.
+        #[clap(long, value_enum, default_value_t = InputFormat::Parl)]
+        input_format: InputFormat,
+    },
+    /// Analyzes the given file again every time it changes.
+    #[clap(name = "watch")]
+    Watch {
+        /// The PArL source file to watch.
+        #[clap(name = "file")]
+        in_file: PathBuf,
     },
     #[clap(name = "compile")]
     /// Compiles the given file to PArIR instructions.
@@ -59,18 +91,68 @@ enum Commands {
         /// The PArL source file to compile.
         #[clap(name = "file")]
         in_file: PathBuf,
+        /// What the file holds.
+        #[clap(long, value_enum, default_value_t = InputFormat::Parl)]
+        input_format: InputFormat,
+        /// What to output.
+        #[clap(long, value_enum, default_value_t = Emit::Parir)]
+        emit: Emit,
     },
 }
 
+#[derive(Clone, ValueEnum)]
+enum DfsaFormat {
+    /// Graphviz DOT
+    Dot,
+}
+
+#[derive(Clone, PartialEq, ValueEnum)]
+enum AstFormat {
+    /// An indented tree
+    Tree,
+    /// JSON, with every token's kind and span
+    Json,
+    /// S-expressions, with every token's kind and span
+    Sexp,
+    /// Graphviz DOT
+    Dot,
+}
+
+#[derive(Clone, PartialEq, ValueEnum)]
+enum InputFormat {
+    /// PArL source
+    Parl,
+    /// An AST exported with `parse --format=json`
+    Json,
+    /// An AST exported with `parse --format=sexp`
+    Sexp,
+}
+
+#[derive(Clone, PartialEq, ValueEnum)]
+enum Emit {
+    /// PArIR instructions, written next to the source file
+    Parir,
+    /// The control-flow graph of each function as Graphviz DOT, printed
+    CfgDot,
+}
+
 fn main() {
     let cli = Cli::parse();
 
     let in_file = match &cli.subcmd {
-        Commands::Lexer { in_file } => in_file,
+        Commands::Lexer {
+            dump_dfsa: Some(DfsaFormat::Dot),
+            ..
+        } => {
+            print!("{}", dfsa_builder().minimised().build().to_dot());
+            std::process::exit(0);
+        }
+        Commands::Lexer { in_file, .. } => in_file.as_ref().unwrap(),
         Commands::Format { in_file } => in_file,
-        Commands::Semantic { in_file } => in_file,
-        Commands::Parse { in_file } => in_file,
-        Commands::Compile { in_file } => in_file,
+        Commands::Semantic { in_file, .. } => in_file,
+        Commands::Parse { in_file, .. } => in_file,
+        Commands::Watch { in_file } => in_file,
+        Commands::Compile { in_file, .. } => in_file,
     };
 
     if !in_file.exists() {
@@ -79,7 +161,7 @@ fn main() {
         std::process::exit(1);
     }
 
-    let input = match std::fs::read_to_string(in_file) {
+    let input = match std::fs::File::open(in_file) {
         Ok(input) => input,
         Err(_) => {
             let msg = style("error: could not read file")
@@ -91,34 +173,34 @@ fn main() {
         }
     };
 
-    println!(
-        "\n{} {}\n",
-        style(match &cli.subcmd {
-            Commands::Lexer { .. } => "Lexing",
-            Commands::Format { .. } => "Formatting",
-            Commands::Semantic { .. } => "Analyzing",
-            Commands::Parse { .. } => "Printing",
-            Commands::Compile { .. } => "Compiling",
-        })
-        .green()
-        .bold(),
-        style(in_file.display())
-    );
-
-    let mut lexer: Lexer<SimpleBuffer> = Lexer::new(&input, in_file, None);
-
-    let tokens = match lexer.lex() {
-        Ok(tokens) => tokens,
-        Err(e) => {
-            for err in e {
-                eprintln!("{}", err);
-            }
-            std::process::exit(1);
-        }
+    // Exported ASTs and graphs are printed alone, so that they can be piped
+    // elsewhere
+    let quiet = match &cli.subcmd {
+        Commands::Parse { format, .. } => *format != AstFormat::Tree,
+        Commands::Compile { emit, .. } => *emit != Emit::Parir,
+        _ => false,
     };
 
+    if !quiet {
+        println!(
+            "\n{} {}\n",
+            style(match &cli.subcmd {
+                Commands::Lexer { .. } => "Lexing",
+                Commands::Format { .. } => "Formatting",
+                Commands::Semantic { .. } => "Analyzing",
+                Commands::Watch { .. } => "Watching",
+                Commands::Parse { .. } => "Printing",
+                Commands::Compile { .. } => "Compiling",
+            })
+            .green()
+            .bold(),
+            style(in_file.display())
+        );
+    }
+
     match &cli.subcmd {
         Commands::Lexer { .. } => {
+            let tokens = lex(input);
             println!("{} lexed successfully.", style(in_file.display()).cyan());
             for token in &tokens {
                 println!("{:?}", token);
@@ -127,6 +209,7 @@ fn main() {
         }
 
         Commands::Format { in_file } => {
+            let tokens = lex(input);
             let mut parser = Parser::new(&tokens, in_file);
             let ast = parser.parse();
 
@@ -146,44 +229,48 @@ fn main() {
             }
         }
 
-        Commands::Semantic { in_file: file } => {
-            let mut parser = Parser::new(&tokens, file);
-            let ast = parser.parse();
+        Commands::Semantic {
+            in_file: file,
+            input_format,
+        } => {
+            let mut ast = read_program(file, input, input_format);
 
-            match ast {
-                Ok(ast) => {
-                    let mut sem_analyzer = SemAnalyzer::new();
-                    let result = sem_analyzer.analyze(ast);
-
-                    if result.has_warnings() {
-                        for warn in &result.warnings {
-                            eprintln!("{}", warn);
-                        }
-                    }
-
-                    if result.has_errors() {
-                        for err in &result.errors {
-                            eprintln!("{}", err);
-                        }
-                        std::process::exit(1);
-                    }
-
-                    println!("{} analyzed successfully.", style(file.display()).cyan());
+            PassManager::standard().run(&mut ast);
+
+            let mut sem_analyzer = SemAnalyzer::new();
+            let result = sem_analyzer.analyze(&ast);
+
+            if result.has_warnings() {
+                for warn in &result.warnings {
+                    eprintln!("{}", warn);
                 }
-                Err(e) => {
-                    eprintln!("{}", e);
-                    std::process::exit(1);
+            }
+
+            if result.has_errors() {
+                for err in &result.errors {
+                    eprintln!("{}", err);
                 }
+                std::process::exit(1);
             }
+
+            println!("{} analyzed successfully.", style(file.display()).cyan());
         }
 
-        Commands::Parse { in_file } => {
+        Commands::Parse { in_file, format } => {
+            let tokens = lex(input);
             let mut parser = Parser::new(&tokens, in_file);
             let ast = parser.parse();
 
             match ast {
+                Ok(ast) if *format == AstFormat::Json => println!("{}", export::to_json(ast)),
+                Ok(ast) if *format == AstFormat::Sexp => println!("{}", export::to_sexp(ast)),
+                Ok(ast) if *format == AstFormat::Dot => print!("{}", export::to_dot(ast)),
                 Ok(ast) => {
-                    let mut printer = TreePrinter::new();
+                    // Only used to show inferred types, errors are left to `sem`
+                    let mut sem_analyzer = SemAnalyzer::new();
+                    let inferred_types = sem_analyzer.analyze(ast).inferred_types.clone();
+
+                    let mut printer = TreePrinter::new(inferred_types);
                     printer.visit(ast).unwrap();
                 }
                 Err(e) => {
@@ -193,17 +280,17 @@ fn main() {
             }
         }
 
-        Commands::Compile { in_file } => {
-            let mut parser = Parser::new(&tokens, in_file);
-            let ast = parser.parse();
+        Commands::Watch { in_file } => watch(in_file),
 
-            let ast = match ast {
-                Ok(ast) => ast,
-                Err(e) => {
-                    eprintln!("{}", e);
-                    std::process::exit(1);
-                }
-            };
+        Commands::Compile {
+            in_file,
+            input_format,
+            emit,
+        } => {
+            let mut ast = read_program(in_file, input, input_format);
+
+            PassManager::standard().run(&mut ast);
+            let ast = &ast;
 
             let mut sem_analyzer = SemAnalyzer::new();
             let result = sem_analyzer.analyze(ast);
@@ -227,8 +314,14 @@ fn main() {
                 std::process::exit(1);
             }
 
+            let hir = sem_analyzer.hir().unwrap();
             let mut gen = generation::PArIRWriter::new();
-            let par_ir_instr = gen.get_program(ast);
+            if *emit == Emit::CfgDot {
+                print!("{}", gen.generate(hir).to_cfg_dot());
+                return;
+            }
+
+            let par_ir_instr = gen.get_program(hir);
             // get in_file, strip suffix, add .parir
             let out_file = in_file.with_extension("parir");
             let mut out_file = std::fs::File::create(out_file).unwrap();
@@ -241,3 +334,97 @@ fn main() {
         }
     }
 }
+
+/// Lexes the whole file, printing any errors and exiting if there are some.
+fn lex(input: File) -> Vec<Token> {
+    let mut lexer = Lexer::from_stream(ReadBuffer::from_reader(input), None);
+
+    match lexer.lex() {
+        Ok(tokens) => tokens,
+        Err(e) => {
+            for err in e {
+                eprintln!("{}", err);
+            }
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Reads the program in `input`, either by lexing and parsing PArL source or
+/// by importing an exported AST. Errors are printed and exit.
+fn read_program(in_file: &Path, input: File, format: &InputFormat) -> AstNode {
+    let program = match format {
+        InputFormat::Parl => Parser::new(&lex(input), in_file).parse_program(),
+        InputFormat::Json => import(input, export::from_json),
+        InputFormat::Sexp => import(input, export::from_sexp),
+    };
+
+    match program {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn import(
+    mut input: File,
+    from: fn(&str) -> Result<AstNode, AstImportError>,
+) -> utils::Result<AstNode> {
+    let mut text = String::new();
+    input.read_to_string(&mut text)?;
+    Ok(from(&text)?)
+}
+
+/// Analyzes `in_file` each time its text changes, until the process is
+/// stopped. The file is kept parsed as a [`Document`], so that only the
+/// statements a change touched are parsed again.
+fn watch(in_file: &Path) -> ! {
+    let dfsa = dfsa_builder().minimised().build();
+    let mut document = Document::new("", in_file, Some(dfsa)).unwrap();
+
+    loop {
+        let text = std::fs::read_to_string(in_file).unwrap_or_default();
+        if text == document.text() {
+            thread::sleep(Duration::from_millis(200));
+            continue;
+        }
+
+        let reparsed = match document.apply(&Edit::between(document.text(), &text)) {
+            Ok(reparsed) => reparsed,
+            Err(errors) => {
+                for err in errors {
+                    eprintln!("{}", err);
+                }
+                continue;
+            }
+        };
+
+        let AstNode::Program { statements } = document.ast() else {
+            unreachable!("a document's AST is always a program");
+        };
+        println!(
+            "Parsed {} of {} statements again",
+            reparsed.statements.len(),
+            statements.len()
+        );
+
+        // The document keeps the parsed AST for the next edit, so the passes
+        // run on a copy
+        let mut ast = document.ast().clone();
+        PassManager::standard().run(&mut ast);
+
+        let mut sem_analyzer = SemAnalyzer::new();
+        let result = sem_analyzer.analyze(&ast);
+        for warn in &result.warnings {
+            eprintln!("{}", warn);
+        }
+        for err in &result.errors {
+            eprintln!("{}", err);
+        }
+        if !result.has_errors() {
+            println!("{} analyzed successfully.", style(in_file.display()).cyan());
+        }
+    }
+}
@@ -1,15 +1,103 @@
+use std::{collections::HashSet, fmt};
+
 use crate::core::Token;
 
 pub type Ast = Box<AstNode>;
 
-#[derive(Debug)]
+/// A type as it's written in a declaration.
+#[derive(Debug, Clone)]
+pub enum TypeAnnotation {
+    /// A builtin type or the name of a struct
+    Named(Token),
+    /// `fun(int, int) -> colour`, where `function` is the `fun` keyword and
+    /// an omitted return type is `void`
+    Function {
+        function: Token,
+        params: Vec<TypeAnnotation>,
+        return_type: Option<Box<TypeAnnotation>>,
+    },
+}
+
+impl TypeAnnotation {
+    /// The token errors about this type point to, which for a function type
+    /// is its `fun` keyword.
+    pub fn token(&self) -> &Token {
+        match self {
+            TypeAnnotation::Named(token) => token,
+            TypeAnnotation::Function { function, .. } => function,
+        }
+    }
+
+    pub fn is_void(&self) -> bool {
+        matches!(self, TypeAnnotation::Named(token) if token.span.lexeme == "void")
+    }
+
+    pub fn for_each_token_mut(&mut self, f: &mut impl FnMut(&mut Token)) {
+        match self {
+            TypeAnnotation::Named(token) => f(token),
+            TypeAnnotation::Function {
+                function,
+                params,
+                return_type,
+            } => {
+                f(function);
+                for param in params {
+                    param.for_each_token_mut(f);
+                }
+                if let Some(return_type) = return_type {
+                    return_type.for_each_token_mut(f);
+                }
+            }
+        }
+    }
+}
+
+/// Writes the type the way it's written in the source.
+impl fmt::Display for TypeAnnotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeAnnotation::Named(token) => write!(f, "{}", token.span.lexeme),
+            TypeAnnotation::Function {
+                params,
+                return_type,
+                ..
+            } => {
+                write!(f, "fun(")?;
+                for (i, param) in params.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", param)?;
+                }
+                match return_type {
+                    Some(return_type) => write!(f, ") -> {}", return_type),
+                    None => write!(f, ")"),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum AstNode {
     Program {
         statements: Vec<AstNode>,
     },
+    /// `r#type` is `None` when the type is inferred from the expression
     VarDec {
         identifier: Token,
-        r#type: Token,
+        r#type: Option<TypeAnnotation>,
+        expression: Ast,
+    },
+    ConstDec {
+        identifier: Token,
+        r#type: Option<TypeAnnotation>,
+        expression: Ast,
+    },
+    /// `let (a, b) = expression;`, where an identifier may be `_` to discard
+    /// that value
+    TupleDec {
+        identifiers: Vec<Token>,
         expression: Ast,
     },
     Block {
@@ -31,6 +119,15 @@ pub enum AstNode {
         operator: Token,
         right: Ast,
     },
+    Tuple {
+        elements: Vec<AstNode>,
+    },
+    /// `condition ? if_true : if_false`
+    Conditional {
+        condition: Ast,
+        if_true: Ast,
+        if_false: Ast,
+    },
     PadWidth,
     PadRandI {
         upper_bound: Ast,
@@ -44,6 +141,12 @@ pub enum AstNode {
     FloatLiteral(Token),
     BoolLiteral(Token),
     ColourLiteral(Token),
+    Rgb {
+        red: Ast,
+        green: Ast,
+        blue: Ast,
+    },
+    StringLiteral(Token),
     FunctionCall {
         identifier: Token,
         args: Vec<Ast>,
@@ -55,7 +158,7 @@ pub enum AstNode {
         expression: Ast,
     },
     Return {
-        expression: Ast,
+        expression: Option<Ast>,
     },
     PadWriteBox {
         loc_x: Ast,
@@ -87,29 +190,590 @@ pub enum AstNode {
         condition: Ast,
         body: Ast,
     },
+    Match {
+        expression: Ast,
+        arms: Vec<AstNode>,
+        default: Option<Ast>,
+    },
+    MatchArm {
+        from: Token,
+        to: Option<Token>,
+        body: Ast,
+    },
     FormalParam {
         identifier: Token,
-        param_type: Token,
+        param_type: TypeAnnotation,
     },
     FunctionDecl {
         identifier: Token,
         params: Vec<AstNode>,
-        return_type: Token,
+        /// No types means the function returns void, and more than one means
+        /// it returns a tuple
+        return_type: Vec<TypeAnnotation>,
         block: Ast,
     },
     Print {
-        expression: Ast,
+        expressions: Vec<Ast>,
+    },
+    CallStatement {
+        call: Ast,
     },
     Assignment {
         identifier: Token,
         expression: Ast,
     },
+    CompoundAssignment {
+        identifier: Token,
+        operator: Token,
+        expression: Option<Ast>,
+    },
+    StructDecl {
+        identifier: Token,
+        fields: Vec<AstNode>,
+    },
+    StructLiteral {
+        identifier: Token,
+        fields: Vec<(Token, Ast)>,
+    },
+    FieldAccess {
+        identifier: Token,
+        fields: Vec<Token>,
+    },
+    /// `=`, a compound assignment or an increment/decrement of a field. Only
+    /// `=` and the compound assignments carry an expression.
+    FieldAssignment {
+        identifier: Token,
+        fields: Vec<Token>,
+        operator: Token,
+        expression: Option<Ast>,
+    },
     EndOfFile,
     PadClear {
         expr: Ast,
     },
 }
 
+impl AstNode {
+    /// Collects the name of every variable read or written and every
+    /// function called in this subtree. Locals aren't told apart from the
+    /// globals they shadow, so this may include names that aren't globals.
+    pub fn collect_names(&self, names: &mut HashSet<String>) {
+        match self {
+            AstNode::Identifier { token: identifier }
+            | AstNode::FieldAccess { identifier, .. }
+            | AstNode::Assignment { identifier, .. }
+            | AstNode::CompoundAssignment { identifier, .. }
+            | AstNode::FieldAssignment { identifier, .. }
+            | AstNode::FunctionCall { identifier, .. } => {
+                names.insert(identifier.span.lexeme.clone());
+            }
+            _ => {}
+        }
+
+        for child in self.children() {
+            child.collect_names(names);
+        }
+    }
+
+    /// The nodes directly under this one, in source order.
+    pub fn children(&self) -> Vec<&AstNode> {
+        let mut children = vec![];
+        match self {
+            AstNode::Program { statements }
+            | AstNode::Block { statements }
+            | AstNode::Tuple {
+                elements: statements,
+            }
+            | AstNode::StructDecl {
+                fields: statements, ..
+            } => children.extend(statements),
+            AstNode::Print { expressions }
+            | AstNode::ActualParams {
+                params: expressions,
+            }
+            | AstNode::FunctionCall {
+                args: expressions, ..
+            } => children.extend(expressions.iter().map(|node| &**node)),
+            AstNode::VarDec { expression, .. }
+            | AstNode::ConstDec { expression, .. }
+            | AstNode::TupleDec { expression, .. }
+            | AstNode::Assignment { expression, .. }
+            | AstNode::Delay { expression }
+            | AstNode::Expression {
+                expr: expression, ..
+            }
+            | AstNode::SubExpression { bin_op: expression }
+            | AstNode::UnaryOp {
+                expr: expression, ..
+            }
+            | AstNode::PadRandI {
+                upper_bound: expression,
+            }
+            | AstNode::PadClear { expr: expression }
+            | AstNode::CallStatement { call: expression }
+            | AstNode::MatchArm {
+                body: expression, ..
+            } => children.push(&**expression),
+            AstNode::Return { expression }
+            | AstNode::CompoundAssignment { expression, .. }
+            | AstNode::FieldAssignment { expression, .. } => children.extend(expression.as_deref()),
+            AstNode::BinOp { left, right, .. } => children.extend([&**left, right]),
+            AstNode::PadRead { x, y } => children.extend([&**x, y]),
+            AstNode::While { condition, body } => children.extend([&**condition, body]),
+            AstNode::Conditional {
+                condition,
+                if_true,
+                if_false,
+            } => children.extend([&**condition, if_true, if_false]),
+            AstNode::Rgb { red, green, blue } => children.extend([&**red, green, blue]),
+            AstNode::PadWrite {
+                loc_x,
+                loc_y,
+                colour,
+            } => children.extend([&**loc_x, loc_y, colour]),
+            AstNode::PadWriteBox {
+                loc_x,
+                loc_y,
+                width,
+                height,
+                colour,
+            } => children.extend([&**loc_x, loc_y, width, height, colour]),
+            AstNode::If {
+                condition,
+                if_true,
+                if_false,
+            } => {
+                children.extend([&**condition, if_true]);
+                children.extend(if_false.as_deref());
+            }
+            AstNode::For {
+                initializer,
+                condition,
+                increment,
+                body,
+            } => {
+                children.extend(initializer.as_deref());
+                children.push(condition);
+                children.extend(increment.as_deref());
+                children.push(body);
+            }
+            AstNode::Match {
+                expression,
+                arms,
+                default,
+            } => {
+                children.push(expression);
+                children.extend(arms);
+                children.extend(default.as_deref());
+            }
+            AstNode::FunctionDecl { params, block, .. } => {
+                children.extend(params);
+                children.push(block);
+            }
+            AstNode::StructLiteral { fields, .. } => {
+                children.extend(fields.iter().map(|(_, value)| &**value));
+            }
+            AstNode::FormalParam { .. }
+            | AstNode::FieldAccess { .. }
+            | AstNode::IntLiteral(_)
+            | AstNode::FloatLiteral(_)
+            | AstNode::BoolLiteral(_)
+            | AstNode::ColourLiteral(_)
+            | AstNode::StringLiteral(_)
+            | AstNode::Identifier { .. }
+            | AstNode::PadWidth
+            | AstNode::PadHeight
+            | AstNode::EndOfFile => {}
+        }
+        children
+    }
+
+    /// Like [`AstNode::children`], but the children can be rewritten or
+    /// replaced.
+    pub fn children_mut(&mut self) -> Vec<&mut AstNode> {
+        let mut children = vec![];
+        match self {
+            AstNode::Program { statements }
+            | AstNode::Block { statements }
+            | AstNode::Tuple {
+                elements: statements,
+            }
+            | AstNode::StructDecl {
+                fields: statements, ..
+            } => children.extend(statements),
+            AstNode::Print { expressions }
+            | AstNode::ActualParams {
+                params: expressions,
+            }
+            | AstNode::FunctionCall {
+                args: expressions, ..
+            } => children.extend(expressions.iter_mut().map(|node| &mut **node)),
+            AstNode::VarDec { expression, .. }
+            | AstNode::ConstDec { expression, .. }
+            | AstNode::TupleDec { expression, .. }
+            | AstNode::Assignment { expression, .. }
+            | AstNode::Delay { expression }
+            | AstNode::Expression {
+                expr: expression, ..
+            }
+            | AstNode::SubExpression { bin_op: expression }
+            | AstNode::UnaryOp {
+                expr: expression, ..
+            }
+            | AstNode::PadRandI {
+                upper_bound: expression,
+            }
+            | AstNode::PadClear { expr: expression }
+            | AstNode::CallStatement { call: expression }
+            | AstNode::MatchArm {
+                body: expression, ..
+            } => children.push(&mut **expression),
+            AstNode::Return { expression }
+            | AstNode::CompoundAssignment { expression, .. }
+            | AstNode::FieldAssignment { expression, .. } => {
+                children.extend(expression.as_deref_mut())
+            }
+            AstNode::BinOp { left, right, .. } => children.extend([&mut **left, right]),
+            AstNode::PadRead { x, y } => children.extend([&mut **x, y]),
+            AstNode::While { condition, body } => children.extend([&mut **condition, body]),
+            AstNode::Conditional {
+                condition,
+                if_true,
+                if_false,
+            } => children.extend([&mut **condition, if_true, if_false]),
+            AstNode::Rgb { red, green, blue } => children.extend([&mut **red, green, blue]),
+            AstNode::PadWrite {
+                loc_x,
+                loc_y,
+                colour,
+            } => children.extend([&mut **loc_x, loc_y, colour]),
+            AstNode::PadWriteBox {
+                loc_x,
+                loc_y,
+                width,
+                height,
+                colour,
+            } => children.extend([&mut **loc_x, loc_y, width, height, colour]),
+            AstNode::If {
+                condition,
+                if_true,
+                if_false,
+            } => {
+                children.extend([&mut **condition, if_true]);
+                children.extend(if_false.as_deref_mut());
+            }
+            AstNode::For {
+                initializer,
+                condition,
+                increment,
+                body,
+            } => {
+                children.extend(initializer.as_deref_mut());
+                children.push(condition);
+                children.extend(increment.as_deref_mut());
+                children.push(body);
+            }
+            AstNode::Match {
+                expression,
+                arms,
+                default,
+            } => {
+                children.push(expression);
+                children.extend(arms);
+                children.extend(default.as_deref_mut());
+            }
+            AstNode::FunctionDecl { params, block, .. } => {
+                children.extend(params);
+                children.push(block);
+            }
+            AstNode::StructLiteral { fields, .. } => {
+                children.extend(fields.iter_mut().map(|(_, value)| &mut **value));
+            }
+            AstNode::FormalParam { .. }
+            | AstNode::FieldAccess { .. }
+            | AstNode::IntLiteral(_)
+            | AstNode::FloatLiteral(_)
+            | AstNode::BoolLiteral(_)
+            | AstNode::ColourLiteral(_)
+            | AstNode::StringLiteral(_)
+            | AstNode::Identifier { .. }
+            | AstNode::PadWidth
+            | AstNode::PadHeight
+            | AstNode::EndOfFile => {}
+        }
+        children
+    }
+
+    /// Calls `f` on every token in this subtree, e.g. to move their spans
+    /// after an edit earlier in the file.
+    pub fn for_each_token_mut(&mut self, f: &mut impl FnMut(&mut Token)) {
+        match self {
+            AstNode::IntLiteral(token)
+            | AstNode::FloatLiteral(token)
+            | AstNode::BoolLiteral(token)
+            | AstNode::ColourLiteral(token)
+            | AstNode::StringLiteral(token)
+            | AstNode::Identifier { token } => f(token),
+            AstNode::VarDec {
+                identifier,
+                r#type,
+                expression,
+            }
+            | AstNode::ConstDec {
+                identifier,
+                r#type,
+                expression,
+            } => {
+                f(identifier);
+                if let Some(r#type) = r#type {
+                    r#type.for_each_token_mut(f);
+                }
+                expression.for_each_token_mut(f);
+            }
+            AstNode::TupleDec {
+                identifiers,
+                expression,
+            } => {
+                identifiers.iter_mut().for_each(&mut *f);
+                expression.for_each_token_mut(f);
+            }
+            AstNode::Expression { casted_type, expr } => {
+                expr.for_each_token_mut(f);
+                casted_type.iter_mut().for_each(f);
+            }
+            AstNode::UnaryOp { operator, expr } => {
+                f(operator);
+                expr.for_each_token_mut(f);
+            }
+            AstNode::BinOp {
+                left,
+                operator,
+                right,
+            } => {
+                left.for_each_token_mut(f);
+                f(operator);
+                right.for_each_token_mut(f);
+            }
+            AstNode::Program { statements }
+            | AstNode::Block { statements }
+            | AstNode::Tuple {
+                elements: statements,
+            } => {
+                for statement in statements {
+                    statement.for_each_token_mut(f);
+                }
+            }
+            AstNode::Print { expressions }
+            | AstNode::ActualParams {
+                params: expressions,
+            } => {
+                for expression in expressions {
+                    expression.for_each_token_mut(f);
+                }
+            }
+            AstNode::FunctionCall { identifier, args } => {
+                f(identifier);
+                for arg in args {
+                    arg.for_each_token_mut(f);
+                }
+            }
+            AstNode::SubExpression { bin_op: expression }
+            | AstNode::Delay { expression }
+            | AstNode::PadRandI {
+                upper_bound: expression,
+            }
+            | AstNode::PadClear { expr: expression }
+            | AstNode::CallStatement { call: expression } => expression.for_each_token_mut(f),
+            AstNode::Return { expression } => {
+                if let Some(expression) = expression {
+                    expression.for_each_token_mut(f);
+                }
+            }
+            AstNode::Conditional {
+                condition,
+                if_true,
+                if_false,
+            } => {
+                for node in [condition, if_true, if_false] {
+                    node.for_each_token_mut(f);
+                }
+            }
+            AstNode::PadRead { x, y } => {
+                x.for_each_token_mut(f);
+                y.for_each_token_mut(f);
+            }
+            AstNode::Rgb { red, green, blue } => {
+                for node in [red, green, blue] {
+                    node.for_each_token_mut(f);
+                }
+            }
+            AstNode::PadWrite {
+                loc_x,
+                loc_y,
+                colour,
+            } => {
+                for node in [loc_x, loc_y, colour] {
+                    node.for_each_token_mut(f);
+                }
+            }
+            AstNode::PadWriteBox {
+                loc_x,
+                loc_y,
+                width,
+                height,
+                colour,
+            } => {
+                for node in [loc_x, loc_y, width, height, colour] {
+                    node.for_each_token_mut(f);
+                }
+            }
+            AstNode::If {
+                condition,
+                if_true,
+                if_false,
+            } => {
+                condition.for_each_token_mut(f);
+                if_true.for_each_token_mut(f);
+                if let Some(if_false) = if_false {
+                    if_false.for_each_token_mut(f);
+                }
+            }
+            AstNode::For {
+                initializer,
+                condition,
+                increment,
+                body,
+            } => {
+                if let Some(initializer) = initializer {
+                    initializer.for_each_token_mut(f);
+                }
+                condition.for_each_token_mut(f);
+                if let Some(increment) = increment {
+                    increment.for_each_token_mut(f);
+                }
+                body.for_each_token_mut(f);
+            }
+            AstNode::While { condition, body } => {
+                condition.for_each_token_mut(f);
+                body.for_each_token_mut(f);
+            }
+            AstNode::Match {
+                expression,
+                arms,
+                default,
+            } => {
+                expression.for_each_token_mut(f);
+                for arm in arms {
+                    arm.for_each_token_mut(f);
+                }
+                if let Some(default) = default {
+                    default.for_each_token_mut(f);
+                }
+            }
+            AstNode::MatchArm { from, to, body } => {
+                f(from);
+                to.iter_mut().for_each(&mut *f);
+                body.for_each_token_mut(f);
+            }
+            AstNode::FormalParam {
+                identifier,
+                param_type,
+            } => {
+                f(identifier);
+                param_type.for_each_token_mut(f);
+            }
+            AstNode::FunctionDecl {
+                identifier,
+                params,
+                return_type,
+                block,
+            } => {
+                f(identifier);
+                for param in params {
+                    param.for_each_token_mut(f);
+                }
+                for return_type in return_type {
+                    return_type.for_each_token_mut(f);
+                }
+                block.for_each_token_mut(f);
+            }
+            AstNode::Assignment {
+                identifier,
+                expression,
+            } => {
+                f(identifier);
+                expression.for_each_token_mut(f);
+            }
+            AstNode::CompoundAssignment {
+                identifier,
+                operator,
+                expression,
+            } => {
+                f(identifier);
+                f(operator);
+                if let Some(expression) = expression {
+                    expression.for_each_token_mut(f);
+                }
+            }
+            AstNode::StructDecl { identifier, fields } => {
+                f(identifier);
+                for field in fields {
+                    field.for_each_token_mut(f);
+                }
+            }
+            AstNode::StructLiteral { identifier, fields } => {
+                f(identifier);
+                for (name, value) in fields {
+                    f(name);
+                    value.for_each_token_mut(f);
+                }
+            }
+            AstNode::FieldAccess { identifier, fields } => {
+                f(identifier);
+                fields.iter_mut().for_each(f);
+            }
+            AstNode::FieldAssignment {
+                identifier,
+                fields,
+                operator,
+                expression,
+            } => {
+                f(identifier);
+                fields.iter_mut().for_each(&mut *f);
+                f(operator);
+                if let Some(expression) = expression {
+                    expression.for_each_token_mut(f);
+                }
+            }
+            AstNode::PadWidth | AstNode::PadHeight | AstNode::EndOfFile => {}
+        }
+    }
+}
+
 pub trait Visitor<T> {
     fn visit(&mut self, node: &AstNode) -> T;
 }
+
+/// Visits every child of `node`, for visitors that only handle some kinds of
+/// node themselves. The children's results are dropped.
+pub fn walk<T, V: Visitor<T> + ?Sized>(visitor: &mut V, node: &AstNode) {
+    for child in node.children() {
+        visitor.visit(child);
+    }
+}
+
+/// A visitor that can rewrite the tree in place, or replace a node outright
+/// by assigning to it.
+pub trait VisitorMut {
+    fn visit_mut(&mut self, node: &mut AstNode) {
+        walk_mut(self, node);
+    }
+}
+
+/// Visits every child of `node` mutably, which is what [`VisitorMut`] does
+/// by default.
+pub fn walk_mut<V: VisitorMut + ?Sized>(visitor: &mut V, node: &mut AstNode) {
+    for child in node.children_mut() {
+        visitor.visit_mut(child);
+    }
+}
@@ -0,0 +1,1392 @@
+use std::{borrow::Borrow, fmt::Write};
+
+use crate::{
+    core::{TextSpan, Token, TOKEN_KINDS},
+    utils::errors::AstImportError,
+};
+
+use super::ast::{Ast, AstNode, TypeAnnotation};
+
+/// A node, token or list in either format, which share one schema.
+///
+/// A node is written as its variant's name and its fields, in the order
+/// they're declared in [`AstNode`], with `r#type` named `type` and the token
+/// of `IntLiteral` and friends named `token`. A token is its kind, its lexeme
+/// and its span. A type is its token, or a `FunctionType` node with the
+/// `fun` keyword as `function` and then its `params` and `return_type`. Lists
+/// are written as lists, and a missing optional field as null. In JSON:
+///
+/// ```text
+/// {
+///   "node": "Identifier",
+///   "token": {"kind": "Identifier", "lexeme": "x", "span": {"from_line": 1,
+///     "from_col": 5, "to_line": 1, "to_col": 6, "from_offset": 4, "to_offset": 5}}
+/// }
+/// ```
+///
+/// and as an S-expression, with the span's numbers in the same order:
+///
+/// ```text
+/// (Identifier
+///   :token (token Identifier "x" 1 5 1 6 4 5))
+/// ```
+#[derive(Debug)]
+enum Value {
+    Null,
+    Token(Token),
+    List(Vec<Value>),
+    Node(String, Vec<(String, Value)>),
+}
+
+pub fn to_json(node: &AstNode) -> String {
+    let mut out = String::new();
+    write_json(&to_value(node), 0, &mut out);
+    out
+}
+
+pub fn to_sexp(node: &AstNode) -> String {
+    let mut out = String::new();
+    write_sexp(&to_value(node), 0, &mut out);
+    out
+}
+
+/// The AST as a Graphviz digraph, with a box per node, an ellipse per token
+/// and edges labelled with the fields they're in.
+pub fn to_dot(node: &AstNode) -> String {
+    let mut dot = String::from("digraph ast {\n    node [shape=box];\n");
+    write_dot(&to_value(node), &mut 0, &mut dot);
+    dot.push_str("}\n");
+    dot
+}
+
+pub fn from_json(input: &str) -> Result<AstNode, AstImportError> {
+    let mut reader = Reader::new(input);
+    let json = reader.json()?;
+    reader.end()?;
+    from_value(json_to_value(json)?)
+}
+
+pub fn from_sexp(input: &str) -> Result<AstNode, AstImportError> {
+    let mut reader = Reader::new(input);
+    let sexp = reader.sexp()?;
+    reader.end()?;
+    from_value(sexp_to_value(sexp)?)
+}
+
+fn token(token: &Token) -> Value {
+    Value::Token(token.clone())
+}
+
+fn optional_token(token: &Option<Token>) -> Value {
+    token.as_ref().map_or(Value::Null, self::token)
+}
+
+fn tokens(tokens: &[Token]) -> Value {
+    Value::List(tokens.iter().map(token).collect())
+}
+
+fn type_annotation(annotation: &TypeAnnotation) -> Value {
+    match annotation {
+        TypeAnnotation::Named(token) => self::token(token),
+        TypeAnnotation::Function {
+            function,
+            params,
+            return_type,
+        } => Value::Node(
+            "FunctionType".to_string(),
+            vec![
+                ("function".to_string(), token(function)),
+                ("params".to_string(), type_annotations(params)),
+                (
+                    "return_type".to_string(),
+                    return_type.as_deref().map_or(Value::Null, type_annotation),
+                ),
+            ],
+        ),
+    }
+}
+
+fn optional_type_annotation(annotation: &Option<TypeAnnotation>) -> Value {
+    annotation.as_ref().map_or(Value::Null, type_annotation)
+}
+
+fn type_annotations(annotations: &[TypeAnnotation]) -> Value {
+    Value::List(annotations.iter().map(type_annotation).collect())
+}
+
+fn tree(node: &AstNode) -> Value {
+    to_value(node)
+}
+
+fn optional_tree(node: &Option<Ast>) -> Value {
+    node.as_ref().map_or(Value::Null, |node| to_value(node))
+}
+
+fn trees<N: Borrow<AstNode>>(nodes: &[N]) -> Value {
+    Value::List(nodes.iter().map(|node| to_value(node.borrow())).collect())
+}
+
+fn to_value(node: &AstNode) -> Value {
+    let (name, fields) = match node {
+        AstNode::Program { statements } => ("Program", vec![("statements", trees(statements))]),
+        AstNode::VarDec {
+            identifier,
+            r#type,
+            expression,
+        } => (
+            "VarDec",
+            vec![
+                ("identifier", token(identifier)),
+                ("type", optional_type_annotation(r#type)),
+                ("expression", tree(expression)),
+            ],
+        ),
+        AstNode::ConstDec {
+            identifier,
+            r#type,
+            expression,
+        } => (
+            "ConstDec",
+            vec![
+                ("identifier", token(identifier)),
+                ("type", optional_type_annotation(r#type)),
+                ("expression", tree(expression)),
+            ],
+        ),
+        AstNode::TupleDec {
+            identifiers,
+            expression,
+        } => (
+            "TupleDec",
+            vec![
+                ("identifiers", tokens(identifiers)),
+                ("expression", tree(expression)),
+            ],
+        ),
+        AstNode::Block { statements } => ("Block", vec![("statements", trees(statements))]),
+        AstNode::Expression { casted_type, expr } => (
+            "Expression",
+            vec![
+                ("casted_type", optional_token(casted_type)),
+                ("expr", tree(expr)),
+            ],
+        ),
+        AstNode::SubExpression { bin_op } => ("SubExpression", vec![("bin_op", tree(bin_op))]),
+        AstNode::UnaryOp { operator, expr } => (
+            "UnaryOp",
+            vec![("operator", token(operator)), ("expr", tree(expr))],
+        ),
+        AstNode::BinOp {
+            left,
+            operator,
+            right,
+        } => (
+            "BinOp",
+            vec![
+                ("left", tree(left)),
+                ("operator", token(operator)),
+                ("right", tree(right)),
+            ],
+        ),
+        AstNode::Tuple { elements } => ("Tuple", vec![("elements", trees(elements))]),
+        AstNode::Conditional {
+            condition,
+            if_true,
+            if_false,
+        } => (
+            "Conditional",
+            vec![
+                ("condition", tree(condition)),
+                ("if_true", tree(if_true)),
+                ("if_false", tree(if_false)),
+            ],
+        ),
+        AstNode::PadWidth => ("PadWidth", vec![]),
+        AstNode::PadRandI { upper_bound } => ("PadRandI", vec![("upper_bound", tree(upper_bound))]),
+        AstNode::PadHeight => ("PadHeight", vec![]),
+        AstNode::PadRead { x, y } => ("PadRead", vec![("x", tree(x)), ("y", tree(y))]),
+        AstNode::IntLiteral(literal) => ("IntLiteral", vec![("token", token(literal))]),
+        AstNode::FloatLiteral(literal) => ("FloatLiteral", vec![("token", token(literal))]),
+        AstNode::BoolLiteral(literal) => ("BoolLiteral", vec![("token", token(literal))]),
+        AstNode::ColourLiteral(literal) => ("ColourLiteral", vec![("token", token(literal))]),
+        AstNode::Rgb { red, green, blue } => (
+            "Rgb",
+            vec![
+                ("red", tree(red)),
+                ("green", tree(green)),
+                ("blue", tree(blue)),
+            ],
+        ),
+        AstNode::StringLiteral(literal) => ("StringLiteral", vec![("token", token(literal))]),
+        AstNode::FunctionCall { identifier, args } => (
+            "FunctionCall",
+            vec![("identifier", token(identifier)), ("args", trees(args))],
+        ),
+        AstNode::ActualParams { params } => ("ActualParams", vec![("params", trees(params))]),
+        AstNode::Delay { expression } => ("Delay", vec![("expression", tree(expression))]),
+        AstNode::Return { expression } => {
+            ("Return", vec![("expression", optional_tree(expression))])
+        }
+        AstNode::PadWriteBox {
+            loc_x,
+            loc_y,
+            width,
+            height,
+            colour,
+        } => (
+            "PadWriteBox",
+            vec![
+                ("loc_x", tree(loc_x)),
+                ("loc_y", tree(loc_y)),
+                ("width", tree(width)),
+                ("height", tree(height)),
+                ("colour", tree(colour)),
+            ],
+        ),
+        AstNode::PadWrite {
+            loc_x,
+            loc_y,
+            colour,
+        } => (
+            "PadWrite",
+            vec![
+                ("loc_x", tree(loc_x)),
+                ("loc_y", tree(loc_y)),
+                ("colour", tree(colour)),
+            ],
+        ),
+        AstNode::Identifier { token: identifier } => {
+            ("Identifier", vec![("token", token(identifier))])
+        }
+        AstNode::If {
+            condition,
+            if_true,
+            if_false,
+        } => (
+            "If",
+            vec![
+                ("condition", tree(condition)),
+                ("if_true", tree(if_true)),
+                ("if_false", optional_tree(if_false)),
+            ],
+        ),
+        AstNode::For {
+            initializer,
+            condition,
+            increment,
+            body,
+        } => (
+            "For",
+            vec![
+                ("initializer", optional_tree(initializer)),
+                ("condition", tree(condition)),
+                ("increment", optional_tree(increment)),
+                ("body", tree(body)),
+            ],
+        ),
+        AstNode::While { condition, body } => (
+            "While",
+            vec![("condition", tree(condition)), ("body", tree(body))],
+        ),
+        AstNode::Match {
+            expression,
+            arms,
+            default,
+        } => (
+            "Match",
+            vec![
+                ("expression", tree(expression)),
+                ("arms", trees(arms)),
+                ("default", optional_tree(default)),
+            ],
+        ),
+        AstNode::MatchArm { from, to, body } => (
+            "MatchArm",
+            vec![
+                ("from", token(from)),
+                ("to", optional_token(to)),
+                ("body", tree(body)),
+            ],
+        ),
+        AstNode::FormalParam {
+            identifier,
+            param_type,
+        } => (
+            "FormalParam",
+            vec![
+                ("identifier", token(identifier)),
+                ("param_type", type_annotation(param_type)),
+            ],
+        ),
+        AstNode::FunctionDecl {
+            identifier,
+            params,
+            return_type,
+            block,
+        } => (
+            "FunctionDecl",
+            vec![
+                ("identifier", token(identifier)),
+                ("params", trees(params)),
+                ("return_type", type_annotations(return_type)),
+                ("block", tree(block)),
+            ],
+        ),
+        AstNode::Print { expressions } => ("Print", vec![("expressions", trees(expressions))]),
+        AstNode::CallStatement { call } => ("CallStatement", vec![("call", tree(call))]),
+        AstNode::Assignment {
+            identifier,
+            expression,
+        } => (
+            "Assignment",
+            vec![
+                ("identifier", token(identifier)),
+                ("expression", tree(expression)),
+            ],
+        ),
+        AstNode::CompoundAssignment {
+            identifier,
+            operator,
+            expression,
+        } => (
+            "CompoundAssignment",
+            vec![
+                ("identifier", token(identifier)),
+                ("operator", token(operator)),
+                ("expression", optional_tree(expression)),
+            ],
+        ),
+        AstNode::StructDecl { identifier, fields } => (
+            "StructDecl",
+            vec![("identifier", token(identifier)), ("fields", trees(fields))],
+        ),
+        AstNode::StructLiteral { identifier, fields } => (
+            "StructLiteral",
+            vec![
+                ("identifier", token(identifier)),
+                (
+                    "fields",
+                    Value::List(
+                        fields
+                            .iter()
+                            .map(|(name, value)| Value::List(vec![token(name), tree(value)]))
+                            .collect(),
+                    ),
+                ),
+            ],
+        ),
+        AstNode::FieldAccess { identifier, fields } => (
+            "FieldAccess",
+            vec![
+                ("identifier", token(identifier)),
+                ("fields", tokens(fields)),
+            ],
+        ),
+        AstNode::FieldAssignment {
+            identifier,
+            fields,
+            operator,
+            expression,
+        } => (
+            "FieldAssignment",
+            vec![
+                ("identifier", token(identifier)),
+                ("fields", tokens(fields)),
+                ("operator", token(operator)),
+                ("expression", optional_tree(expression)),
+            ],
+        ),
+        AstNode::EndOfFile => ("EndOfFile", vec![]),
+        AstNode::PadClear { expr } => ("PadClear", vec![("expr", tree(expr))]),
+    };
+
+    Value::Node(
+        name.to_string(),
+        fields
+            .into_iter()
+            .map(|(field, value)| (field.to_string(), value))
+            .collect(),
+    )
+}
+
+/// The fields of a node being read, which are taken out by name.
+struct Fields {
+    node: String,
+    fields: Vec<(String, Value)>,
+}
+
+impl Fields {
+    fn take(&mut self, name: &str) -> Result<Value, AstImportError> {
+        match self.fields.iter().position(|(field, _)| field == name) {
+            Some(i) => Ok(self.fields.remove(i).1),
+            None => Err(AstImportError::MissingField(
+                self.node.clone(),
+                name.to_string(),
+            )),
+        }
+    }
+
+    fn invalid(&self, name: &str) -> AstImportError {
+        AstImportError::InvalidField(self.node.clone(), name.to_string())
+    }
+
+    fn token(&mut self, name: &str) -> Result<Token, AstImportError> {
+        match self.take(name)? {
+            Value::Token(token) => Ok(token),
+            _ => Err(self.invalid(name)),
+        }
+    }
+
+    fn optional_token(&mut self, name: &str) -> Result<Option<Token>, AstImportError> {
+        match self.take(name)? {
+            Value::Null => Ok(None),
+            Value::Token(token) => Ok(Some(token)),
+            _ => Err(self.invalid(name)),
+        }
+    }
+
+    fn tokens(&mut self, name: &str) -> Result<Vec<Token>, AstImportError> {
+        match self.take(name)? {
+            Value::List(items) => items
+                .into_iter()
+                .map(|item| match item {
+                    Value::Token(token) => Ok(token),
+                    _ => Err(self.invalid(name)),
+                })
+                .collect(),
+            _ => Err(self.invalid(name)),
+        }
+    }
+
+    fn type_annotation(&mut self, name: &str) -> Result<TypeAnnotation, AstImportError> {
+        match self.take(name)? {
+            value @ (Value::Token(_) | Value::Node(..)) => to_type_annotation(value),
+            _ => Err(self.invalid(name)),
+        }
+    }
+
+    fn optional_type_annotation(
+        &mut self,
+        name: &str,
+    ) -> Result<Option<TypeAnnotation>, AstImportError> {
+        match self.take(name)? {
+            Value::Null => Ok(None),
+            value @ (Value::Token(_) | Value::Node(..)) => to_type_annotation(value).map(Some),
+            _ => Err(self.invalid(name)),
+        }
+    }
+
+    fn type_annotations(&mut self, name: &str) -> Result<Vec<TypeAnnotation>, AstImportError> {
+        match self.take(name)? {
+            Value::List(items) => items
+                .into_iter()
+                .map(|item| match item {
+                    value @ (Value::Token(_) | Value::Node(..)) => to_type_annotation(value),
+                    _ => Err(self.invalid(name)),
+                })
+                .collect(),
+            _ => Err(self.invalid(name)),
+        }
+    }
+
+    fn tree(&mut self, name: &str) -> Result<Ast, AstImportError> {
+        match self.take(name)? {
+            node @ Value::Node(..) => Ok(Box::new(from_value(node)?)),
+            _ => Err(self.invalid(name)),
+        }
+    }
+
+    fn optional_tree(&mut self, name: &str) -> Result<Option<Ast>, AstImportError> {
+        match self.take(name)? {
+            Value::Null => Ok(None),
+            node @ Value::Node(..) => Ok(Some(Box::new(from_value(node)?))),
+            _ => Err(self.invalid(name)),
+        }
+    }
+
+    fn trees(&mut self, name: &str) -> Result<Vec<AstNode>, AstImportError> {
+        match self.take(name)? {
+            Value::List(items) => items
+                .into_iter()
+                .map(|item| match item {
+                    node @ Value::Node(..) => from_value(node),
+                    _ => Err(self.invalid(name)),
+                })
+                .collect(),
+            _ => Err(self.invalid(name)),
+        }
+    }
+
+    fn boxed_trees(&mut self, name: &str) -> Result<Vec<Ast>, AstImportError> {
+        Ok(self.trees(name)?.into_iter().map(Box::new).collect())
+    }
+
+    fn struct_fields(&mut self, name: &str) -> Result<Vec<(Token, Ast)>, AstImportError> {
+        match self.take(name)? {
+            Value::List(items) => items
+                .into_iter()
+                .map(|item| match item {
+                    Value::List(pair) => match <[Value; 2]>::try_from(pair) {
+                        Ok([Value::Token(field), node @ Value::Node(..)]) => {
+                            Ok((field, Box::new(from_value(node)?)))
+                        }
+                        _ => Err(self.invalid(name)),
+                    },
+                    _ => Err(self.invalid(name)),
+                })
+                .collect(),
+            _ => Err(self.invalid(name)),
+        }
+    }
+}
+
+/// Reads a type, which is either its token or a `FunctionType` node.
+fn to_type_annotation(value: Value) -> Result<TypeAnnotation, AstImportError> {
+    match value {
+        Value::Token(token) => Ok(TypeAnnotation::Named(token)),
+        Value::Node(name, fields) if name == "FunctionType" => {
+            let mut f = Fields { node: name, fields };
+            Ok(TypeAnnotation::Function {
+                function: f.token("function")?,
+                params: f.type_annotations("params")?,
+                return_type: f.optional_type_annotation("return_type")?.map(Box::new),
+            })
+        }
+        Value::Node(name, _) => Err(AstImportError::UnknownNode(name)),
+        _ => Err(AstImportError::InvalidValue),
+    }
+}
+
+fn from_value(value: Value) -> Result<AstNode, AstImportError> {
+    let Value::Node(name, fields) = value else {
+        return Err(AstImportError::InvalidValue);
+    };
+    let mut f = Fields {
+        node: name.clone(),
+        fields,
+    };
+
+    Ok(match name.as_str() {
+        "Program" => AstNode::Program {
+            statements: f.trees("statements")?,
+        },
+        "VarDec" => AstNode::VarDec {
+            identifier: f.token("identifier")?,
+            r#type: f.optional_type_annotation("type")?,
+            expression: f.tree("expression")?,
+        },
+        "ConstDec" => AstNode::ConstDec {
+            identifier: f.token("identifier")?,
+            r#type: f.optional_type_annotation("type")?,
+            expression: f.tree("expression")?,
+        },
+        "TupleDec" => AstNode::TupleDec {
+            identifiers: f.tokens("identifiers")?,
+            expression: f.tree("expression")?,
+        },
+        "Block" => AstNode::Block {
+            statements: f.trees("statements")?,
+        },
+        "Expression" => AstNode::Expression {
+            casted_type: f.optional_token("casted_type")?,
+            expr: f.tree("expr")?,
+        },
+        "SubExpression" => AstNode::SubExpression {
+            bin_op: f.tree("bin_op")?,
+        },
+        "UnaryOp" => AstNode::UnaryOp {
+            operator: f.token("operator")?,
+            expr: f.tree("expr")?,
+        },
+        "BinOp" => AstNode::BinOp {
+            left: f.tree("left")?,
+            operator: f.token("operator")?,
+            right: f.tree("right")?,
+        },
+        "Tuple" => AstNode::Tuple {
+            elements: f.trees("elements")?,
+        },
+        "Conditional" => AstNode::Conditional {
+            condition: f.tree("condition")?,
+            if_true: f.tree("if_true")?,
+            if_false: f.tree("if_false")?,
+        },
+        "PadWidth" => AstNode::PadWidth,
+        "PadRandI" => AstNode::PadRandI {
+            upper_bound: f.tree("upper_bound")?,
+        },
+        "PadHeight" => AstNode::PadHeight,
+        "PadRead" => AstNode::PadRead {
+            x: f.tree("x")?,
+            y: f.tree("y")?,
+        },
+        "IntLiteral" => AstNode::IntLiteral(f.token("token")?),
+        "FloatLiteral" => AstNode::FloatLiteral(f.token("token")?),
+        "BoolLiteral" => AstNode::BoolLiteral(f.token("token")?),
+        "ColourLiteral" => AstNode::ColourLiteral(f.token("token")?),
+        "Rgb" => AstNode::Rgb {
+            red: f.tree("red")?,
+            green: f.tree("green")?,
+            blue: f.tree("blue")?,
+        },
+        "StringLiteral" => AstNode::StringLiteral(f.token("token")?),
+        "FunctionCall" => AstNode::FunctionCall {
+            identifier: f.token("identifier")?,
+            args: f.boxed_trees("args")?,
+        },
+        "ActualParams" => AstNode::ActualParams {
+            params: f.boxed_trees("params")?,
+        },
+        "Delay" => AstNode::Delay {
+            expression: f.tree("expression")?,
+        },
+        "Return" => AstNode::Return {
+            expression: f.optional_tree("expression")?,
+        },
+        "PadWriteBox" => AstNode::PadWriteBox {
+            loc_x: f.tree("loc_x")?,
+            loc_y: f.tree("loc_y")?,
+            width: f.tree("width")?,
+            height: f.tree("height")?,
+            colour: f.tree("colour")?,
+        },
+        "PadWrite" => AstNode::PadWrite {
+            loc_x: f.tree("loc_x")?,
+            loc_y: f.tree("loc_y")?,
+            colour: f.tree("colour")?,
+        },
+        "Identifier" => AstNode::Identifier {
+            token: f.token("token")?,
+        },
+        "If" => AstNode::If {
+            condition: f.tree("condition")?,
+            if_true: f.tree("if_true")?,
+            if_false: f.optional_tree("if_false")?,
+        },
+        "For" => AstNode::For {
+            initializer: f.optional_tree("initializer")?,
+            condition: f.tree("condition")?,
+            increment: f.optional_tree("increment")?,
+            body: f.tree("body")?,
+        },
+        "While" => AstNode::While {
+            condition: f.tree("condition")?,
+            body: f.tree("body")?,
+        },
+        "Match" => AstNode::Match {
+            expression: f.tree("expression")?,
+            arms: f.trees("arms")?,
+            default: f.optional_tree("default")?,
+        },
+        "MatchArm" => AstNode::MatchArm {
+            from: f.token("from")?,
+            to: f.optional_token("to")?,
+            body: f.tree("body")?,
+        },
+        "FormalParam" => AstNode::FormalParam {
+            identifier: f.token("identifier")?,
+            param_type: f.type_annotation("param_type")?,
+        },
+        "FunctionDecl" => AstNode::FunctionDecl {
+            identifier: f.token("identifier")?,
+            params: f.trees("params")?,
+            return_type: f.type_annotations("return_type")?,
+            block: f.tree("block")?,
+        },
+        "Print" => AstNode::Print {
+            expressions: f.boxed_trees("expressions")?,
+        },
+        "CallStatement" => AstNode::CallStatement {
+            call: f.tree("call")?,
+        },
+        "Assignment" => AstNode::Assignment {
+            identifier: f.token("identifier")?,
+            expression: f.tree("expression")?,
+        },
+        "CompoundAssignment" => AstNode::CompoundAssignment {
+            identifier: f.token("identifier")?,
+            operator: f.token("operator")?,
+            expression: f.optional_tree("expression")?,
+        },
+        "StructDecl" => AstNode::StructDecl {
+            identifier: f.token("identifier")?,
+            fields: f.trees("fields")?,
+        },
+        "StructLiteral" => AstNode::StructLiteral {
+            identifier: f.token("identifier")?,
+            fields: f.struct_fields("fields")?,
+        },
+        "FieldAccess" => AstNode::FieldAccess {
+            identifier: f.token("identifier")?,
+            fields: f.tokens("fields")?,
+        },
+        "FieldAssignment" => AstNode::FieldAssignment {
+            identifier: f.token("identifier")?,
+            fields: f.tokens("fields")?,
+            operator: f.token("operator")?,
+            expression: f.optional_tree("expression")?,
+        },
+        "EndOfFile" => AstNode::EndOfFile,
+        "PadClear" => AstNode::PadClear {
+            expr: f.tree("expr")?,
+        },
+        _ => return Err(AstImportError::UnknownNode(name)),
+    })
+}
+
+fn span_numbers(span: &TextSpan) -> [usize; 6] {
+    [
+        span.from_line,
+        span.from_col,
+        span.to_line,
+        span.to_col,
+        span.from_offset as usize,
+        span.to_offset as usize,
+    ]
+}
+
+const SPAN_FIELDS: [&str; 6] = [
+    "from_line",
+    "from_col",
+    "to_line",
+    "to_col",
+    "from_offset",
+    "to_offset",
+];
+
+fn make_token(kind: &str, lexeme: &str, numbers: [usize; 6]) -> Result<Token, AstImportError> {
+    let kind = TOKEN_KINDS
+        .into_iter()
+        .find(|k| format!("{k:?}") == kind)
+        .ok_or_else(|| AstImportError::UnknownTokenKind(kind.to_string()))?;
+    let [from_line, from_col, to_line, to_col, from_offset, to_offset] = numbers;
+    let offset = |n: usize| u32::try_from(n).map_err(|_| AstImportError::InvalidToken);
+
+    Ok(Token::new(
+        kind,
+        TextSpan::new(from_line, to_line, from_col, to_col, lexeme)
+            .with_offsets(offset(from_offset)?, offset(to_offset)?),
+    ))
+}
+
+/// A string in double quotes, with the same escapes in both formats.
+fn quote(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => write!(quoted, "\\u{:04x}", c as u32).unwrap(),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn write_json(value: &Value, indent: usize, out: &mut String) {
+    let pad = "  ".repeat(indent + 1);
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Token(token) => {
+            write!(
+                out,
+                "{{\"kind\": \"{:?}\", \"lexeme\": {}, \"span\": {{",
+                token.kind,
+                quote(&token.span.lexeme)
+            )
+            .unwrap();
+            let numbers = span_numbers(&token.span);
+            for (i, (field, n)) in SPAN_FIELDS.iter().zip(numbers).enumerate() {
+                let separator = if i == 0 { "" } else { ", " };
+                write!(out, "{separator}\"{field}\": {n}").unwrap();
+            }
+            out.push_str("}}");
+        }
+        Value::List(items) if items.is_empty() => out.push_str("[]"),
+        Value::List(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                out.push_str(if i == 0 { "\n" } else { ",\n" });
+                out.push_str(&pad);
+                write_json(item, indent + 1, out);
+            }
+            write!(out, "\n{}]", "  ".repeat(indent)).unwrap();
+        }
+        Value::Node(name, fields) => {
+            write!(out, "{{\n{pad}\"node\": {}", quote(name)).unwrap();
+            for (field, value) in fields {
+                write!(out, ",\n{pad}{}: ", quote(field)).unwrap();
+                write_json(value, indent + 1, out);
+            }
+            write!(out, "\n{}}}", "  ".repeat(indent)).unwrap();
+        }
+    }
+}
+
+fn write_sexp(value: &Value, indent: usize, out: &mut String) {
+    let pad = "  ".repeat(indent + 1);
+    match value {
+        Value::Null => out.push_str("nil"),
+        Value::Token(token) => {
+            write!(out, "(token {:?} {}", token.kind, quote(&token.span.lexeme)).unwrap();
+            for n in span_numbers(&token.span) {
+                write!(out, " {n}").unwrap();
+            }
+            out.push(')');
+        }
+        Value::List(items) => {
+            out.push_str("(list");
+            for item in items {
+                write!(out, "\n{pad}").unwrap();
+                write_sexp(item, indent + 1, out);
+            }
+            out.push(')');
+        }
+        Value::Node(name, fields) => {
+            write!(out, "({name}").unwrap();
+            for (field, value) in fields {
+                write!(out, "\n{pad}:{field} ").unwrap();
+                write_sexp(value, indent + 1, out);
+            }
+            out.push(')');
+        }
+    }
+}
+
+/// Writes `value` and everything under it as DOT nodes numbered from
+/// `count`, returning the number of `value`'s node, if it has one.
+fn write_dot(value: &Value, count: &mut usize, dot: &mut String) -> Option<usize> {
+    let id = *count;
+    match value {
+        Value::Null | Value::List(_) => return None,
+        Value::Token(token) => {
+            *count += 1;
+            let label = format!("{:?}\n{}", token.kind, token.span.lexeme);
+            writeln!(dot, "    n{id} [shape=ellipse, label={}];", quote(&label)).unwrap();
+        }
+        Value::Node(name, fields) => {
+            *count += 1;
+            writeln!(dot, "    n{id} [label={}];", quote(name)).unwrap();
+
+            for (field, value) in fields {
+                let children: Vec<(String, &Value)> = match value {
+                    Value::List(items) => items
+                        .iter()
+                        .enumerate()
+                        .map(|(i, item)| (format!("{field}[{i}]"), item))
+                        .collect(),
+                    value => vec![(field.clone(), value)],
+                };
+
+                for (label, child) in children {
+                    if let Some(child) = write_dot(child, count, dot) {
+                        writeln!(dot, "    n{id} -> n{child} [label={}];", quote(&label)).unwrap();
+                    }
+                }
+            }
+        }
+    }
+    Some(id)
+}
+
+enum Json {
+    Null,
+    Number(usize),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+fn json_to_value(json: Json) -> Result<Value, AstImportError> {
+    match json {
+        Json::Null => Ok(Value::Null),
+        Json::Array(items) => Ok(Value::List(
+            items
+                .into_iter()
+                .map(json_to_value)
+                .collect::<Result<_, _>>()?,
+        )),
+        Json::Object(mut members) => {
+            let mut take = |key: &str| {
+                members
+                    .iter()
+                    .position(|(k, _)| k == key)
+                    .map(|i| members.remove(i).1)
+            };
+
+            if let Some(name) = take("node") {
+                let Json::String(name) = name else {
+                    return Err(AstImportError::InvalidValue);
+                };
+                let fields = members
+                    .into_iter()
+                    .map(|(field, value)| Ok((field, json_to_value(value)?)))
+                    .collect::<Result<_, _>>()?;
+                return Ok(Value::Node(name, fields));
+            }
+
+            let (Some(Json::String(kind)), Some(Json::String(lexeme)), Some(Json::Object(span))) =
+                (take("kind"), take("lexeme"), take("span"))
+            else {
+                return Err(AstImportError::InvalidToken);
+            };
+            let mut numbers = [0; 6];
+            for (n, field) in numbers.iter_mut().zip(SPAN_FIELDS) {
+                match span.iter().find(|(k, _)| k == field) {
+                    Some((_, Json::Number(value))) => *n = *value,
+                    _ => return Err(AstImportError::InvalidToken),
+                }
+            }
+
+            Ok(Value::Token(make_token(&kind, &lexeme, numbers)?))
+        }
+        Json::Number(_) | Json::String(_) => Err(AstImportError::InvalidValue),
+    }
+}
+
+enum Sexp {
+    Atom(String),
+    String(String),
+    List(Vec<Sexp>),
+}
+
+fn sexp_to_value(sexp: Sexp) -> Result<Value, AstImportError> {
+    let items = match sexp {
+        Sexp::Atom(atom) if atom == "nil" => return Ok(Value::Null),
+        Sexp::List(items) => items,
+        _ => return Err(AstImportError::InvalidValue),
+    };
+
+    let mut items = items.into_iter();
+    let Some(Sexp::Atom(head)) = items.next() else {
+        return Err(AstImportError::InvalidValue);
+    };
+
+    match head.as_str() {
+        "list" => Ok(Value::List(
+            items.map(sexp_to_value).collect::<Result<_, _>>()?,
+        )),
+        "token" => {
+            let (Some(Sexp::Atom(kind)), Some(Sexp::String(lexeme))) = (items.next(), items.next())
+            else {
+                return Err(AstImportError::InvalidToken);
+            };
+            let mut numbers = [0; 6];
+            for n in &mut numbers {
+                *n = match items.next() {
+                    Some(Sexp::Atom(atom)) => {
+                        atom.parse().map_err(|_| AstImportError::InvalidToken)?
+                    }
+                    _ => return Err(AstImportError::InvalidToken),
+                };
+            }
+            if items.next().is_some() {
+                return Err(AstImportError::InvalidToken);
+            }
+
+            Ok(Value::Token(make_token(&kind, &lexeme, numbers)?))
+        }
+        _ => {
+            let mut fields = vec![];
+            while let Some(field) = items.next() {
+                let (Sexp::Atom(field), Some(value)) = (field, items.next()) else {
+                    return Err(AstImportError::InvalidField(head, String::new()));
+                };
+                let Some(field) = field.strip_prefix(':') else {
+                    return Err(AstImportError::InvalidField(head, field));
+                };
+                fields.push((field.to_string(), sexp_to_value(value)?));
+            }
+
+            Ok(Value::Node(head, fields))
+        }
+    }
+}
+
+/// Reads JSON or S-expressions from a string.
+struct Reader<'a> {
+    input: &'a str,
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(input: &'a str) -> Self {
+        Reader { input, offset: 0 }
+    }
+
+    /// The next character that isn't whitespace, without reading it.
+    fn peek(&mut self) -> Option<char> {
+        let rest = &self.input[self.offset..];
+        self.offset += rest.len() - rest.trim_start().len();
+        self.input[self.offset..].chars().next()
+    }
+
+    fn unexpected(&mut self) -> AstImportError {
+        match self.peek() {
+            Some(c) => AstImportError::UnexpectedCharacter(self.offset, c),
+            None => AstImportError::UnexpectedEnd,
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), AstImportError> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.offset += c.len_utf8();
+                Ok(())
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    fn end(&mut self) -> Result<(), AstImportError> {
+        match self.peek() {
+            Some(_) => Err(self.unexpected()),
+            None => Ok(()),
+        }
+    }
+
+    /// Reads characters up to whitespace or punctuation.
+    fn word(&mut self) -> Result<&'a str, AstImportError> {
+        self.peek();
+        let rest = &self.input[self.offset..];
+        let len = rest
+            .find(|c: char| c.is_whitespace() || "()[]{},:\"".contains(c))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return Err(self.unexpected());
+        }
+
+        self.offset += len;
+        Ok(&rest[..len])
+    }
+
+    fn string(&mut self) -> Result<String, AstImportError> {
+        self.expect('"')?;
+        let mut value = String::new();
+        let mut chars = self.input[self.offset..].char_indices();
+
+        while let Some((i, c)) = chars.next() {
+            let escaped = match c {
+                '"' => {
+                    self.offset += i + 1;
+                    return Ok(value);
+                }
+                '\\' => chars.next().map(|(_, c)| c),
+                c => {
+                    value.push(c);
+                    continue;
+                }
+            };
+
+            value.push(match escaped {
+                Some('"') => '"',
+                Some('\\') => '\\',
+                Some('/') => '/',
+                Some('n') => '\n',
+                Some('r') => '\r',
+                Some('t') => '\t',
+                Some('u') => {
+                    let digits: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+                    match u32::from_str_radix(&digits, 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                    {
+                        Some(c) => c,
+                        None => {
+                            self.offset += i;
+                            return Err(AstImportError::UnexpectedCharacter(self.offset, '\\'));
+                        }
+                    }
+                }
+                Some(_) => {
+                    self.offset += i;
+                    return Err(AstImportError::UnexpectedCharacter(self.offset, '\\'));
+                }
+                None => break,
+            });
+        }
+
+        Err(AstImportError::UnexpectedEnd)
+    }
+
+    fn json(&mut self) -> Result<Json, AstImportError> {
+        match self.peek() {
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some('[') => {
+                self.expect('[')?;
+                let mut items = vec![];
+                if self.peek() == Some(']') {
+                    self.expect(']')?;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.json()?);
+                    match self.peek() {
+                        Some(',') => self.expect(',')?,
+                        Some(']') => {
+                            self.expect(']')?;
+                            return Ok(Json::Array(items));
+                        }
+                        _ => return Err(self.unexpected()),
+                    }
+                }
+            }
+            Some('{') => {
+                self.expect('{')?;
+                let mut members = vec![];
+                if self.peek() == Some('}') {
+                    self.expect('}')?;
+                    return Ok(Json::Object(members));
+                }
+                loop {
+                    let key = self.string()?;
+                    self.expect(':')?;
+                    members.push((key, self.json()?));
+                    match self.peek() {
+                        Some(',') => self.expect(',')?,
+                        Some('}') => {
+                            self.expect('}')?;
+                            return Ok(Json::Object(members));
+                        }
+                        _ => return Err(self.unexpected()),
+                    }
+                }
+            }
+            Some(_) => {
+                let start = self.offset;
+                match self.word()? {
+                    "null" => Ok(Json::Null),
+                    word => word.parse().map(Json::Number).map_err(|_| {
+                        AstImportError::UnexpectedCharacter(start, word.chars().next().unwrap())
+                    }),
+                }
+            }
+            None => Err(AstImportError::UnexpectedEnd),
+        }
+    }
+
+    fn sexp(&mut self) -> Result<Sexp, AstImportError> {
+        match self.peek() {
+            Some('"') => Ok(Sexp::String(self.string()?)),
+            Some('(') => {
+                self.expect('(')?;
+                let mut items = vec![];
+                while self.peek() != Some(')') {
+                    items.push(self.sexp()?);
+                }
+                self.expect(')')?;
+                Ok(Sexp::List(items))
+            }
+            Some(':') => {
+                self.expect(':')?;
+                Ok(Sexp::Atom(format!(":{}", self.word()?)))
+            }
+            Some(_) => Ok(Sexp::Atom(self.word()?.to_string())),
+            None => Err(AstImportError::UnexpectedEnd),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::{core::TokenKind, lexing::Lexer, parsing::Parser, utils::Utf8Buffer};
+
+    use super::*;
+    use assert_matches::assert_matches;
+    use rstest::rstest;
+
+    /// Uses every node the parser produces
+    const SOURCE: &str = r#"
+        struct Point { x: int, y: int }
+        const n = 0x10;
+        fun pair(a: int) -> (int, float) {
+            return (a, a as float);
+        }
+        fun apply(f: fun(int) -> int, x: int) -> int {
+            return f(x);
+        }
+        fun each(g: fun(fun(int) -> int, int)) { g(apply, 1); }
+        let (a, _) = pair(3);
+        let p: Point = Point { x: 1, y: -2 };
+        p.x += 1;
+        p.y++;
+        a *= 2;
+        let c: colour = rgb(1, 2, 3);
+        let t = a > 2 ? "big\n" : "small";
+        match (a) {
+            0..3 => { __print 1; }
+            4 => { __print 2; }
+            _ => { __print 3; }
+        }
+        for (let i: int = 0; i < 10; i++) {
+            __write __randi __width, __read 1, 2, #ff0000;
+        }
+        while (not (a == 0) and true) { a = a - 1; }
+        if (a < 1) { __clear #000000; } else if (a < 2) { __delay 1; } else { __write_box 1, 2, 3, 4, c; }
+        { __print p.x, __height, 1.5e3; }
+        apply(pair, 2);
+    "#;
+
+    fn parse(input: &str) -> AstNode {
+        let tokens = Lexer::<Utf8Buffer>::new(input, None).lex().unwrap();
+        let statements = Parser::new(&tokens, Path::new("test"))
+            .parse_statements(usize::MAX)
+            .unwrap();
+
+        AstNode::Program {
+            statements: statements.into_iter().map(|(node, _)| node).collect(),
+        }
+    }
+
+    fn spans(node: &mut AstNode) -> Vec<(TokenKind, TextSpan)> {
+        let mut spans = vec![];
+        node.for_each_token_mut(&mut |t| spans.push((t.kind, t.span.clone())));
+        spans
+    }
+
+    #[rstest]
+    #[case::json(to_json, from_json)]
+    #[case::sexp(to_sexp, from_sexp)]
+    fn test_round_trip(
+        #[case] write: fn(&AstNode) -> String,
+        #[case] read: fn(&str) -> Result<AstNode, AstImportError>,
+    ) {
+        let mut ast = parse(SOURCE);
+        let text = write(&ast);
+        let mut read_back = read(&text).unwrap();
+
+        assert_eq!(format!("{read_back:?}"), format!("{ast:?}"));
+        assert_eq!(spans(&mut read_back), spans(&mut ast));
+        assert_eq!(write(&read_back), text);
+    }
+
+    #[rstest]
+    fn test_spans_match_source() {
+        let mut ast = parse(SOURCE);
+
+        for (kind, span) in spans(&mut ast) {
+            let source = &SOURCE[span.from_offset as usize..span.to_offset as usize];
+            assert_eq!(
+                source, span.lexeme,
+                "{kind:?} at {}:{}",
+                span.from_line, span.from_col
+            );
+        }
+    }
+
+    #[rstest]
+    fn test_dot() {
+        let ast = parse("__print 1;");
+
+        assert_eq!(
+            to_dot(&ast),
+            r#"digraph ast {
+    node [shape=box];
+    n0 [label="Program"];
+    n1 [label="Print"];
+    n2 [label="Expression"];
+    n3 [label="IntLiteral"];
+    n4 [shape=ellipse, label="IntLiteral\n1"];
+    n3 -> n4 [label="token"];
+    n2 -> n3 [label="expr"];
+    n1 -> n2 [label="expressions[0]"];
+    n0 -> n1 [label="statements[0]"];
+}
+"#
+        );
+    }
+
+    #[rstest]
+    fn test_schema() {
+        let ast = parse("__print \"a\\\"b\";");
+
+        assert_eq!(
+            to_json(&ast),
+            r#"{
+  "node": "Program",
+  "statements": [
+    {
+      "node": "Print",
+      "expressions": [
+        {
+          "node": "Expression",
+          "casted_type": null,
+          "expr": {
+            "node": "StringLiteral",
+            "token": {"kind": "StringLiteral", "lexeme": "\"a\\\"b\"", "span": {"from_line": 1, "from_col": 9, "to_line": 1, "to_col": 15, "from_offset": 8, "to_offset": 14}}
+          }
+        }
+      ]
+    }
+  ]
+}"#
+        );
+        assert_eq!(
+            to_sexp(&ast),
+            r#"(Program
+  :statements (list
+    (Print
+      :expressions (list
+        (Expression
+          :casted_type nil
+          :expr (StringLiteral
+            :token (token StringLiteral "\"a\\\"b\"" 1 9 1 15 8 14)))))))"#
+        );
+    }
+
+    #[rstest]
+    #[case::unknown_node(r#"{"node": "Loop"}"#, AstImportError::UnknownNode("Loop".to_string()))]
+    #[case::missing_field(
+        r#"{"node": "Delay"}"#,
+        AstImportError::MissingField("Delay".to_string(), "expression".to_string())
+    )]
+    #[case::invalid_field(
+        r#"{"node": "Program", "statements": null}"#,
+        AstImportError::InvalidField("Program".to_string(), "statements".to_string())
+    )]
+    #[case::unknown_kind(
+        r#"{"node": "Identifier", "token": {"kind": "Ident", "lexeme": "x", "span": {"from_line": 1, "from_col": 1, "to_line": 1, "to_col": 2, "from_offset": 0, "to_offset": 1}}}"#,
+        AstImportError::UnknownTokenKind("Ident".to_string())
+    )]
+    #[case::invalid_token(
+        r#"{"node": "Identifier", "token": {"kind": "Identifier", "lexeme": "x"}}"#,
+        AstImportError::InvalidToken
+    )]
+    #[case::not_a_node(r#""Program""#, AstImportError::InvalidValue)]
+    #[case::truncated(
+        r#"{"node": "Program", "statements": ["#,
+        AstImportError::UnexpectedEnd
+    )]
+    #[case::trailing(
+        r#"{"node": "PadWidth"} }"#,
+        AstImportError::UnexpectedCharacter(21, '}')
+    )]
+    fn test_json_errors(#[case] input: &str, #[case] error: AstImportError) {
+        assert_matches!(from_json(input), Err(e) => assert_eq!(e, error));
+    }
+
+    #[rstest]
+    #[case::unknown_node("(Loop)", AstImportError::UnknownNode("Loop".to_string()))]
+    #[case::missing_value(
+        "(Delay :expression)",
+        AstImportError::InvalidField("Delay".to_string(), String::new())
+    )]
+    #[case::unknown_kind(
+        r#"(Identifier :token (token Ident "x" 1 1 1 2 0 1))"#,
+        AstImportError::UnknownTokenKind("Ident".to_string())
+    )]
+    #[case::short_token(
+        r#"(Identifier :token (token Identifier "x" 1 1))"#,
+        AstImportError::InvalidToken
+    )]
+    #[case::truncated("(Program :statements (list", AstImportError::UnexpectedEnd)]
+    fn test_sexp_errors(#[case] input: &str, #[case] error: AstImportError) {
+        assert_matches!(from_sexp(input), Err(e) => assert_eq!(e, error));
+    }
+}
@@ -0,0 +1,527 @@
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    core::{TextSpan, Token, TokenKind},
+    lexing::{dfsa::Dfsa, lexer::dfsa_builder, Lexer},
+    utils::{errors::Error, Stream, Utf8Buffer},
+};
+
+use super::{ast::AstNode, Parser};
+
+/// Replaces the bytes of a document in `range` with `text`.
+#[derive(Debug, Clone)]
+pub struct Edit {
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+impl Edit {
+    pub fn new(range: Range<usize>, text: &str) -> Edit {
+        Edit {
+            range,
+            text: text.to_string(),
+        }
+    }
+
+    /// The edit that turns `old` into `new`, which replaces whatever is
+    /// between the longest prefix and suffix they share.
+    pub fn between(old: &str, new: &str) -> Edit {
+        let prefix = old
+            .char_indices()
+            .zip(new.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(old.len().min(new.len()), |((i, _), _)| i);
+        let suffix: usize = old[prefix..]
+            .chars()
+            .rev()
+            .zip(new[prefix..].chars().rev())
+            .take_while(|(a, b)| a == b)
+            .map(|(c, _)| c.len_utf8())
+            .sum();
+
+        Edit::new(prefix..old.len() - suffix, &new[prefix..new.len() - suffix])
+    }
+}
+
+/// The tokens that were lexed again and the top-level statements that were
+/// parsed again after an edit, as indices into the edited document.
+#[derive(Debug, PartialEq)]
+pub struct Reparsed {
+    pub tokens: Range<usize>,
+    pub statements: Range<usize>,
+}
+
+/// How the spans of the tokens after an edit move: by `offset` bytes and
+/// `lines` lines, and by `cols` columns where they're on line `line`.
+struct Shift {
+    offset: i64,
+    lines: isize,
+    line: usize,
+    cols: isize,
+}
+
+impl Shift {
+    fn apply(&self, span: &mut TextSpan) {
+        if span.from_line == self.line {
+            span.from_col = span.from_col.wrapping_add_signed(self.cols);
+        }
+        if span.to_line == self.line {
+            span.to_col = span.to_col.wrapping_add_signed(self.cols);
+        }
+        span.from_line = span.from_line.wrapping_add_signed(self.lines);
+        span.to_line = span.to_line.wrapping_add_signed(self.lines);
+        span.from_offset = (span.from_offset as i64 + self.offset) as u32;
+        span.to_offset = (span.to_offset as i64 + self.offset) as u32;
+    }
+}
+
+/// The line and column of byte `to` in `text`, counting from byte `from` at
+/// `line` and `col` the same way the buffers do.
+fn position(
+    text: &str,
+    from: usize,
+    (mut line, mut col): (usize, usize),
+    to: usize,
+) -> (usize, usize) {
+    let mut rest = &text[from..to];
+    if from == 0 {
+        rest = rest.strip_prefix('\u{feff}').unwrap_or(rest);
+    }
+
+    let mut chars = rest.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                line += 1;
+                col = 1;
+            }
+            _ => col += 1,
+        }
+    }
+
+    (line, col)
+}
+
+/// A source file that's kept lexed and parsed as it's edited. An edit is
+/// re-lexed from the token before it up to the first token after it that
+/// lines up with an old one, and only the top-level statements that overlap
+/// those tokens are parsed again. The rest of the tokens and statements are
+/// kept, with their spans moved.
+pub struct Document {
+    path: PathBuf,
+    text: String,
+    dfsa: Dfsa,
+    tokens: Vec<Token>,
+    /// Always a `Program`
+    program: AstNode,
+    /// The range of tokens each top-level statement was parsed from
+    ranges: Vec<Range<usize>>,
+    /// Set when the text doesn't lex or parse. The tokens and AST are then
+    /// those of an older text, and the next edit parses the whole file.
+    stale: bool,
+}
+
+impl Document {
+    pub fn new(text: &str, path: &Path, dfsa: Option<Dfsa>) -> Result<Document, Vec<Error>> {
+        let mut document = Document {
+            path: path.to_path_buf(),
+            text: text.to_string(),
+            dfsa: dfsa.unwrap_or_else(|| dfsa_builder().minimised().build()),
+            tokens: vec![],
+            program: AstNode::Program { statements: vec![] },
+            ranges: vec![],
+            stale: true,
+        };
+
+        document.parse_all()?;
+        Ok(document)
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    #[cfg(test)]
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    pub fn ast(&self) -> &AstNode {
+        &self.program
+    }
+
+    #[cfg(test)]
+    pub fn is_stale(&self) -> bool {
+        self.stale
+    }
+
+    fn parse_all(&mut self) -> Result<Reparsed, Vec<Error>> {
+        self.stale = true;
+
+        let mut lexer = Lexer::from_stream(Utf8Buffer::new(&self.text), Some(self.dfsa.clone()));
+        let tokens = lexer.lex()?;
+        let statements = Parser::new(&tokens, &self.path)
+            .parse_statements(usize::MAX)
+            .map_err(|e| vec![e])?;
+
+        let (statements, ranges): (Vec<_>, Vec<_>) = statements.into_iter().unzip();
+        let reparsed = Reparsed {
+            tokens: 0..tokens.len(),
+            statements: 0..statements.len(),
+        };
+
+        self.tokens = tokens;
+        self.program = AstNode::Program { statements };
+        self.ranges = ranges;
+        self.stale = false;
+        Ok(reparsed)
+    }
+
+    /// Applies `edit` and brings the tokens and AST up to date. If the new
+    /// text doesn't lex or parse, the errors are those a full parse gives and
+    /// the document is left stale.
+    ///
+    /// # Panics
+    ///
+    /// If the edit's range isn't on character boundaries within the text.
+    pub fn apply(&mut self, edit: &Edit) -> Result<Reparsed, Vec<Error>> {
+        if self.stale {
+            self.text.replace_range(edit.range.clone(), &edit.text);
+            return self.parse_all();
+        }
+
+        // Lexing restarts at the token before the edit, since the edit may
+        // extend it, and at the start of the file if there's none.
+        let first = self
+            .tokens
+            .partition_point(|t| (t.span.to_offset as usize) < edit.range.start);
+        let restart_token = first.saturating_sub(1);
+        let (restart, base) = match first {
+            0 => (0, (1, 1)),
+            _ => {
+                let span = &self.tokens[restart_token].span;
+                (span.from_offset as usize, (span.from_line, span.from_col))
+            }
+        };
+
+        let old_end = position(&self.text, restart, base, edit.range.end);
+        self.text.replace_range(edit.range.clone(), &edit.text);
+        let new_end = position(
+            &self.text,
+            restart,
+            base,
+            edit.range.start + edit.text.len(),
+        );
+
+        let after = Shift {
+            offset: (edit.range.start + edit.text.len()) as i64 - edit.range.end as i64,
+            lines: new_end.0 as isize - old_end.0 as isize,
+            line: old_end.0,
+            cols: new_end.1 as isize - old_end.1 as isize,
+        };
+        let relexed = Shift {
+            offset: restart as i64,
+            lines: base.0 as isize - 1,
+            line: 1,
+            cols: base.1 as isize - 1,
+        };
+
+        // Tokens lex the same as before once one starts where an old token
+        // after the edit did, since the text from there on hasn't changed.
+        let mut next = self
+            .tokens
+            .partition_point(|t| (t.span.from_offset as usize) < edit.range.end);
+        let mut synced = None;
+        let mut lexer = Lexer::from_stream(
+            Utf8Buffer::new(&self.text[restart..]),
+            Some(self.dfsa.clone()),
+        );
+        let lexed = lexer.lex_until(|token| {
+            let start = token.span.from_offset as i64 + relexed.offset;
+            let moved_start = |t: &Token| t.span.from_offset as i64 + after.offset;
+            while self
+                .tokens
+                .get(next)
+                .is_some_and(|t| moved_start(t) < start)
+            {
+                next += 1;
+            }
+
+            match self.tokens.get(next) {
+                Some(t) if t.kind != TokenKind::EndOfFile && moved_start(t) == start => {
+                    synced = Some(next);
+                    true
+                }
+                _ => false,
+            }
+        });
+
+        let Ok(mut lexed) = lexed else {
+            return self.parse_all();
+        };
+
+        let end = synced.unwrap_or(self.tokens.len());
+        let added = lexed.len();
+        lexed.iter_mut().for_each(|t| relexed.apply(&mut t.span));
+        self.tokens[end..]
+            .iter_mut()
+            .for_each(|t| after.apply(&mut t.span));
+        self.tokens.splice(restart_token..end, lexed);
+
+        // Statements are parsed again from the first one that could have
+        // seen a changed token, up to the first old one that starts after
+        // them. Top-level statements follow each other with no gaps.
+        let first_statement = self.ranges.partition_point(|r| r.end < restart_token);
+        let last_statement = self.ranges.partition_point(|r| r.start < end);
+        let start = match self.ranges.get(first_statement) {
+            Some(range) => range.start,
+            None => self.ranges.last().map_or(0, |r| r.end),
+        };
+        let moved = added as isize - (end - restart_token) as isize;
+        let stop = self
+            .ranges
+            .get(last_statement)
+            .map(|r| r.start.wrapping_add_signed(moved));
+
+        // The window includes the first token after it, which the last
+        // statement in it may have looked at.
+        let window = match stop {
+            Some(stop) => &self.tokens[start..=stop],
+            None => &self.tokens[start..],
+        };
+        let parsed = Parser::new(window, &self.path)
+            .parse_statements(stop.map_or(usize::MAX, |stop| stop - start));
+
+        let parsed = match parsed {
+            Ok(parsed)
+                if stop.is_none()
+                    || parsed.last().map_or(0, |(_, r)| r.end) == stop.unwrap() - start =>
+            {
+                parsed
+            }
+            _ => return self.parse_all(),
+        };
+
+        let AstNode::Program { statements } = &mut self.program else {
+            unreachable!("a document's AST is always a program");
+        };
+
+        for statement in &mut statements[last_statement..] {
+            statement.for_each_token_mut(&mut |t| after.apply(&mut t.span));
+        }
+        for range in &mut self.ranges[last_statement..] {
+            *range = range.start.wrapping_add_signed(moved)..range.end.wrapping_add_signed(moved);
+        }
+
+        let count = parsed.len();
+        let (parsed, ranges): (Vec<_>, Vec<_>) = parsed
+            .into_iter()
+            .map(|(node, r)| (node, r.start + start..r.end + start))
+            .unzip();
+        statements.splice(first_statement..last_statement, parsed);
+        self.ranges.splice(first_statement..last_statement, ranges);
+
+        Ok(Reparsed {
+            tokens: restart_token..restart_token + added,
+            statements: first_statement..first_statement + count,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::OnceLock;
+
+    use super::*;
+    use assert_matches::assert_matches;
+    use rstest::rstest;
+
+    const SOURCE: &str = "/* Moves a pixel */
+fun Step(x: int, dx: int) -> int {
+    let next: int = x + dx; // may wrap
+    if (next > __width) { next = 0; }
+    return next;
+}
+
+let x: int = 0;
+while (x < 10) {
+    x = Step(x, 1);
+    __write x, 0, #ff0000;
+}
+__print \"done\";
+";
+
+    fn parse(text: &str) -> Result<Document, Vec<Error>> {
+        static DFSA: OnceLock<Dfsa> = OnceLock::new();
+        let dfsa = DFSA.get_or_init(|| dfsa_builder().minimised().build());
+        Document::new(text, Path::new("test"), Some(dfsa.clone()))
+    }
+
+    fn spans(document: &mut Document) -> Vec<TextSpan> {
+        let mut spans = vec![];
+        document
+            .program
+            .for_each_token_mut(&mut |t| spans.push(t.span.clone()));
+        spans
+    }
+
+    /// Checks that `document` has the same tokens and AST as parsing its text
+    /// from scratch.
+    fn assert_matches_full_parse(document: &mut Document) {
+        let mut full = parse(document.text()).unwrap();
+
+        let tokens = |d: &Document| {
+            d.tokens()
+                .iter()
+                .map(|t| (t.kind, t.span.clone()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(tokens(document), tokens(&full));
+        assert_eq!(format!("{:?}", document.ast()), format!("{:?}", full.ast()));
+        assert_eq!(spans(document), spans(&mut full));
+        assert_eq!(document.ranges, full.ranges);
+    }
+
+    #[rstest]
+    #[case::rename("x: int, dx", "xs: int, dx")]
+    #[case::extend_identifier("let next", "let nextx")]
+    #[case::join_tokens("x + dx", "x +dx")]
+    #[case::new_line("let x: int = 0;", "let x: int =\n    0;")]
+    #[case::remove_line("    return next;\n", "")]
+    #[case::open_comment("// may wrap", "/* may wrap */")]
+    #[case::comment_out_statement("let x: int = 0;", "// let x: int = 0;\nlet x: int = 1;")]
+    #[case::split_statement("__write x, 0, #ff0000;", "__write x, 0, #ff0000; __delay 5;")]
+    #[case::merge_functions("}\n\nlet x", "    let y: int = 2;\n}\n\nlet x")]
+    #[case::colour("#ff0000", "#00ff00")]
+    #[case::end_of_file("__print \"done\";\n", "__print \"done\", x;")]
+    #[case::start_of_file("/* Moves", "// Moves\n/* A")]
+    fn test_edit_matches_full_parse(#[case] old: &str, #[case] new: &str) {
+        let mut document = parse(SOURCE).unwrap();
+        let start = SOURCE.find(old).unwrap();
+
+        let reparsed = document.apply(&Edit::new(start..start + old.len(), new));
+
+        assert_matches!(reparsed, Ok(_));
+        assert!(!document.is_stale());
+        assert_eq!(document.text(), SOURCE.replacen(old, new, 1));
+        assert_matches_full_parse(&mut document);
+    }
+
+    #[rstest]
+    fn test_edit_reparses_enclosing_statement() {
+        let mut document = parse(SOURCE).unwrap();
+        let start = SOURCE.find("x < 10").unwrap() + 4;
+
+        let reparsed = document.apply(&Edit::new(start..start + 2, "20")).unwrap();
+
+        // Only the `while` loop is parsed again, and only `<` and `20` are
+        // lexed again
+        assert_eq!(reparsed.statements, 2..3);
+        assert_eq!(reparsed.tokens.len(), 2);
+        assert_matches_full_parse(&mut document);
+    }
+
+    #[rstest]
+    fn test_typing_matches_full_parse() {
+        let mut document = parse("").unwrap();
+
+        // Every prefix of the source, most of which don't parse, then every
+        // suffix as it's deleted from the front
+        for (i, c) in SOURCE.char_indices() {
+            let result = document.apply(&Edit::new(i..i, &c.to_string()));
+            let full = parse(document.text());
+
+            assert_eq!(result.is_ok(), full.is_ok());
+            if result.is_ok() {
+                assert_matches_full_parse(&mut document);
+            }
+        }
+
+        while !document.text().is_empty() {
+            let len = document.text().chars().next().unwrap().len_utf8();
+            let result = document.apply(&Edit::new(0..len, ""));
+            let full = parse(document.text());
+
+            assert_eq!(result.is_ok(), full.is_ok());
+            if result.is_ok() {
+                assert_matches_full_parse(&mut document);
+            }
+        }
+    }
+
+    #[rstest]
+    fn test_insert_anywhere_matches_full_parse(
+        #[values(" ", "\n", "\r\n", "/**/", "1")] text: &str,
+    ) {
+        for i in (0..=SOURCE.len()).filter(|i| SOURCE.is_char_boundary(*i)) {
+            let mut document = parse(SOURCE).unwrap();
+            let result = document.apply(&Edit::new(i..i, text));
+            let full = parse(document.text());
+
+            assert_eq!(result.is_ok(), full.is_ok());
+            if result.is_ok() {
+                assert_matches_full_parse(&mut document);
+            }
+        }
+    }
+
+    #[rstest]
+    fn test_stale_document_recovers() {
+        let mut document = parse(SOURCE).unwrap();
+        let start = SOURCE.find("let x").unwrap();
+
+        let result = document.apply(&Edit::new(start..start + 3, "lte"));
+        assert_matches!(result, Err(errors) => assert_eq!(errors.len(), 1));
+        assert!(document.is_stale());
+
+        let result = document.apply(&Edit::new(start..start + 3, "let"));
+        assert_matches!(result, Ok(Reparsed { statements, .. }) => assert_eq!(statements.len(), 4));
+        assert!(!document.is_stale());
+        assert_matches_full_parse(&mut document);
+    }
+
+    #[rstest]
+    #[case::insert("abc", "abXc", 2..2, "X")]
+    #[case::delete("aaa", "aa", 2..3, "")]
+    #[case::replace("let x = 1;", "let y = 2;", 4..9, "y = 2")]
+    #[case::unicode("é", "è", 0..2, "è")]
+    #[case::same("abc", "abc", 3..3, "")]
+    fn test_edit_between(
+        #[case] old: &str,
+        #[case] new: &str,
+        #[case] range: Range<usize>,
+        #[case] text: &str,
+    ) {
+        let edit = Edit::between(old, new);
+
+        assert_eq!((edit.range.clone(), edit.text.as_str()), (range, text));
+        let mut applied = old.to_string();
+        applied.replace_range(edit.range, &edit.text);
+        assert_eq!(applied, new);
+    }
+
+    #[rstest]
+    fn test_function_types_match_full_parse(#[values(" ", "\n", "1")] text: &str) {
+        let source = "let a: int = 1;
+fun apply(f: fun(int) -> int, g: fun(int, fun(int))) -> int {
+    return f(a);
+}
+let b: fun(int) -> int = apply;
+";
+
+        for i in (0..=source.len()).filter(|i| source.is_char_boundary(*i)) {
+            let mut document = parse(source).unwrap();
+            let result = document.apply(&Edit::new(i..i, text));
+            let full = parse(document.text());
+
+            assert_eq!(result.is_ok(), full.is_ok());
+            if result.is_ok() {
+                assert_matches_full_parse(&mut document);
+            }
+        }
+    }
+}
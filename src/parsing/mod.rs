@@ -1,5 +1,7 @@
 #[allow(dead_code, unused_variables)]
 pub mod ast;
+pub mod export;
+pub mod incremental;
 pub mod parser;
 
 pub use parser::Parser;
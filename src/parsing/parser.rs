@@ -1,4 +1,7 @@
-use std::path::{Path, PathBuf};
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+};
 
 use crate::{
     core::{TextSpan, Token, TokenKind},
@@ -8,7 +11,7 @@ use crate::{
     },
 };
 
-use super::ast::{Ast, AstNode};
+use super::ast::{Ast, AstNode, TypeAnnotation};
 
 pub struct Parser {
     tokens: Vec<Token>,
@@ -94,23 +97,37 @@ impl Parser {
         Ok(&self.root)
     }
 
-    fn parse_program(&mut self) -> Result<AstNode> {
+    /// Like [`Parser::parse`], but the caller owns the tree, e.g. to rewrite it
+    /// before analysis.
+    pub fn parse_program(&mut self) -> Result<AstNode> {
+        let statements = self.parse_statements(usize::MAX)?;
+        Ok(AstNode::Program {
+            statements: statements.into_iter().map(|(node, _)| node).collect(),
+        })
+    }
+
+    /// Parses top-level statements until the next one would start at or past
+    /// the token at `stop`, or until the end of the file. Each statement comes
+    /// with the range of tokens it was parsed from.
+    pub fn parse_statements(&mut self, stop: usize) -> Result<Vec<(AstNode, Range<usize>)>> {
         let mut statements = vec![];
-        while self.current < self.tokens.len() {
+        while self.current < self.tokens.len() && self.current < stop {
+            let start = self.current;
             let next_statement = self.parse_statement()?;
 
             if let AstNode::EndOfFile = next_statement {
                 break;
             }
 
-            statements.push(next_statement);
+            statements.push((next_statement, start..self.current));
         }
-        Ok(AstNode::Program { statements })
+        Ok(statements)
     }
 
     fn parse_statement(&mut self) -> Result<AstNode> {
         self.assert_token_is_any([
             TokenKind::Let,
+            TokenKind::Const,
             TokenKind::Print,
             TokenKind::Delay,
             TokenKind::PadWrite,
@@ -120,21 +137,33 @@ impl Parser {
             TokenKind::Identifier,
             TokenKind::For,
             TokenKind::While,
+            TokenKind::Match,
             TokenKind::Function,
             TokenKind::Return,
+            TokenKind::Struct,
             TokenKind::LBrace,
             TokenKind::EndOfFile,
         ])?;
 
         match self.current_token_kind() {
             TokenKind::Let => self.parse_var_decl(),
+            TokenKind::Const => self.parse_const_decl(),
             TokenKind::Identifier => match self.peek_token() {
                 Some(tok) => match tok.kind {
-                    TokenKind::Equals => {
+                    TokenKind::Equals
+                    | TokenKind::Dot
+                    | TokenKind::PlusEquals
+                    | TokenKind::MinusEquals
+                    | TokenKind::MultiplyEquals
+                    | TokenKind::DivideEquals
+                    | TokenKind::ModEquals
+                    | TokenKind::PlusPlus
+                    | TokenKind::MinusMinus => {
                         let a = self.parse_assignment_statement();
                         self.consume_if(TokenKind::Semicolon)?;
                         a
                     }
+                    TokenKind::LParen => self.parse_call_statement(),
                     _ => self.parse_identifier(),
                 },
                 None => self.parse_identifier(),
@@ -147,8 +176,10 @@ impl Parser {
             TokenKind::If => self.parse_if(),
             TokenKind::For => self.parse_for(),
             TokenKind::While => self.parse_while(),
+            TokenKind::Match => self.parse_match(),
             TokenKind::Function => self.parse_function_decl(),
             TokenKind::Return => self.parse_return(),
+            TokenKind::Struct => self.parse_struct_decl(),
             TokenKind::LBrace => self.parse_block(),
             TokenKind::EndOfFile => Ok(AstNode::EndOfFile),
             _ => Err(Error::Parse(ParseError::UnexpectedToken {
@@ -171,9 +202,14 @@ impl Parser {
 
         self.consume_if(TokenKind::RParen)?;
 
-        self.consume_if(TokenKind::Arrow)?;
-
-        let return_type = self.consume_if(TokenKind::Type)?.clone();
+        // An omitted return type is the same as `-> void`
+        let return_type = match self.current_token().kind {
+            TokenKind::Arrow => {
+                self.consume();
+                self.parse_return_type()?
+            }
+            _ => vec![],
+        };
 
         // TODO: Add array like function array_list() -> int[] {}
         let block = self.parse_block()?;
@@ -181,11 +217,117 @@ impl Parser {
         Ok(AstNode::FunctionDecl {
             identifier: identifier.clone(),
             params,
-            return_type: return_type.clone(),
+            return_type,
             block: Box::new(block),
         })
     }
 
+    /// A single type, or a parenthesized list of the types in a tuple.
+    fn parse_return_type(&mut self) -> Result<Vec<TypeAnnotation>> {
+        if self.current_token().kind != TokenKind::LParen {
+            return Ok(vec![self.parse_type()?]);
+        }
+
+        self.consume_if(TokenKind::LParen)?;
+        let mut types = vec![self.parse_type()?];
+        while self.current_token().kind == TokenKind::Comma {
+            self.consume();
+            types.push(self.parse_type()?);
+        }
+        self.consume_if(TokenKind::RParen)?;
+
+        Ok(types)
+    }
+
+    /// A built-in type, the name of a struct or a function type.
+    fn parse_type(&mut self) -> Result<TypeAnnotation> {
+        if self.current_token().kind == TokenKind::Function {
+            return self.parse_function_type();
+        }
+
+        self.assert_token_is_any([TokenKind::Type, TokenKind::Identifier])?;
+        Ok(TypeAnnotation::Named(self.consume().clone()))
+    }
+
+    /// Parses `fun(int, int) -> colour`, where the return type may be left
+    /// out.
+    fn parse_function_type(&mut self) -> Result<TypeAnnotation> {
+        let function = self.consume_if(TokenKind::Function)?.clone();
+        self.consume_if(TokenKind::LParen)?;
+
+        let mut params = vec![];
+        while self.current_token().kind != TokenKind::RParen {
+            params.push(self.parse_type()?);
+
+            if self.current_token().kind != TokenKind::RParen {
+                self.consume_if(TokenKind::Comma)?;
+            }
+        }
+        self.consume_if(TokenKind::RParen)?;
+
+        let return_type = match self.current_token().kind {
+            TokenKind::Arrow => {
+                self.consume();
+                Some(Box::new(self.parse_type()?))
+            }
+            _ => None,
+        };
+
+        Ok(TypeAnnotation::Function {
+            function,
+            params,
+            return_type,
+        })
+    }
+
+    fn parse_struct_decl(&mut self) -> Result<AstNode> {
+        self.consume_if(TokenKind::Struct)?;
+        let identifier = self.consume_if(TokenKind::Identifier)?.clone();
+        self.consume_if(TokenKind::LBrace)?;
+
+        let mut fields = vec![];
+        while self.current_token().kind != TokenKind::RBrace {
+            fields.push(self.parse_formal_param()?);
+
+            if self.current_token().kind != TokenKind::RBrace {
+                self.consume_if(TokenKind::Comma)?;
+            }
+        }
+        self.consume_if(TokenKind::RBrace)?;
+
+        Ok(AstNode::StructDecl { identifier, fields })
+    }
+
+    fn parse_struct_literal(&mut self, identifier: Token) -> Result<AstNode> {
+        self.consume_if(TokenKind::LBrace)?;
+
+        let mut fields = vec![];
+        while self.current_token().kind != TokenKind::RBrace {
+            let field = self.consume_if(TokenKind::Identifier)?.clone();
+            self.consume_if(TokenKind::Colon)?;
+            let value = self.parse_expression()?;
+            fields.push((field, Box::new(value)));
+
+            if self.current_token().kind != TokenKind::RBrace {
+                self.consume_if(TokenKind::Comma)?;
+            }
+        }
+        self.consume_if(TokenKind::RBrace)?;
+
+        Ok(AstNode::StructLiteral { identifier, fields })
+    }
+
+    /// Parses `.a.b.c` following a variable name.
+    fn parse_field_path(&mut self) -> Result<Vec<Token>> {
+        let mut fields = vec![];
+        while self.current_token().kind == TokenKind::Dot {
+            self.consume();
+            fields.push(self.consume_if(TokenKind::Identifier)?.clone());
+        }
+
+        Ok(fields)
+    }
+
     fn parse_formal_params(&mut self) -> Result<Vec<AstNode>> {
         let mut params = vec![];
 
@@ -203,7 +345,7 @@ impl Parser {
     fn parse_formal_param(&mut self) -> Result<AstNode> {
         let identifier = self.consume_if(TokenKind::Identifier)?.clone();
         self.consume_if(TokenKind::Colon)?;
-        let param_type = self.consume_if(TokenKind::Type)?.clone();
+        let param_type = self.parse_type()?;
         Ok(AstNode::FormalParam {
             identifier: identifier.clone(),
             param_type,
@@ -280,7 +422,13 @@ impl Parser {
 
         if self.current_token().kind == TokenKind::Else {
             self.consume();
-            let else_block = self.parse_block()?;
+            // `else if` is sugar for an else block holding a single if statement
+            let else_block = match self.current_token().kind {
+                TokenKind::If => AstNode::Block {
+                    statements: vec![self.parse_if()?],
+                },
+                _ => self.parse_block()?,
+            };
             return Ok(AstNode::If {
                 condition: Box::new(condition),
                 if_true: Box::new(block),
@@ -295,6 +443,68 @@ impl Parser {
         })
     }
 
+    fn parse_match(&mut self) -> Result<AstNode> {
+        self.consume_if(TokenKind::Match)?;
+        self.consume_if(TokenKind::LParen)?;
+        let expression = self.parse_expression()?;
+        self.consume_if(TokenKind::RParen)?;
+        self.consume_if(TokenKind::LBrace)?;
+
+        let mut arms = vec![];
+        let mut default = None;
+
+        while self.current_token().kind != TokenKind::RBrace {
+            match self.current_token().kind {
+                TokenKind::EndOfFile => return Err(ParseError::UnclosedBlock.into()),
+                TokenKind::Underscore => {
+                    self.consume();
+                    self.consume_if(TokenKind::FatArrow)?;
+                    default = Some(Box::new(self.parse_block()?));
+                    // The default arm must be the last one
+                    self.assert_token_is(TokenKind::RBrace)?;
+                }
+                _ => arms.push(self.parse_match_arm()?),
+            }
+        }
+
+        self.consume_if(TokenKind::RBrace)?;
+
+        Ok(AstNode::Match {
+            expression: Box::new(expression),
+            arms,
+            default,
+        })
+    }
+
+    fn parse_match_arm(&mut self) -> Result<AstNode> {
+        let pattern_kinds = [
+            TokenKind::IntLiteral,
+            TokenKind::ColourLiteral,
+            TokenKind::BoolLiteral,
+        ];
+
+        self.assert_token_is_any(pattern_kinds)?;
+        let from = self.consume().clone();
+
+        let to = match self.current_token().kind {
+            TokenKind::DotDot => {
+                self.consume();
+                self.assert_token_is_any(pattern_kinds)?;
+                Some(self.consume().clone())
+            }
+            _ => None,
+        };
+
+        self.consume_if(TokenKind::FatArrow)?;
+        let body = self.parse_block()?;
+
+        Ok(AstNode::MatchArm {
+            from,
+            to,
+            body: Box::new(body),
+        })
+    }
+
     fn parse_write_box(&mut self) -> Result<AstNode> {
         self.consume_if(TokenKind::PadWriteBox)?;
         let loc_x = self.parse_expression()?;
@@ -355,16 +565,40 @@ impl Parser {
     fn parse_return(&mut self) -> Result<AstNode> {
         self.consume_if(TokenKind::Return)?;
 
-        let expr = self.parse_expression()?;
+        let expression = match self.current_token().kind {
+            TokenKind::Semicolon => None,
+            _ => Some(Box::new(self.parse_expression()?)),
+        };
 
         self.consume_if(TokenKind::Semicolon)?;
 
-        Ok(AstNode::Return {
-            expression: Box::new(expr),
-        })
+        Ok(AstNode::Return { expression })
     }
 
     fn parse_expression(&mut self) -> Result<AstNode> {
+        let condition = self.parse_logical()?;
+
+        if self.current_token().kind != TokenKind::Question {
+            return Ok(condition);
+        }
+
+        // Right associative, so `a ? b : c ? d : e` is `a ? b : (c ? d : e)`
+        self.consume();
+        let if_true = self.parse_expression()?;
+        self.consume_if(TokenKind::Colon)?;
+        let if_false = self.parse_expression()?;
+
+        Ok(AstNode::Expression {
+            casted_type: None,
+            expr: Box::new(AstNode::Conditional {
+                condition: Box::new(condition),
+                if_true: Box::new(if_true),
+                if_false: Box::new(if_false),
+            }),
+        })
+    }
+
+    fn parse_logical(&mut self) -> Result<AstNode> {
         let left = self.parse_equality()?;
 
         match self.current_token().kind {
@@ -379,7 +613,7 @@ impl Parser {
 
             TokenKind::And | TokenKind::Or => {
                 let operator = self.consume().clone();
-                let right = self.parse_expression()?;
+                let right = self.parse_logical()?;
 
                 let bin_op = AstNode::BinOp {
                     left: Box::new(left),
@@ -498,10 +732,12 @@ impl Parser {
             TokenKind::FloatLiteral,
             TokenKind::BoolLiteral,
             TokenKind::ColourLiteral,
+            TokenKind::StringLiteral,
             TokenKind::PadHeight,
             TokenKind::PadWidth,
             TokenKind::PadRead,
             TokenKind::PadRandI,
+            TokenKind::Rgb,
             TokenKind::LParen,
         ])?;
 
@@ -525,6 +761,13 @@ impl Parser {
                             args,
                         })
                     }
+                } else if self.current_token().kind == TokenKind::LBrace {
+                    self.parse_struct_literal(ident)
+                } else if self.current_token().kind == TokenKind::Dot {
+                    Ok(AstNode::FieldAccess {
+                        identifier: ident,
+                        fields: self.parse_field_path()?,
+                    })
                 } else {
                     Ok(AstNode::Identifier {
                         token: ident.clone(),
@@ -535,19 +778,43 @@ impl Parser {
             TokenKind::IntLiteral
             | TokenKind::FloatLiteral
             | TokenKind::BoolLiteral
-            | TokenKind::ColourLiteral => self.parse_literal(),
+            | TokenKind::ColourLiteral
+            | TokenKind::StringLiteral => self.parse_literal(),
             TokenKind::PadHeight => self.parse_pad_height(),
             TokenKind::PadWidth => self.parse_pad_width(),
             TokenKind::PadRead => self.parse_pad_read(),
             TokenKind::PadRandI => self.parse_pad_rand_i(),
+            TokenKind::Rgb => self.parse_rgb(),
             TokenKind::LParen => self.parse_sub_expr(),
             _ => unreachable!(),
         }
     }
 
+    fn parse_call_statement(&mut self) -> Result<AstNode> {
+        let call = self.parse_primary()?;
+        self.consume_if(TokenKind::Semicolon)?;
+
+        Ok(AstNode::CallStatement {
+            call: Box::new(call),
+        })
+    }
+
     fn parse_sub_expr(&mut self) -> Result<AstNode> {
         self.consume_if(TokenKind::LParen)?;
         let expr = self.parse_expression()?;
+
+        // A comma makes it a tuple instead, e.g. `(x, y)`
+        if self.current_token().kind == TokenKind::Comma {
+            let mut elements = vec![expr];
+            while self.current_token().kind == TokenKind::Comma {
+                self.consume();
+                elements.push(self.parse_expression()?);
+            }
+            self.consume_if(TokenKind::RParen)?;
+
+            return Ok(AstNode::Tuple { elements });
+        }
+
         self.consume_if(TokenKind::RParen)?;
         Ok(AstNode::SubExpression {
             bin_op: Box::new(expr),
@@ -599,12 +866,32 @@ impl Parser {
         })
     }
 
+    fn parse_rgb(&mut self) -> Result<AstNode> {
+        self.consume_if(TokenKind::Rgb)?;
+        self.consume_if(TokenKind::LParen)?;
+        let red = self.parse_expression()?;
+        self.consume_if(TokenKind::Comma)?;
+        let green = self.parse_expression()?;
+        self.consume_if(TokenKind::Comma)?;
+        let blue = self.parse_expression()?;
+        self.consume_if(TokenKind::RParen)?;
+
+        Ok(AstNode::Rgb {
+            red: Box::new(red),
+            green: Box::new(green),
+            blue: Box::new(blue),
+        })
+    }
+
     // TODO: Add array functionality
     fn parse_var_decl(&mut self) -> Result<AstNode> {
         self.consume_if(TokenKind::Let)?;
+        if self.current_token().kind == TokenKind::LParen {
+            return self.parse_tuple_decl();
+        }
+
         let identifier = self.consume_if(TokenKind::Identifier)?.clone();
-        self.consume_if(TokenKind::Colon)?;
-        let kind = self.consume_if(TokenKind::Type)?.clone();
+        let kind = self.parse_optional_type()?;
         self.consume_if(TokenKind::Equals)?;
         let expression = self.parse_expression()?;
         self.consume_if(TokenKind::Semicolon)?;
@@ -615,15 +902,65 @@ impl Parser {
         })
     }
 
-    fn parse_print_statement(&mut self) -> Result<AstNode> {
-        self.consume_if(TokenKind::Print)?;
+    /// Parses the `(a, b) = expression;` after the `let` of a destructuring
+    /// declaration.
+    fn parse_tuple_decl(&mut self) -> Result<AstNode> {
+        self.consume_if(TokenKind::LParen)?;
+
+        let mut identifiers = vec![];
+        loop {
+            self.assert_token_is_any([TokenKind::Identifier, TokenKind::Underscore])?;
+            identifiers.push(self.consume().clone());
+
+            if self.current_token().kind == TokenKind::RParen {
+                break;
+            }
+            self.consume_if(TokenKind::Comma)?;
+        }
+
+        self.consume_if(TokenKind::RParen)?;
+        self.consume_if(TokenKind::Equals)?;
         let expression = self.parse_expression()?;
         self.consume_if(TokenKind::Semicolon)?;
-        Ok(AstNode::Print {
+
+        Ok(AstNode::TupleDec {
+            identifiers,
             expression: Box::new(expression),
         })
     }
 
+    /// Parses the `: type` of a declaration, which may be left out to have
+    /// the type inferred.
+    fn parse_optional_type(&mut self) -> Result<Option<TypeAnnotation>> {
+        if self.current_token().kind != TokenKind::Colon {
+            return Ok(None);
+        }
+
+        self.consume_if(TokenKind::Colon)?;
+        Ok(Some(self.parse_type()?))
+    }
+
+    fn parse_const_decl(&mut self) -> Result<AstNode> {
+        self.consume_if(TokenKind::Const)?;
+        let identifier = self.consume_if(TokenKind::Identifier)?.clone();
+        let kind = self.parse_optional_type()?;
+        self.consume_if(TokenKind::Equals)?;
+        let expression = self.parse_expression()?;
+        self.consume_if(TokenKind::Semicolon)?;
+        Ok(AstNode::ConstDec {
+            identifier,
+            r#type: kind,
+            expression: Box::new(expression),
+        })
+    }
+
+    fn parse_print_statement(&mut self) -> Result<AstNode> {
+        self.consume_if(TokenKind::Print)?;
+        let expressions = self.parse_actual_params()?;
+        self.consume_if(TokenKind::Semicolon)?;
+        Ok(AstNode::Print { expressions })
+    }
+
     fn parse_block(&mut self) -> Result<AstNode> {
         let mut statements = vec![];
         self.consume_if(TokenKind::LBrace)?;
@@ -650,6 +987,7 @@ impl Parser {
             TokenKind::FloatLiteral => Ok(AstNode::FloatLiteral(token)),
             TokenKind::BoolLiteral => Ok(AstNode::BoolLiteral(token)),
             TokenKind::ColourLiteral => Ok(AstNode::ColourLiteral(token)),
+            TokenKind::StringLiteral => Ok(AstNode::StringLiteral(token)),
             TokenKind::PadHeight => Ok(AstNode::PadHeight),
             TokenKind::PadWidth => Ok(AstNode::PadWidth),
             TokenKind::PadRead => self.parse_pad_read(),
@@ -664,12 +1002,55 @@ impl Parser {
 
     fn parse_assignment_statement(&mut self) -> Result<AstNode> {
         let identifier = self.consume_if(TokenKind::Identifier)?.clone();
-        self.consume_if(TokenKind::Equals)?;
-        let expression = self.parse_expression()?;
-        Ok(AstNode::Assignment {
-            identifier: identifier.clone(),
-            expression: Box::new(expression),
-        })
+
+        let fields = match self.current_token().kind {
+            TokenKind::Dot => self.parse_field_path()?,
+            _ => vec![],
+        };
+
+        self.assert_token_is_any([
+            TokenKind::Equals,
+            TokenKind::PlusEquals,
+            TokenKind::MinusEquals,
+            TokenKind::MultiplyEquals,
+            TokenKind::DivideEquals,
+            TokenKind::ModEquals,
+            TokenKind::PlusPlus,
+            TokenKind::MinusMinus,
+        ])?;
+
+        let operator = self.consume().clone();
+
+        if !fields.is_empty() {
+            let expression = match operator.kind {
+                TokenKind::PlusPlus | TokenKind::MinusMinus => None,
+                _ => Some(Box::new(self.parse_expression()?)),
+            };
+
+            return Ok(AstNode::FieldAssignment {
+                identifier,
+                fields,
+                operator,
+                expression,
+            });
+        }
+
+        match operator.kind {
+            TokenKind::Equals => Ok(AstNode::Assignment {
+                identifier,
+                expression: Box::new(self.parse_expression()?),
+            }),
+            TokenKind::PlusPlus | TokenKind::MinusMinus => Ok(AstNode::CompoundAssignment {
+                identifier,
+                operator,
+                expression: None,
+            }),
+            _ => Ok(AstNode::CompoundAssignment {
+                identifier,
+                operator,
+                expression: Some(Box::new(self.parse_expression()?)),
+            }),
+        }
     }
 
     fn parse_clear_statement(&mut self) -> Result<AstNode> {
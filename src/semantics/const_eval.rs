@@ -0,0 +1,224 @@
+use crate::core::{Token, TokenKind};
+use crate::parsing::ast::AstNode;
+use crate::semantics::utils::channel_scale;
+
+/// The value of an expression that was folded at compile time.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ConstValue {
+    Int(i64),
+    Float(f32),
+    Bool(bool),
+    Colour(u32),
+}
+
+/// Why an expression couldn't be folded.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ConstError {
+    /// The expression depends on something only known at runtime
+    NotConstant,
+    DivisionByZero,
+    Overflow,
+}
+
+use ConstError::*;
+
+impl ConstValue {
+    fn as_float(&self) -> Option<f32> {
+        match self {
+            ConstValue::Int(value) => Some(*value as f32),
+            ConstValue::Float(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+/// Folds `node` into a single value, or says why it can't be. Identifiers are
+/// resolved through `lookup`, which should only know about the constants in
+/// scope.
+pub fn evaluate(
+    node: &AstNode,
+    lookup: &dyn Fn(&Token) -> Option<ConstValue>,
+) -> Result<ConstValue, ConstError> {
+    match node {
+        AstNode::Expression { casted_type, expr } => {
+            let value = evaluate(expr, lookup)?;
+
+            match casted_type {
+                Some(casted_type) => cast(value, &casted_type.span.lexeme).ok_or(NotConstant),
+                None => Ok(value),
+            }
+        }
+
+        AstNode::SubExpression { bin_op } => evaluate(bin_op, lookup),
+
+        AstNode::BinOp {
+            left,
+            operator,
+            right,
+        } => {
+            let left = evaluate(left, lookup)?;
+            let right = evaluate(right, lookup)?;
+
+            fold_bin_op(operator.kind, left, right)
+        }
+
+        AstNode::Conditional {
+            condition,
+            if_true,
+            if_false,
+        } => match evaluate(condition, lookup)? {
+            ConstValue::Bool(true) => evaluate(if_true, lookup),
+            ConstValue::Bool(false) => evaluate(if_false, lookup),
+            _ => Err(NotConstant),
+        },
+
+        AstNode::UnaryOp { operator, expr } => match (operator.kind, evaluate(expr, lookup)?) {
+            (TokenKind::Minus, ConstValue::Int(value)) => {
+                Ok(ConstValue::Int(value.checked_neg().ok_or(Overflow)?))
+            }
+            (TokenKind::Minus, ConstValue::Float(value)) => Ok(ConstValue::Float(-value)),
+            (TokenKind::Not, ConstValue::Bool(value)) => Ok(ConstValue::Bool(!value)),
+            _ => Err(NotConstant),
+        },
+
+        AstNode::Rgb { red, green, blue } => {
+            let mut colour = 0;
+            for (channel, scale) in [(red, 0x10000), (green, 0x100), (blue, 1)] {
+                match evaluate(channel, lookup)? {
+                    ConstValue::Int(value) => colour += value.clamp(0, 255) as u32 * scale,
+                    _ => return Err(NotConstant),
+                }
+            }
+
+            Ok(ConstValue::Colour(colour))
+        }
+
+        AstNode::FieldAccess { identifier, fields } => {
+            match (lookup(identifier).ok_or(NotConstant)?, &fields[..]) {
+                (ConstValue::Colour(colour), [channel]) => {
+                    let scale = channel_scale(&channel.span.lexeme).ok_or(NotConstant)?;
+                    Ok(ConstValue::Int((colour / scale % 256) as i64))
+                }
+                _ => Err(NotConstant),
+            }
+        }
+
+        AstNode::Identifier { token } => lookup(token).ok_or(NotConstant),
+
+        // Literals too big for their type overflow
+        AstNode::IntLiteral(l) => l.int_value().map(ConstValue::Int).ok_or(Overflow),
+        AstNode::FloatLiteral(l) => l.float_value().map(ConstValue::Float).ok_or(Overflow),
+        AstNode::BoolLiteral(l) => Ok(ConstValue::Bool(l.span.lexeme == "true")),
+        AstNode::ColourLiteral(l) => u32::from_str_radix(&l.span.lexeme[1..], 16)
+            .map(ConstValue::Colour)
+            .map_err(|_| Overflow),
+
+        // Anything reading the pad, calling functions or touching variables
+        // can only be known at runtime
+        _ => Err(NotConstant),
+    }
+}
+
+/// Mirrors the casts allowed by the analyzer. A colour becomes its packed
+/// `0xRRGGBB` value, and an int is clamped into the range of valid colours.
+fn cast(value: ConstValue, to: &str) -> Option<ConstValue> {
+    match (value, to) {
+        (ConstValue::Int(_), "int")
+        | (ConstValue::Float(_), "float")
+        | (ConstValue::Bool(_), "bool")
+        | (ConstValue::Colour(_), "colour") => Some(value),
+        (ConstValue::Int(value), "float") => Some(ConstValue::Float(value as f32)),
+        (ConstValue::Int(value), "colour") => {
+            Some(ConstValue::Colour(value.clamp(0, 0xFFFFFF) as u32))
+        }
+        (ConstValue::Colour(value), "int") => Some(ConstValue::Int(value as i64)),
+        (ConstValue::Bool(value), "int") => Some(ConstValue::Int(value as i64)),
+        (ConstValue::Bool(value), "float") => Some(ConstValue::Float(value as u8 as f32)),
+        _ => None,
+    }
+}
+
+fn fold_bin_op(
+    operator: TokenKind,
+    left: ConstValue,
+    right: ConstValue,
+) -> Result<ConstValue, ConstError> {
+    use ConstValue::*;
+
+    let value = match (left, right) {
+        (Int(_), Int(0)) if matches!(operator, TokenKind::Divide | TokenKind::Mod) => {
+            return Err(DivisionByZero)
+        }
+        (Int(l), Int(r)) => match operator {
+            TokenKind::Plus => Int(l.checked_add(r).ok_or(Overflow)?),
+            TokenKind::Minus => Int(l.checked_sub(r).ok_or(Overflow)?),
+            TokenKind::Multiply => Int(l.checked_mul(r).ok_or(Overflow)?),
+            TokenKind::Divide => Int(l.checked_div(r).ok_or(Overflow)?),
+            TokenKind::Mod => Int(l.checked_rem(r).ok_or(Overflow)?),
+            _ => Bool(compare(operator, l, r).ok_or(NotConstant)?),
+        },
+        (Float(_), Float(_)) | (Float(_), Int(_)) | (Int(_), Float(_)) => {
+            let (l, r) = (left.as_float(), right.as_float());
+            let (l, r) = (l.ok_or(NotConstant)?, r.ok_or(NotConstant)?);
+            let value = match operator {
+                TokenKind::Divide if r == 0.0 => return Err(DivisionByZero),
+                TokenKind::Plus => l + r,
+                TokenKind::Minus => l - r,
+                TokenKind::Multiply => l * r,
+                TokenKind::Divide => l / r,
+                _ => return Ok(Bool(compare(operator, l, r).ok_or(NotConstant)?)),
+            };
+
+            // Infinities have no literal to be written as
+            match value.is_finite() {
+                true => Float(value),
+                false => return Err(Overflow),
+            }
+        }
+        (Colour(l), Colour(r)) => match operator {
+            TokenKind::Plus | TokenKind::Minus | TokenKind::Multiply | TokenKind::Divide => {
+                Colour(fold_channels(operator, l, r))
+            }
+            _ => Bool(compare(operator, l, r).ok_or(NotConstant)?),
+        },
+        (Bool(l), Bool(r)) => match operator {
+            TokenKind::And => Bool(l && r),
+            TokenKind::Or => Bool(l || r),
+            _ => Bool(compare(operator, l, r).ok_or(NotConstant)?),
+        },
+        _ => return Err(NotConstant),
+    };
+
+    Ok(value)
+}
+
+/// Applies `operator` to each channel of two colours separately, saturating
+/// the result to `0..=255`. Dividing by a zero channel divides by 1 instead.
+fn fold_channels(operator: TokenKind, left: u32, right: u32) -> u32 {
+    [0x10000, 0x100, 1]
+        .into_iter()
+        .map(|scale| {
+            let (l, r) = ((left / scale % 256) as i64, (right / scale % 256) as i64);
+            let channel = match operator {
+                TokenKind::Plus => l + r,
+                TokenKind::Minus => l - r,
+                TokenKind::Multiply => l * r,
+                _ => l / r.max(1),
+            };
+
+            channel.clamp(0, 255) as u32 * scale
+        })
+        .sum()
+}
+
+fn compare<T: PartialOrd>(operator: TokenKind, left: T, right: T) -> Option<bool> {
+    match operator {
+        TokenKind::EqEq => Some(left == right),
+        TokenKind::NotEqual => Some(left != right),
+        TokenKind::LessThan => Some(left < right),
+        TokenKind::LessThanEqual => Some(left <= right),
+        TokenKind::GreaterThan => Some(left > right),
+        TokenKind::GreaterThanEqual => Some(left >= right),
+        _ => None,
+    }
+}
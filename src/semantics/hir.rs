@@ -0,0 +1,533 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::core::TokenKind;
+use crate::semantics::const_eval::ConstValue;
+use crate::semantics::symbol_table::{SymbolId, SymbolTable};
+use crate::semantics::utils::Type;
+
+/// A program after analysis, with every expression typed and every
+/// identifier resolved. Only produced for programs without errors.
+#[derive(Debug, Clone)]
+pub struct Hir {
+    pub statements: Vec<Node>,
+    /// Every symbol declared in the program, with all scopes closed
+    pub symbols: SymbolTable,
+    /// The fields of every struct, in declaration order
+    pub structs: HashMap<String, Vec<(String, Type)>>,
+}
+
+/// A node and its type. An expression's type is that of its value, and a
+/// statement's is the type it returns from the function it's in, or void if
+/// it doesn't return.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub ty: Type,
+}
+
+impl Node {
+    pub fn new(kind: NodeKind, ty: Type) -> Self {
+        Node { kind, ty }
+    }
+
+    pub fn void(kind: NodeKind) -> Self {
+        Node::new(kind, Type::Void)
+    }
+
+    /// Stands in for anything that failed to analyze
+    pub fn error() -> Self {
+        Node::new(NodeKind::Error, Type::Unknown)
+    }
+}
+
+/// A variable or one of its fields being assigned to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Place {
+    pub variable: SymbolId,
+    /// The struct fields followed from the variable, if any
+    pub fields: Vec<String>,
+    /// The type of what's assigned to
+    pub ty: Type,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchArm {
+    /// The runtime value of the pattern, or of its bounds for a range
+    pub from: usize,
+    pub to: Option<usize>,
+    pub body: Vec<Node>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeKind {
+    Block(Vec<Node>),
+    FunctionDecl {
+        function: SymbolId,
+        params: Vec<SymbolId>,
+        body: Vec<Node>,
+    },
+    /// Also a redeclaration, which reuses the variable
+    VarDec {
+        variable: SymbolId,
+        expression: Box<Node>,
+    },
+    /// `None` where a value is discarded with `_`
+    TupleDec {
+        variables: Vec<Option<SymbolId>>,
+        expression: Box<Node>,
+    },
+    Assignment {
+        target: Place,
+        expression: Box<Node>,
+    },
+    /// `operator` is the arithmetic operator applied, and there's no
+    /// expression for `++` and `--`
+    CompoundAssignment {
+        target: Place,
+        operator: TokenKind,
+        expression: Option<Box<Node>>,
+    },
+    CallStatement(Box<Node>),
+    Return(Option<Box<Node>>),
+    If {
+        condition: Box<Node>,
+        if_true: Vec<Node>,
+        if_false: Option<Vec<Node>>,
+    },
+    For {
+        initializer: Option<Box<Node>>,
+        condition: Box<Node>,
+        increment: Option<Box<Node>>,
+        body: Vec<Node>,
+    },
+    While {
+        condition: Box<Node>,
+        body: Vec<Node>,
+    },
+    Match {
+        expression: Box<Node>,
+        arms: Vec<MatchArm>,
+        default: Option<Vec<Node>>,
+    },
+    Print(Vec<Node>),
+    Delay(Box<Node>),
+    PadWrite {
+        loc_x: Box<Node>,
+        loc_y: Box<Node>,
+        colour: Box<Node>,
+    },
+    PadWriteBox {
+        loc_x: Box<Node>,
+        loc_y: Box<Node>,
+        width: Box<Node>,
+        height: Box<Node>,
+        colour: Box<Node>,
+    },
+    PadClear(Box<Node>),
+    /// Constant and struct declarations, which need no code
+    Empty,
+
+    /// A literal, or a constant or colour channel folded at compile time
+    Constant(ConstValue),
+    String(String),
+    Variable(SymbolId),
+    /// A function used as a value
+    Function(SymbolId),
+    Field {
+        variable: SymbolId,
+        fields: Vec<String>,
+    },
+    /// A channel of a colour variable, or of a colour field of one, with the
+    /// channel's place value
+    Channel {
+        variable: SymbolId,
+        fields: Vec<String>,
+        scale: u32,
+    },
+    /// A cast to the node's type
+    Cast(Box<Node>),
+    UnaryOp {
+        operator: TokenKind,
+        expression: Box<Node>,
+    },
+    BinOp {
+        left: Box<Node>,
+        operator: TokenKind,
+        right: Box<Node>,
+    },
+    Conditional {
+        condition: Box<Node>,
+        if_true: Box<Node>,
+        if_false: Box<Node>,
+    },
+    Rgb {
+        red: Box<Node>,
+        green: Box<Node>,
+        blue: Box<Node>,
+    },
+    Tuple(Vec<Node>),
+    /// The field values in the order the struct declares them
+    StructLiteral(Vec<Node>),
+    Call {
+        function: SymbolId,
+        args: Vec<Node>,
+    },
+    /// A call through a variable of function type
+    IndirectCall {
+        variable: SymbolId,
+        args: Vec<Node>,
+    },
+    PadWidth,
+    PadHeight,
+    PadRandI(Box<Node>),
+    PadRead {
+        x: Box<Node>,
+        y: Box<Node>,
+    },
+    Error,
+}
+
+impl Node {
+    /// The nodes directly under this one, in source order.
+    pub fn children(&self) -> Vec<&Node> {
+        let mut children = vec![];
+        match &self.kind {
+            NodeKind::Block(nodes)
+            | NodeKind::Print(nodes)
+            | NodeKind::Tuple(nodes)
+            | NodeKind::StructLiteral(nodes)
+            | NodeKind::FunctionDecl { body: nodes, .. }
+            | NodeKind::Call { args: nodes, .. }
+            | NodeKind::IndirectCall { args: nodes, .. } => children.extend(nodes),
+            NodeKind::VarDec { expression, .. }
+            | NodeKind::TupleDec { expression, .. }
+            | NodeKind::Assignment { expression, .. }
+            | NodeKind::CallStatement(expression)
+            | NodeKind::Delay(expression)
+            | NodeKind::PadClear(expression)
+            | NodeKind::Cast(expression)
+            | NodeKind::UnaryOp { expression, .. }
+            | NodeKind::PadRandI(expression) => children.push(&**expression),
+            NodeKind::CompoundAssignment { expression, .. } | NodeKind::Return(expression) => {
+                children.extend(expression.as_deref())
+            }
+            NodeKind::If {
+                condition,
+                if_true,
+                if_false,
+            } => {
+                children.push(&**condition);
+                children.extend(if_true);
+                children.extend(if_false.iter().flatten());
+            }
+            NodeKind::For {
+                initializer,
+                condition,
+                increment,
+                body,
+            } => {
+                children.extend(initializer.as_deref());
+                children.push(condition);
+                children.extend(increment.as_deref());
+                children.extend(body);
+            }
+            NodeKind::While { condition, body } => {
+                children.push(&**condition);
+                children.extend(body);
+            }
+            NodeKind::Match {
+                expression,
+                arms,
+                default,
+            } => {
+                children.push(&**expression);
+                children.extend(arms.iter().flat_map(|arm| &arm.body));
+                children.extend(default.iter().flatten());
+            }
+            NodeKind::PadWrite {
+                loc_x,
+                loc_y,
+                colour,
+            } => children.extend([&**loc_x, loc_y, colour]),
+            NodeKind::PadWriteBox {
+                loc_x,
+                loc_y,
+                width,
+                height,
+                colour,
+            } => children.extend([&**loc_x, loc_y, width, height, colour]),
+            NodeKind::BinOp { left, right, .. } => children.extend([&**left, right]),
+            NodeKind::Conditional {
+                condition,
+                if_true,
+                if_false,
+            } => children.extend([&**condition, if_true, if_false]),
+            NodeKind::Rgb { red, green, blue } => children.extend([&**red, green, blue]),
+            NodeKind::PadRead { x, y } => children.extend([&**x, y]),
+            NodeKind::Empty
+            | NodeKind::Constant(_)
+            | NodeKind::String(_)
+            | NodeKind::Variable(_)
+            | NodeKind::Function(_)
+            | NodeKind::Field { .. }
+            | NodeKind::Channel { .. }
+            | NodeKind::PadWidth
+            | NodeKind::PadHeight
+            | NodeKind::Error => {}
+        }
+        children
+    }
+
+    /// Collects every symbol read, written or called in this subtree,
+    /// including in the functions declared in it.
+    pub fn collect_symbols(&self, symbols: &mut HashSet<SymbolId>) {
+        match &self.kind {
+            NodeKind::Variable(id)
+            | NodeKind::Function(id)
+            | NodeKind::Field { variable: id, .. }
+            | NodeKind::Channel { variable: id, .. }
+            | NodeKind::Call { function: id, .. }
+            | NodeKind::IndirectCall { variable: id, .. }
+            | NodeKind::Assignment {
+                target: Place { variable: id, .. },
+                ..
+            }
+            | NodeKind::CompoundAssignment {
+                target: Place { variable: id, .. },
+                ..
+            } => {
+                symbols.insert(*id);
+            }
+            _ => {}
+        }
+
+        for child in self.children() {
+            child.collect_symbols(symbols);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        lexing::Lexer, parsing::Parser, semantics::visitors::SemAnalyzer, utils::Utf8Buffer,
+    };
+
+    use super::*;
+    use assert_matches::assert_matches;
+    use rstest::rstest;
+    use std::path::Path;
+
+    fn lower(input: &str) -> Option<Hir> {
+        let mut lexer: Lexer<Utf8Buffer> = Lexer::new(input, None);
+        let tokens = lexer.lex().unwrap();
+
+        let mut parser = Parser::new(&tokens, Path::new(""));
+        let ast = parser.parse().unwrap();
+
+        let mut analyzer = SemAnalyzer::new();
+        analyzer.analyze(ast);
+
+        analyzer.hir().cloned()
+    }
+
+    fn kinds(hir: &Hir) -> Vec<&NodeKind> {
+        hir.statements.iter().map(|s| &s.kind).collect()
+    }
+
+    #[rstest]
+    fn test_only_lowered_without_errors() {
+        assert!(lower("let x: int = 1;").is_some());
+        assert!(lower("let x: int = true;").is_none());
+    }
+
+    #[rstest]
+    fn test_shadowing_resolves_to_new_symbol() {
+        let hir = lower(
+            r#"
+            let x: int = 1;
+            {
+                let x: float = 2.0;
+                __print x;
+            }
+            __print x;
+        "#,
+        )
+        .unwrap();
+
+        let [_, NodeKind::Block(inner), NodeKind::Print(outer)] = kinds(&hir).as_slice() else {
+            panic!("unexpected statements: {:#?}", hir.statements);
+        };
+        let NodeKind::Print(shadowing) = &inner[1].kind else {
+            panic!("unexpected statement: {:#?}", inner[1]);
+        };
+
+        assert_matches!(shadowing[0].kind, NodeKind::Variable(1));
+        assert_eq!(shadowing[0].ty, Type::Float);
+        assert_matches!(outer[0].kind, NodeKind::Variable(0));
+        assert_eq!(outer[0].ty, Type::Int);
+        assert_eq!(hir.symbols.symbol(1).shadows, Some(0));
+    }
+
+    #[rstest]
+    fn test_redeclaration_reuses_symbol() {
+        let hir = lower("let x: int = 1; let x: int = 2;").unwrap();
+
+        assert_matches!(
+            kinds(&hir).as_slice(),
+            [
+                NodeKind::VarDec { variable: 0, .. },
+                NodeKind::VarDec { variable: 0, .. }
+            ]
+        );
+        assert_eq!(hir.symbols.depth(), 0);
+    }
+
+    #[rstest]
+    fn test_expressions_are_typed() {
+        let hir = lower("let f: float = 1 + 2.5; let c: colour = 3 as colour;").unwrap();
+
+        let NodeKind::VarDec { expression, .. } = &hir.statements[0].kind else {
+            panic!("unexpected statement: {:#?}", hir.statements[0]);
+        };
+        assert_eq!(expression.ty, Type::Float);
+        assert_matches!(
+            &expression.kind,
+            NodeKind::BinOp { left, operator: TokenKind::Plus, right }
+                if left.ty == Type::Int && right.ty == Type::Float
+        );
+
+        let NodeKind::VarDec { expression, .. } = &hir.statements[1].kind else {
+            panic!("unexpected statement: {:#?}", hir.statements[1]);
+        };
+        assert_eq!(expression.ty, Type::Colour);
+        assert_matches!(&expression.kind, NodeKind::Cast(inner) if inner.ty == Type::Int);
+    }
+
+    #[rstest]
+    fn test_constants_are_inlined() {
+        let hir = lower(
+            r#"
+            const base: colour = #102030;
+            let g: int = base.g;
+            let b: colour = base;
+        "#,
+        )
+        .unwrap();
+
+        assert_matches!(hir.statements[0].kind, NodeKind::Empty);
+        assert_matches!(
+            kinds(&hir).as_slice(),
+            [
+                _,
+                NodeKind::VarDec { expression: g, .. },
+                NodeKind::VarDec { expression: b, .. },
+            ] if g.kind == NodeKind::Constant(ConstValue::Int(0x20))
+                && b.kind == NodeKind::Constant(ConstValue::Colour(0x102030))
+        );
+    }
+
+    #[rstest]
+    fn test_fields_and_channels() {
+        let hir = lower(
+            r#"
+            struct Pix { at: int, c: colour }
+            let p: Pix = Pix { c: #ff0000, at: 1 };
+            __print p.c.r, p.at;
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            hir.structs["Pix"],
+            vec![
+                ("at".to_string(), Type::Int),
+                ("c".to_string(), Type::Colour)
+            ]
+        );
+
+        // Literal fields are put in declaration order
+        let NodeKind::VarDec { expression, .. } = &hir.statements[1].kind else {
+            panic!("unexpected statement: {:#?}", hir.statements[1]);
+        };
+        assert_matches!(
+            &expression.kind,
+            NodeKind::StructLiteral(values)
+                if values[0].ty == Type::Int && values[1].ty == Type::Colour
+        );
+
+        let NodeKind::Print(values) = &hir.statements[2].kind else {
+            panic!("unexpected statement: {:#?}", hir.statements[2]);
+        };
+        assert_eq!(
+            values[0].kind,
+            NodeKind::Channel {
+                variable: 1,
+                fields: vec!["c".to_string()],
+                scale: 0x10000
+            }
+        );
+        assert_eq!(
+            values[1].kind,
+            NodeKind::Field {
+                variable: 1,
+                fields: vec!["at".to_string()]
+            }
+        );
+    }
+
+    #[rstest]
+    fn test_calls() {
+        let hir = lower(
+            r#"
+            fun double(v: int) -> int { return v * 2; }
+            fun apply(f: fun(int) -> int, v: int) -> int { return f(v); }
+            let x: int = apply(double, 4);
+        "#,
+        )
+        .unwrap();
+
+        let NodeKind::FunctionDecl {
+            function,
+            params,
+            body,
+        } = &hir.statements[1].kind
+        else {
+            panic!("unexpected statement: {:#?}", hir.statements[1]);
+        };
+        assert_eq!(*function, 2);
+        assert_eq!(*params, vec![3, 4]);
+        assert_matches!(
+            &body[0].kind,
+            NodeKind::Return(Some(call))
+                if matches!(call.kind, NodeKind::IndirectCall { variable: 3, .. })
+        );
+
+        let NodeKind::VarDec { expression, .. } = &hir.statements[2].kind else {
+            panic!("unexpected statement: {:#?}", hir.statements[2]);
+        };
+        assert_eq!(expression.ty, Type::Int);
+        assert_matches!(
+            &expression.kind,
+            NodeKind::Call { function: 2, args }
+                if args[0].kind == NodeKind::Function(0)
+        );
+    }
+
+    #[rstest]
+    fn test_collect_symbols() {
+        let hir = lower(
+            r#"
+            let total: int = 0;
+            let unused: int = 0;
+            fun add(v: int) { total += v; }
+        "#,
+        )
+        .unwrap();
+
+        let mut symbols = HashSet::new();
+        hir.statements[2].collect_symbols(&mut symbols);
+
+        assert_eq!(symbols, HashSet::from([0, 3]));
+    }
+}
@@ -1,2 +1,6 @@
+pub mod const_eval;
+pub mod hir;
+pub mod passes;
+pub mod symbol_table;
 pub mod utils;
 pub mod visitors;
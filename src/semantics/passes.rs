@@ -0,0 +1,395 @@
+use std::mem::{discriminant, replace};
+
+use crate::core::{TextSpan, Token, TokenKind};
+use crate::parsing::ast::{walk_mut, AstNode, VisitorMut};
+use crate::semantics::const_eval::{self, ConstValue};
+
+/// Runs AST-to-AST passes over a program in the order they were added, before
+/// it's analyzed.
+#[derive(Default)]
+pub struct PassManager {
+    passes: Vec<Box<dyn VisitorMut>>,
+}
+
+impl PassManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The passes `compile` runs
+    pub fn standard() -> Self {
+        Self::new().with(StripParentheses).with(ConstantFolder)
+    }
+
+    pub fn with(mut self, pass: impl VisitorMut + 'static) -> Self {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    pub fn run(&mut self, ast: &mut AstNode) {
+        for pass in &mut self.passes {
+            pass.visit_mut(ast);
+        }
+    }
+}
+
+/// Replaces each parenthesised expression with the expression inside it,
+/// since the tree already records the grouping.
+pub struct StripParentheses;
+
+impl VisitorMut for StripParentheses {
+    fn visit_mut(&mut self, node: &mut AstNode) {
+        walk_mut(self, node);
+
+        if let AstNode::SubExpression { bin_op } = node {
+            *node = replace(&mut **bin_op, AstNode::EndOfFile);
+        }
+    }
+}
+
+/// Folds operators, casts, conditionals and `rgb` whose operands are all
+/// literals into a single literal. Only expressions the analyzer would accept
+/// are folded, so no type errors are hidden from it.
+pub struct ConstantFolder;
+
+impl VisitorMut for ConstantFolder {
+    fn visit_mut(&mut self, node: &mut AstNode) {
+        // Folding bottom-up means every operand has already been folded
+        walk_mut(self, node);
+
+        let foldable = match node {
+            AstNode::BinOp {
+                left,
+                operator,
+                right,
+            } => match (literal(left), literal(right)) {
+                (Some(left), Some(right)) => well_typed(operator.kind, left, right),
+                _ => false,
+            },
+            AstNode::Conditional {
+                condition,
+                if_true,
+                if_false,
+            } => match (literal(condition), literal(if_true), literal(if_false)) {
+                (Some(_), Some(if_true), Some(if_false)) => {
+                    discriminant(&if_true) == discriminant(&if_false)
+                }
+                _ => false,
+            },
+            AstNode::Expression {
+                casted_type: Some(_),
+                ..
+            }
+            | AstNode::UnaryOp { .. }
+            | AstNode::Rgb { .. } => node.children().into_iter().all(|c| literal(c).is_some()),
+            _ => false,
+        };
+
+        let Some(value) = foldable
+            .then(|| const_eval::evaluate(node, &|_| None).ok())
+            .flatten()
+        else {
+            return;
+        };
+        let Some(folded) = to_literal(value, &span_of(node)) else {
+            return;
+        };
+
+        *node = match node {
+            // The expression is kept for whatever expects one here, without
+            // the cast it no longer needs
+            AstNode::Expression { .. } => AstNode::Expression {
+                casted_type: None,
+                expr: Box::new(folded),
+            },
+            _ => folded,
+        };
+    }
+}
+
+/// The value of a literal, looking through parentheses and expressions that
+/// don't cast. Negative numbers are a minus applied to a literal.
+fn literal(node: &AstNode) -> Option<ConstValue> {
+    match node {
+        AstNode::Expression {
+            casted_type: None,
+            expr,
+        }
+        | AstNode::SubExpression { bin_op: expr } => literal(expr),
+        AstNode::UnaryOp { operator, expr } if operator.kind == TokenKind::Minus => {
+            match literal(expr)? {
+                ConstValue::Int(value) => Some(ConstValue::Int(-value)),
+                ConstValue::Float(value) => Some(ConstValue::Float(-value)),
+                _ => None,
+            }
+        }
+        AstNode::IntLiteral(_)
+        | AstNode::FloatLiteral(_)
+        | AstNode::BoolLiteral(_)
+        | AstNode::ColourLiteral(_) => const_eval::evaluate(node, &|_| None).ok(),
+        _ => None,
+    }
+}
+
+/// Whether the analyzer accepts `operator` between these values. Ints and
+/// floats can only be mixed by `+`, and bools can't be ordered.
+fn well_typed(operator: TokenKind, left: ConstValue, right: ConstValue) -> bool {
+    match (left, right) {
+        (ConstValue::Int(_), ConstValue::Float(_)) | (ConstValue::Float(_), ConstValue::Int(_)) => {
+            operator == TokenKind::Plus
+        }
+        (ConstValue::Bool(_), ConstValue::Bool(_)) => matches!(
+            operator,
+            TokenKind::And | TokenKind::Or | TokenKind::EqEq | TokenKind::NotEqual
+        ),
+        _ => discriminant(&left) == discriminant(&right),
+    }
+}
+
+/// The literal node for `value`, with a token covering `span`. Returns `None`
+/// for floats that aren't finite, which have no literal.
+fn to_literal(value: ConstValue, span: &TextSpan) -> Option<AstNode> {
+    let token = |kind, lexeme: String| {
+        Token::new(
+            kind,
+            TextSpan {
+                lexeme,
+                ..span.clone()
+            },
+        )
+    };
+
+    let node = match value {
+        ConstValue::Int(value) if value < 0 => negate(
+            AstNode::IntLiteral(token(
+                TokenKind::IntLiteral,
+                value.unsigned_abs().to_string(),
+            )),
+            span,
+        ),
+        ConstValue::Int(value) => {
+            AstNode::IntLiteral(token(TokenKind::IntLiteral, value.to_string()))
+        }
+        ConstValue::Float(value) if !value.is_finite() => return None,
+        ConstValue::Float(value) if value.is_sign_negative() => negate(
+            AstNode::FloatLiteral(token(TokenKind::FloatLiteral, format!("{:?}", -value))),
+            span,
+        ),
+        ConstValue::Float(value) => {
+            AstNode::FloatLiteral(token(TokenKind::FloatLiteral, format!("{value:?}")))
+        }
+        ConstValue::Bool(value) => {
+            AstNode::BoolLiteral(token(TokenKind::BoolLiteral, value.to_string()))
+        }
+        ConstValue::Colour(value) => {
+            AstNode::ColourLiteral(token(TokenKind::ColourLiteral, format!("#{value:06x}")))
+        }
+    };
+
+    Some(node)
+}
+
+fn negate(node: AstNode, span: &TextSpan) -> AstNode {
+    AstNode::UnaryOp {
+        operator: Token::new(
+            TokenKind::Minus,
+            TextSpan {
+                lexeme: "-".to_string(),
+                ..span.clone()
+            },
+        ),
+        expr: Box::new(node),
+    }
+}
+
+/// The span from the first token of `node` to its last, so errors about a
+/// folded literal still point at the expression it came from.
+fn span_of(node: &mut AstNode) -> TextSpan {
+    let mut spans: Vec<TextSpan> = vec![];
+    node.for_each_token_mut(&mut |token| spans.push(token.span.clone()));
+
+    let first = spans.iter().min_by_key(|span| span.from_offset);
+    let last = spans.iter().max_by_key(|span| span.to_offset);
+    match (first, last) {
+        (Some(first), Some(last)) => TextSpan {
+            to_line: last.to_line,
+            to_col: last.to_col,
+            to_offset: last.to_offset,
+            ..first.clone()
+        },
+        _ => TextSpan::new(0, 0, 0, 0, ""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::{
+        lexing::Lexer,
+        parsing::{
+            ast::{walk, Visitor},
+            Parser,
+        },
+        utils::Utf8Buffer,
+    };
+
+    use super::*;
+    use assert_matches::assert_matches;
+    use rstest::rstest;
+
+    fn parse(input: &str) -> AstNode {
+        let tokens = Lexer::<Utf8Buffer>::new(input, None).lex().unwrap();
+        Parser::new(&tokens, Path::new("test"))
+            .parse_program()
+            .unwrap()
+    }
+
+    /// The expression `x` is declared with, after running the standard passes
+    fn fold(expression: &str) -> AstNode {
+        let mut ast = parse(&format!("let x = {expression};"));
+        PassManager::standard().run(&mut ast);
+
+        let AstNode::Program { mut statements } = ast else {
+            unreachable!()
+        };
+        match statements.remove(0) {
+            AstNode::VarDec { expression, .. } => *expression,
+            node => panic!("expected a declaration, got {node:?}"),
+        }
+    }
+
+    /// Counts the nodes it sees, leaving the traversal to `walk`
+    struct NodeCounter(usize);
+
+    impl Visitor<()> for NodeCounter {
+        fn visit(&mut self, node: &AstNode) {
+            self.0 += 1;
+            walk(self, node);
+        }
+    }
+
+    #[rstest]
+    #[case::int("(1 + 2) * 3 - 4", "5")]
+    #[case::float("1.5 * 2.0 + 1", "4.0")]
+    #[case::comparison("not (1 < 2) or false", "false")]
+    #[case::colour("rgb(255, 0, 0) + #000010", "#ff0010")]
+    #[case::cast("300 as colour", "#00012c")]
+    #[case::conditional("true ? 4 : 5", "4")]
+    #[case::negative_operand("(2 * 3) - -1", "7")]
+    fn test_fold(#[case] expression: &str, #[case] expected: &str) {
+        assert_matches!(
+            fold(expression),
+            AstNode::Expression { casted_type: None, expr } => match *expr {
+                AstNode::IntLiteral(literal)
+                | AstNode::FloatLiteral(literal)
+                | AstNode::BoolLiteral(literal)
+                | AstNode::ColourLiteral(literal) => assert_eq!(literal.span.lexeme, expected),
+                node => panic!("expected a literal, got {node:?}"),
+            }
+        );
+    }
+
+    #[rstest]
+    #[case::int("2 - 5", TokenKind::IntLiteral, "3")]
+    #[case::float("0.5 - 2.0", TokenKind::FloatLiteral, "1.5")]
+    fn test_fold_negative(
+        #[case] expression: &str,
+        #[case] kind: TokenKind,
+        #[case] expected: &str,
+    ) {
+        assert_matches!(
+            fold(expression),
+            AstNode::Expression { expr, .. } => assert_matches!(
+                *expr,
+                AstNode::UnaryOp { operator, expr } => {
+                    assert_eq!(operator.kind, TokenKind::Minus);
+                    assert_matches!(
+                        *expr,
+                        AstNode::IntLiteral(literal) | AstNode::FloatLiteral(literal)
+                            if literal.kind == kind && literal.span.lexeme == expected
+                    );
+                }
+            )
+        );
+    }
+
+    #[rstest]
+    #[case::mixed_types("1 - 2.0")]
+    #[case::ordered_bools("true < false")]
+    #[case::division_by_zero("1 / 0")]
+    #[case::infinity("1.0 / 0.0")]
+    #[case::overflow("9223372036854775807 + 1")]
+    #[case::variable("y + 1")]
+    #[case::branch_types("true ? 1 : 2.0")]
+    fn test_not_folded(#[case] expression: &str) {
+        assert_matches!(
+            fold(expression),
+            AstNode::Expression { expr, .. } => assert_matches!(
+                *expr,
+                AstNode::BinOp { .. } | AstNode::Conditional { .. }
+            )
+        );
+    }
+
+    #[rstest]
+    fn test_folded_span() {
+        assert_matches!(
+            fold("1 + 2 * 3"),
+            AstNode::Expression { expr, .. } => assert_matches!(
+                *expr,
+                AstNode::IntLiteral(literal) => {
+                    assert_eq!(literal.span.lexeme, "7");
+                    assert_eq!((literal.span.from_col, literal.span.to_col), (9, 18));
+                    assert_eq!((literal.span.from_offset, literal.span.to_offset), (8, 17));
+                }
+            )
+        );
+    }
+
+    #[rstest]
+    fn test_strip_parentheses() {
+        let mut ast = parse("let x = (y + (1)) * 2;");
+        let mut counter = NodeCounter(0);
+        counter.visit(&ast);
+        assert_eq!(counter.0, 12);
+
+        PassManager::new().with(StripParentheses).run(&mut ast);
+
+        let mut counter = NodeCounter(0);
+        counter.visit(&ast);
+        assert_eq!(counter.0, 10);
+
+        let mut names = std::collections::HashSet::new();
+        ast.collect_names(&mut names);
+        assert_eq!(names, ["y".to_string()].into());
+    }
+
+    #[rstest]
+    fn test_passes_run_in_order() {
+        // Without the parentheses stripped first, folding still sees through
+        // them, so both orders fold completely
+        for mut passes in [
+            PassManager::new()
+                .with(StripParentheses)
+                .with(ConstantFolder),
+            PassManager::new()
+                .with(ConstantFolder)
+                .with(StripParentheses),
+        ] {
+            let mut ast = parse("let x = (1 + 2) * 3;");
+            passes.run(&mut ast);
+
+            let mut counter = NodeCounter(0);
+            counter.visit(&ast);
+            assert_eq!(counter.0, 4);
+        }
+
+        // Folding alone leaves the parentheses around a variable
+        let mut ast = parse("let x = (y) * 3;");
+        PassManager::new().with(ConstantFolder).run(&mut ast);
+        let mut counter = NodeCounter(0);
+        counter.visit(&ast);
+        assert_eq!(counter.0, 8);
+    }
+}
@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+
+use crate::core::Token;
+use crate::semantics::utils::{SymbolType, Type};
+
+/// Identifies a symbol declared in a program. Ids index into the symbols of
+/// a [`SymbolTable`], in the order the symbols were declared.
+pub type SymbolId = usize;
+
+/// An interned identifier. Two names are equal exactly when their text is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Name(u32);
+
+/// Maps each distinct identifier to a [`Name`], so that scopes compare and
+/// hash small integers rather than strings.
+#[derive(Debug, Clone, Default)]
+pub struct Interner {
+    names: HashMap<String, Name>,
+}
+
+impl Interner {
+    pub fn intern(&mut self, text: &str) -> Name {
+        if let Some(name) = self.names.get(text) {
+            return *name;
+        }
+
+        let name = Name(self.names.len() as u32);
+        self.names.insert(text.to_string(), name);
+        name
+    }
+
+    /// The name of `text`, if it has been interned.
+    pub fn get(&self, text: &str) -> Option<Name> {
+        self.names.get(text).copied()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub id: SymbolId,
+    /// The identifier the symbol was declared with, which also gives where
+    pub token: Token,
+    pub symbol_type: SymbolType,
+    /// How deeply nested the declaring scope is, 0 being the global scope
+    pub scope: usize,
+    /// The symbol with the same name this one hides from an enclosing scope
+    pub shadows: Option<SymbolId>,
+}
+
+impl Symbol {
+    /// The type of a variable, or of a function when used as a value.
+    pub fn value_type(&self) -> Type {
+        match &self.symbol_type {
+            SymbolType::Variable(t) | SymbolType::Constant(t, _) => t.clone(),
+            SymbolType::Function(signature) => signature.function_type(),
+            SymbolType::Struct(_) => Type::Unknown,
+        }
+    }
+}
+
+/// Every symbol declared in a program, along with the scopes currently open.
+///
+/// Each scope maps names to the symbols declared in it, and each name keeps
+/// the stack of symbols it's bound to in the open scopes, so finding what a
+/// name refers to doesn't walk the scopes. Symbols outlive their scopes, so
+/// ids stay valid once analysis is done.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    interner: Interner,
+    symbols: Vec<Symbol>,
+    scopes: Vec<HashMap<Name, SymbolId>>,
+    /// The symbols each name is bound to, innermost last, indexed by name
+    bindings: Vec<Vec<SymbolId>>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        SymbolTable::default()
+    }
+
+    pub fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    pub fn pop_scope(&mut self) {
+        for name in self.scopes.pop().unwrap().into_keys() {
+            self.bindings[name.0 as usize].pop();
+        }
+    }
+
+    /// The number of scopes currently open.
+    pub fn depth(&self) -> usize {
+        self.scopes.len()
+    }
+
+    /// Declares a symbol in the innermost scope, which mustn't already have
+    /// one with the same name.
+    pub fn declare(&mut self, token: &Token, symbol_type: SymbolType) -> SymbolId {
+        let name = self.interner.intern(&token.span.lexeme);
+        let id = self.symbols.len();
+
+        if self.bindings.len() <= name.0 as usize {
+            self.bindings.push(vec![]);
+        }
+        let bindings = &mut self.bindings[name.0 as usize];
+
+        self.symbols.push(Symbol {
+            id,
+            token: token.clone(),
+            symbol_type,
+            scope: self.scopes.len() - 1,
+            shadows: bindings.last().copied(),
+        });
+        bindings.push(id);
+
+        let previous = self.scopes.last_mut().unwrap().insert(name, id);
+        debug_assert!(previous.is_none(), "redeclared {}", token.span.lexeme);
+
+        id
+    }
+
+    /// The symbols `text` is bound to in the open scopes, innermost first.
+    pub fn visible(&self, text: &str) -> impl Iterator<Item = &Symbol> {
+        self.interner
+            .get(text)
+            .map(|name| self.bindings[name.0 as usize].as_slice())
+            .unwrap_or_default()
+            .iter()
+            .rev()
+            .map(|id| &self.symbols[*id])
+    }
+
+    /// The symbol `text` refers to from the innermost scope.
+    pub fn find(&self, text: &str) -> Option<&Symbol> {
+        self.visible(text).next()
+    }
+
+    /// The symbol declared as `text` in the scope `depth` levels deep.
+    pub fn find_in_scope(&self, depth: usize, text: &str) -> Option<&Symbol> {
+        let name = self.interner.get(text)?;
+        self.scopes[depth].get(&name).map(|id| &self.symbols[*id])
+    }
+
+    /// The symbol declared as `text` in the innermost scope.
+    pub fn find_in_current_scope(&self, text: &str) -> Option<&Symbol> {
+        self.find_in_scope(self.scopes.len() - 1, text)
+    }
+
+    pub fn symbol(&self, id: SymbolId) -> &Symbol {
+        &self.symbols[id]
+    }
+
+    pub fn symbol_mut(&mut self, id: SymbolId) -> &mut Symbol {
+        &mut self.symbols[id]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        core::{TextSpan, TokenKind},
+        lexing::Lexer,
+        parsing::Parser,
+        semantics::visitors::SemAnalyzer,
+        utils::Utf8Buffer,
+    };
+
+    use super::*;
+    use assert_matches::assert_matches;
+    use rstest::rstest;
+    use std::{fmt::Write, path::Path, time::Instant};
+
+    fn identifier(lexeme: &str, line: usize) -> Token {
+        Token::new(
+            TokenKind::Identifier,
+            TextSpan::new(line, line, 1, lexeme.len() + 1, lexeme),
+        )
+    }
+
+    #[rstest]
+    fn test_symbol_table() {
+        let mut symbol_table = SymbolTable::new();
+        symbol_table.push_scope();
+
+        symbol_table.declare(&identifier("x", 1), SymbolType::Variable(Type::Int));
+        symbol_table.declare(&identifier("y", 2), SymbolType::Variable(Type::Float));
+        symbol_table.declare(&identifier("z", 3), SymbolType::Variable(Type::Bool));
+
+        assert_matches!(
+            symbol_table.find("x").unwrap().symbol_type,
+            SymbolType::Variable(Type::Int)
+        );
+
+        assert_matches!(
+            symbol_table.find("y").unwrap().symbol_type,
+            SymbolType::Variable(Type::Float)
+        );
+
+        assert_matches!(
+            symbol_table.find("z").unwrap().symbol_type,
+            SymbolType::Variable(Type::Bool)
+        );
+
+        assert!(symbol_table.find("w").is_none());
+    }
+
+    #[rstest]
+    fn test_shadowing() {
+        let mut symbol_table = SymbolTable::new();
+        symbol_table.push_scope();
+        let outer = symbol_table.declare(&identifier("x", 1), SymbolType::Variable(Type::Int));
+
+        symbol_table.push_scope();
+        assert!(symbol_table.find_in_current_scope("x").is_none());
+
+        let inner = symbol_table.declare(&identifier("x", 2), SymbolType::Variable(Type::Float));
+        let symbol = symbol_table.find("x").unwrap();
+        assert_eq!(symbol.shadows, Some(outer));
+        assert_eq!(symbol.scope, 1);
+        assert_eq!(symbol.token.span.from_line, 2);
+
+        let lines: Vec<usize> = symbol_table
+            .visible("x")
+            .map(|s| s.token.span.from_line)
+            .collect();
+        assert_eq!(lines, [2, 1]);
+
+        // Leaving the scope uncovers the outer symbol, but keeps the inner one
+        symbol_table.pop_scope();
+        assert_eq!(symbol_table.find("x").unwrap().shadows, None);
+        assert_eq!(symbol_table.symbol(inner).value_type(), Type::Float);
+    }
+
+    #[rstest]
+    fn test_find_in_scope() {
+        let mut symbol_table = SymbolTable::new();
+        symbol_table.push_scope();
+        symbol_table.declare(&identifier("g", 1), SymbolType::Variable(Type::Int));
+        symbol_table.push_scope();
+        symbol_table.declare(&identifier("l", 2), SymbolType::Variable(Type::Int));
+
+        assert_eq!(symbol_table.depth(), 2);
+        assert!(symbol_table.find_in_scope(0, "g").is_some());
+        assert!(symbol_table.find_in_scope(0, "l").is_none());
+        assert!(symbol_table.find_in_current_scope("l").is_some());
+    }
+
+    /// Times analyzing programs made of little but declarations and lookups,
+    /// where a table that walks its scopes slows down quadratically. Run with
+    /// `cargo test --release bench_declarations -- --ignored --nocapture`.
+    #[rstest]
+    #[ignore = "benchmark"]
+    fn bench_declarations() {
+        for count in [1_000, 4_000, 16_000] {
+            let mut input = String::new();
+            for i in 0..count {
+                writeln!(input, "let v{}: int = {};", i, i).unwrap();
+            }
+            for i in 0..count {
+                writeln!(input, "v{} = v{} + v{};", i, count - 1 - i, i / 2).unwrap();
+            }
+            writeln!(input, "fun f(a: int) -> int {{").unwrap();
+            for i in 0..count {
+                writeln!(input, "let v{}: int = a + v{};", i, i).unwrap();
+            }
+            writeln!(input, "return a; }}").unwrap();
+
+            let tokens = Lexer::<Utf8Buffer>::new(&input, None).lex().unwrap();
+            let mut parser = Parser::new(&tokens, Path::new(""));
+            let ast = parser.parse().unwrap();
+
+            let start = Instant::now();
+            let mut analyzer = SemAnalyzer::new();
+            let has_errors = analyzer.analyze(ast).has_errors();
+            let elapsed = start.elapsed();
+            println!(
+                "{} declarations: {:?} ({:?} each)",
+                count * 2,
+                elapsed,
+                elapsed / (count as u32 * 2)
+            );
+
+            assert!(!has_errors);
+        }
+    }
+}
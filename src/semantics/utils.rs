@@ -1,13 +1,33 @@
-use std::{collections::LinkedList, fmt::Display};
+use std::fmt::Display;
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+use crate::core::{Token, TokenKind};
+use crate::parsing::ast::TypeAnnotation;
+use crate::semantics::const_eval::ConstValue;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub enum Type {
     Int,
     Float,
     Bool,
     Colour,
+    /// Only string literals have this type, and they can only be printed
+    String,
     Void,
     Unknown,
+    /// A user defined struct, referred to by name
+    Struct(String),
+    /// Several values returned together by a function. Boxed to keep `Type`
+    /// as small as a `String`.
+    Tuple(Box<[Type]>),
+    /// A reference to a function, written `fun(int, int) -> colour`
+    Function(Box<FunctionType>),
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub struct FunctionType {
+    /// The parameter types, in declaration order
+    pub parameters: Vec<Type>,
+    pub return_type: Type,
 }
 
 impl Display for Type {
@@ -17,16 +37,103 @@ impl Display for Type {
             Type::Float => write!(f, "float"),
             Type::Bool => write!(f, "bool"),
             Type::Colour => write!(f, "colour"),
+            Type::String => write!(f, "string"),
             Type::Void => write!(f, "void"),
             Type::Unknown => write!(f, "unknown"),
+            Type::Struct(name) => write!(f, "{}", name),
+            Type::Tuple(types) => {
+                let types: Vec<String> = types.iter().map(|t| t.to_string()).collect();
+                write!(f, "({})", types.join(", "))
+            }
+            Type::Function(function) => {
+                let parameters: Vec<String> =
+                    function.parameters.iter().map(|t| t.to_string()).collect();
+                write!(
+                    f,
+                    "fun({}) -> {}",
+                    parameters.join(", "),
+                    function.return_type
+                )
+            }
         }
     }
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+impl Type {
+    /// The type of a literal token, or `Unknown` if the token isn't a literal.
+    pub fn of_literal(token: &Token) -> Type {
+        match token.kind {
+            TokenKind::IntLiteral => Type::Int,
+            TokenKind::FloatLiteral => Type::Float,
+            TokenKind::BoolLiteral => Type::Bool,
+            TokenKind::ColourLiteral => Type::Colour,
+            TokenKind::StringLiteral => Type::String,
+            _ => Type::Unknown,
+        }
+    }
+
+    /// The type written as `name`. Anything that isn't a builtin type names
+    /// a struct.
+    pub fn of_name(name: &str) -> Type {
+        match name {
+            "int" => Type::Int,
+            "float" => Type::Float,
+            "bool" => Type::Bool,
+            "colour" => Type::Colour,
+            "void" => Type::Void,
+            name => Type::Struct(name.to_string()),
+        }
+    }
+
+    /// The type an annotation stands for, whether or not the structs it
+    /// names are declared.
+    pub fn of_annotation(annotation: &TypeAnnotation) -> Type {
+        match annotation {
+            TypeAnnotation::Named(token) => Type::of_name(&token.span.lexeme),
+            TypeAnnotation::Function {
+                params,
+                return_type,
+                ..
+            } => Type::Function(Box::new(FunctionType {
+                parameters: params.iter().map(Type::of_annotation).collect(),
+                return_type: return_type
+                    .as_deref()
+                    .map_or(Type::Void, Type::of_annotation),
+            })),
+        }
+    }
+}
+
+/// The value an int, bool or colour literal takes at runtime.
+pub fn literal_value(token: &Token) -> usize {
+    match token.kind {
+        TokenKind::IntLiteral => token.int_value().unwrap() as usize,
+        TokenKind::BoolLiteral => (token.span.lexeme == "true") as usize,
+        TokenKind::ColourLiteral => usize::from_str_radix(&token.span.lexeme[1..], 16).unwrap(),
+        _ => unreachable!(),
+    }
+}
+
+/// The place value of a colour channel read with `.r`, `.g` or `.b` in a
+/// packed `0xRRGGBB` colour. Each channel holds a value in `0..=255`.
+pub fn channel_scale(channel: &str) -> Option<u32> {
+    match channel {
+        "r" => Some(0x10000),
+        "g" => Some(0x100),
+        "b" => Some(1),
+        _ => None,
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum SymbolType {
     Variable(Type),
+    /// A constant and its folded value, which is `None` if the initializer
+    /// couldn't be evaluated at compile time
+    Constant(Type, Option<ConstValue>),
     Function(Signature),
+    /// A struct declaration and its fields, in declaration order
+    Struct(Vec<(String, Type)>),
 }
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
@@ -44,6 +151,14 @@ impl Signature {
             instruction_index: None,
         }
     }
+
+    /// The type of a reference to a function with this signature.
+    pub fn function_type(&self) -> Type {
+        Type::Function(Box::new(FunctionType {
+            parameters: self.parameters.iter().map(|(t, _)| t.clone()).collect(),
+            return_type: self.return_type.clone(),
+        }))
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -51,94 +166,3 @@ pub struct MemLoc {
     pub stack_level: usize,
     pub frame_index: usize,
 }
-
-#[derive(Debug, Clone)]
-pub struct Symbol {
-    pub lexeme: String,
-    pub symbol_type: SymbolType,
-    pub memory_location: Option<MemLoc>,
-}
-
-impl PartialEq for Symbol {
-    fn eq(&self, other: &Self) -> bool {
-        self.lexeme == other.lexeme
-    }
-}
-
-impl Eq for Symbol {}
-
-impl PartialOrd for Symbol {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.lexeme.cmp(&other.lexeme))
-    }
-}
-
-impl Ord for Symbol {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.lexeme.cmp(&other.lexeme)
-    }
-}
-
-impl Symbol {
-    pub fn new(lexeme: &str, symbol_type: SymbolType, mem_loc: Option<MemLoc>) -> Self {
-        Symbol {
-            lexeme: lexeme.to_string(),
-            symbol_type,
-            memory_location: mem_loc,
-        }
-    }
-}
-
-#[derive(Debug)]
-pub struct SymbolTable {
-    pub symbols: LinkedList<Symbol>,
-}
-
-impl SymbolTable {
-    pub fn new() -> Self {
-        SymbolTable {
-            symbols: LinkedList::new(),
-        }
-    }
-
-    pub fn token_to_type(&self, token: &str) -> Type {
-        match token {
-            "int" => Type::Int,
-            "float" => Type::Float,
-            "bool" => Type::Bool,
-            "colour" => Type::Colour,
-            _ => unreachable!(),
-        }
-    }
-
-    pub fn add_symbol(&mut self, lexeme: &str, symbol_type: &SymbolType, mem_loc: Option<MemLoc>) {
-        let mut index = 0;
-        let symbol = Symbol::new(lexeme, symbol_type.clone(), mem_loc);
-        for s in &self.symbols {
-            if s < &symbol {
-                break;
-            }
-            index += 1;
-        }
-
-        self.insert_at(index, symbol);
-    }
-
-    pub fn insert_at(&mut self, index: usize, symbol: Symbol) {
-        let mut tail = self.symbols.split_off(index);
-        self.symbols.push_back(symbol);
-        self.symbols.append(&mut tail);
-    }
-
-    pub fn find_symbol(&self, symbol: &str) -> Option<&Symbol> {
-        self.symbols.iter().find(|s| s.lexeme == symbol)
-    }
-
-    pub fn find_symbol_mut(&mut self, symbol: &str) -> Option<&mut Symbol> {
-        self.symbols.iter_mut().find(|s| s.lexeme == symbol)
-    }
-
-    pub fn all_symbols(&self) -> impl Iterator<Item = &Symbol> {
-        self.symbols.iter()
-    }
-}
@@ -1,15 +1,24 @@
+use std::collections::{HashMap, HashSet};
+
 use crate::core::TokenKind;
-use crate::semantics::utils::{Signature, Symbol, SymbolTable, SymbolType, Type};
+use crate::semantics::const_eval::{self, ConstError, ConstValue};
+use crate::semantics::hir::{Hir, MatchArm, Node, NodeKind, Place};
+use crate::semantics::symbol_table::{Symbol, SymbolId, SymbolTable};
+use crate::semantics::utils::{
+    channel_scale, literal_value, FunctionType, Signature, SymbolType, Type,
+};
 use crate::utils::errors::SemanticError;
 use crate::{
     core::Token,
-    parsing::ast::{AstNode, Visitor},
+    parsing::ast::{AstNode, TypeAnnotation, Visitor},
 };
 
 #[derive(Debug)]
 pub struct SemanticResult {
     pub errors: Vec<SemanticError>,
     pub warnings: Vec<SemanticError>,
+    /// The type inferred for each declaration without one, by identifier
+    pub inferred_types: Vec<(Token, Type)>,
 }
 
 impl SemanticResult {
@@ -17,6 +26,7 @@ impl SemanticResult {
         SemanticResult {
             errors: Vec::new(),
             warnings: Vec::new(),
+            inferred_types: Vec::new(),
         }
     }
 
@@ -39,13 +49,28 @@ impl SemanticResult {
 
 #[derive(Debug)]
 pub struct SemAnalyzer {
-    /// Stack of symbol tables, each representing a scope
-    symbol_table: Vec<SymbolTable>,
+    /// Every symbol declared so far, and the scopes currently open
+    symbol_table: SymbolTable,
     /// Flag to denote that the current scope lies within a function
     inside_function: bool,
     /// If this is 0, we can check for the existence of the symbol in any
     /// scope, up to the global scope.
     scope_peek_limit: usize,
+    /// Set when the next function call is a statement on its own, so its
+    /// result is discarded and it may return void.
+    discard_call_result: bool,
+    /// Set while checking the arguments of `__print`, the only place string
+    /// literals may appear
+    inside_print: bool,
+    /// Functions that receive copies of globals, directly or through the
+    /// functions they call. These can't be used as values.
+    global_users: HashSet<SymbolId>,
+    /// Every function used as a value, and where
+    function_values: Vec<(SymbolId, Token)>,
+    /// The fields of every struct declared so far
+    structs: HashMap<String, Vec<(String, Type)>>,
+    /// The analyzed program, once it's been analyzed without errors
+    hir: Option<Hir>,
     /// The results of the semantic analysis
     results: SemanticResult,
 }
@@ -53,66 +78,199 @@ pub struct SemAnalyzer {
 impl SemAnalyzer {
     pub fn new() -> Self {
         SemAnalyzer {
-            symbol_table: Vec::new(),
+            symbol_table: SymbolTable::new(),
             inside_function: false,
             scope_peek_limit: 0,
+            discard_call_result: false,
+            inside_print: false,
+            global_users: HashSet::new(),
+            function_values: Vec::new(),
+            structs: HashMap::new(),
+            hir: None,
             results: SemanticResult::new(),
         }
     }
 
     pub fn analyze(&mut self, ast: &AstNode) -> &SemanticResult {
-        self.visit(ast);
+        let program = self.visit(ast);
+
+        if let (NodeKind::Block(statements), false) = (program.kind, self.results.has_errors()) {
+            self.hir = Some(Hir {
+                statements,
+                symbols: std::mem::take(&mut self.symbol_table),
+                structs: std::mem::take(&mut self.structs),
+            });
+        }
+
         &self.results
     }
 
-    fn find_symbol(&self, symbol: &Token) -> Option<&Symbol> {
-        self.symbol_table
-            .iter()
-            .rev()
-            .find_map(|table| table.find_symbol(&symbol.span.lexeme))
+    /// The typed and resolved program, if it was analyzed without errors.
+    pub fn hir(&self) -> Option<&Hir> {
+        self.hir.as_ref()
     }
 
-    fn find_symbol_mut(&mut self, symbol: &Token) -> Option<&mut Symbol> {
-        self.symbol_table
-            .iter_mut()
-            .rev()
-            .find_map(|table| table.find_symbol_mut(&symbol.span.lexeme))
+    fn find_symbol(&self, symbol: &Token) -> Option<&Symbol> {
+        self.symbol_table.find(&symbol.span.lexeme)
     }
 
-    fn current_scope(&self) -> &SymbolTable {
-        self.symbol_table.last().unwrap()
+    /// Declares a symbol in the current scope, warning if it hides one from
+    /// an enclosing scope.
+    fn add_symbol(&mut self, symbol: &Token, symbol_type: &SymbolType) -> SymbolId {
+        let id = self.symbol_table.declare(symbol, symbol_type.clone());
+
+        if let Some(shadowed) = self.symbol_table.symbol(id).shadows {
+            let span = &self.symbol_table.symbol(shadowed).token.span;
+            let (line, col) = (span.from_line, span.from_col);
+            self.results
+                .add_warning(SemanticError::Shadowing(symbol.clone(), line, col));
+        }
+
+        id
     }
 
-    fn mut_current_scope(&mut self) -> &mut SymbolTable {
-        self.symbol_table.last_mut().unwrap()
+    /// The id of the symbol `symbol` refers to from here.
+    fn resolve(&self, symbol: &Token) -> Option<SymbolId> {
+        self.find_symbol(symbol).map(|s| s.id)
     }
 
-    fn add_symbol(&mut self, symbol: &Token, symbol_type: &SymbolType) {
-        self.mut_current_scope()
-            .add_symbol(&symbol.span.lexeme, symbol_type, None);
+    /// The id of the symbol declared as `symbol` in the current scope.
+    fn resolve_in_scope(&self, symbol: &Token) -> Option<SymbolId> {
+        self.symbol_table
+            .find_in_current_scope(&symbol.span.lexeme)
+            .map(|s| s.id)
     }
 
     fn get_symbol_type(&self, symbol: &Token) -> Type {
         self.find_symbol(symbol)
             .map(|s| match &s.symbol_type {
-                SymbolType::Variable(t) => *t,
-                SymbolType::Function(signature) => signature.return_type,
+                SymbolType::Variable(t) | SymbolType::Constant(t, _) => t.clone(),
+                SymbolType::Function(signature) => signature.function_type(),
+                SymbolType::Struct(_) => Type::Unknown,
             })
             .unwrap_or(Type::Unknown)
     }
 
-    fn get_signature(&self, symbol: &Token) -> Signature {
-        self.find_symbol(symbol)
-            .map(|s| match &s.symbol_type {
-                SymbolType::Function(signature) => signature.clone(),
-                _ => unreachable!(),
+    /// The signature of a function, or of the function referenced by a
+    /// variable of function type. Reports anything else being called.
+    fn get_signature(&mut self, symbol: &Token) -> Signature {
+        match self.find_symbol(symbol).map(|s| &s.symbol_type) {
+            Some(SymbolType::Function(signature)) => signature.clone(),
+            Some(SymbolType::Variable(Type::Function(function))) => Signature {
+                parameters: function
+                    .parameters
+                    .iter()
+                    .map(|t| (t.clone(), symbol.span.lexeme.clone()))
+                    .collect(),
+                ..Signature::new(function.return_type.clone())
+            },
+            Some(SymbolType::Variable(Type::Unknown)) | None => Signature::new(Type::Unknown),
+            Some(_) => {
+                let symbol_type = self.get_symbol_type(symbol);
+                self.results
+                    .add_error(SemanticError::NotCallable(symbol.clone(), symbol_type));
+                Signature::new(Type::Unknown)
+            }
+        }
+    }
+
+    /// Whether a function receives copies of globals: its body uses a global
+    /// variable, or calls a function that does. This mirrors the globals the
+    /// writer copies in for a function.
+    fn uses_globals(&self, body: &[Node]) -> bool {
+        let mut used = HashSet::new();
+        for node in body {
+            node.collect_symbols(&mut used);
+        }
+
+        used.iter().any(|id| {
+            let symbol = self.symbol_table.symbol(*id);
+            self.global_users.contains(id)
+                || (symbol.scope == 0 && matches!(symbol.symbol_type, SymbolType::Variable(_)))
+        })
+    }
+
+    fn get_struct_fields(&self, name: &str) -> Option<Vec<(String, Type)>> {
+        self.symbol_table
+            .visible(name)
+            .find_map(|symbol| match &symbol.symbol_type {
+                SymbolType::Struct(fields) => Some(fields.clone()),
+                _ => None,
             })
-            .unwrap_or(Signature::new(Type::Unknown))
+    }
+
+    /// Resolves a type annotation, reporting struct names that aren't
+    /// declared.
+    fn resolve_type(&mut self, annotation: &TypeAnnotation) -> Type {
+        match annotation {
+            TypeAnnotation::Named(token) => {
+                let resolved = Type::of_name(&token.span.lexeme);
+
+                if let Type::Struct(name) = &resolved {
+                    if self.get_struct_fields(name).is_none() {
+                        self.results
+                            .add_error(SemanticError::UndefinedType(token.clone()));
+                        return Type::Unknown;
+                    }
+                }
+
+                resolved
+            }
+            TypeAnnotation::Function {
+                params,
+                return_type,
+                ..
+            } => {
+                let parameters = params.iter().map(|p| self.resolve_type(p)).collect();
+                let return_type = match return_type {
+                    Some(return_type) => self.resolve_type(return_type),
+                    None => Type::Void,
+                };
+
+                Type::Function(Box::new(FunctionType {
+                    parameters,
+                    return_type,
+                }))
+            }
+        }
+    }
+
+    /// The type reached by following `fields` from a value of type `base`,
+    /// e.g. `int` for `rect.pos.x`.
+    fn get_field_type(&mut self, base: Type, fields: &[Token]) -> Type {
+        let mut current = base;
+
+        for field in fields {
+            let field_type = match &current {
+                Type::Unknown => return Type::Unknown,
+                Type::Struct(name) => self
+                    .get_struct_fields(name)
+                    .and_then(|struct_fields| {
+                        struct_fields
+                            .into_iter()
+                            .find(|(field_name, _)| *field_name == field.span.lexeme)
+                    })
+                    .map(|(_, field_type)| field_type),
+                Type::Colour => channel_scale(&field.span.lexeme).map(|_| Type::Int),
+                _ => None,
+            };
+
+            current = match field_type {
+                Some(field_type) => field_type,
+                None => {
+                    self.results
+                        .add_error(SemanticError::UnknownField(field.clone(), current));
+                    return Type::Unknown;
+                }
+            };
+        }
+
+        current
     }
 
     fn check_scope(&self, symbol: &Token) -> bool {
-        self.current_scope()
-            .find_symbol(&symbol.span.lexeme)
+        self.symbol_table
+            .find_in_current_scope(&symbol.span.lexeme)
             .is_some()
     }
 
@@ -181,57 +339,139 @@ impl SemAnalyzer {
         if expected != found {
             self.results.add_error(SemanticError::TypeMismatch(
                 token.to_string(),
-                *found,
-                *expected,
+                found.clone(),
+                expected.clone(),
             ));
         }
 
-        *found
+        found.clone()
+    }
+
+    /// Reports an error if the variable `token` can't be seen from here.
+    fn check_defined(&mut self, token: &Token) {
+        if self.inside_function {
+            if !self.check_up_to_scope(token) {
+                self.results
+                    .add_error(SemanticError::VarUndefinedInFunc(token.clone()));
+            }
+        } else if self.find_symbol(token).is_none() {
+            self.results
+                .add_error(SemanticError::UndefinedVariable(token.clone()));
+        }
+    }
+
+    /// Checks `target <op>= expression`, or `target++`/`target--` when there
+    /// is no expression, exactly like the expanded `target = target <op> expr`.
+    fn check_compound_assignment(
+        &mut self,
+        target: &str,
+        target_type: &Type,
+        operator: &Token,
+        expression: &Option<Box<AstNode>>,
+    ) -> (Option<Box<Node>>, Type) {
+        let expression = expression.as_ref().map(|e| Box::new(self.visit(e)));
+        let expression_type = match &expression {
+            Some(expression) => expression.ty.clone(),
+            None => Type::Int, // `x++` is `x = x + 1`
+        };
+
+        let bin_op = Token::new(
+            operator.kind.arithmetic_operator().unwrap(),
+            operator.span.clone(),
+        );
+        let result_type = self.get_bin_op_type(&bin_op, target_type, &expression_type);
+
+        let result_type = self.assert_type(&target.to_string(), target_type, &result_type);
+        (expression, result_type)
+    }
+
+    /// Reports an error if `identifier` can't be assigned to from here.
+    fn check_assignable(&mut self, identifier: &Token) {
+        self.check_defined(identifier);
+
+        if let Some(SymbolType::Constant(..)) = self.find_symbol(identifier).map(|s| &s.symbol_type)
+        {
+            self.results
+                .add_error(SemanticError::ConstAssignment(identifier.clone()));
+        }
+    }
+
+    /// Folds a constant's initializer using the constants currently in scope.
+    fn evaluate_constant(&self, expression: &AstNode) -> Result<ConstValue, ConstError> {
+        const_eval::evaluate(expression, &|token| match self.find_symbol(token) {
+            Some(Symbol {
+                symbol_type: SymbolType::Constant(_, value),
+                ..
+            }) => *value,
+            _ => None,
+        })
     }
 
     fn check_up_to_scope(&self, symbol: &Token) -> bool {
+        // Function bodies see their own scopes and the global scope
         self.symbol_table
-            .iter()
-            .skip(self.scope_peek_limit)
-            .find_map(|table| table.find_symbol(&symbol.span.lexeme))
-            .is_some()
+            .visible(&symbol.span.lexeme)
+            .any(|s| s.scope >= self.scope_peek_limit || s.scope == 0)
     }
 
-    fn visit_unscoped_block(&mut self, block: &AstNode) -> Type {
+    /// Visits the statements of a block in the current scope, up to the first
+    /// return. Also returns the type returned, or void if it doesn't return.
+    fn visit_unscoped_block(&mut self, block: &AstNode) -> (Vec<Node>, Type) {
         match block {
             AstNode::Block { statements } => {
-                let last = Type::Void;
+                let mut nodes = vec![];
                 for statement in statements {
-                    let last = self.visit(statement);
+                    let node = self.visit(statement);
                     if let AstNode::Return { .. } = statement {
-                        return last;
+                        let last = node.ty.clone();
+                        nodes.push(node);
+                        return (nodes, last);
                     }
+                    nodes.push(node);
                 }
-                last
+                (nodes, Type::Void)
             }
             _ => unreachable!(), // Unless called with a non-block node
         }
     }
     fn push_scope(&mut self) {
-        self.symbol_table.push(SymbolTable::new());
+        self.symbol_table.push_scope();
     }
 
     fn pop_scope(&mut self) {
-        self.symbol_table.pop();
+        self.symbol_table.pop_scope();
+    }
+
+    /// Merges the return type of a branch into the return type collected so
+    /// far, where `Void` means the branch doesn't return.
+    fn merge_branch_type(&mut self, name: &str, current: Type, branch: Type) -> Type {
+        match (&current, &branch) {
+            (Type::Void, _) => branch,
+            (_, Type::Void) => current,
+            _ if current != branch => {
+                self.results.add_error(SemanticError::TypeMismatch(
+                    name.to_string(),
+                    branch,
+                    current.clone(),
+                ));
+                current
+            }
+            _ => current,
+        }
     }
 
     fn check_cast(&mut self, to: &Token, from: Type) -> Type {
-        let to = self.current_scope().token_to_type(&to.span.lexeme);
+        let to = Type::of_name(&to.span.lexeme);
 
         if from == to {
             return from;
         }
 
-        match (from, to) {
+        match (&from, &to) {
             (Type::Int, Type::Float) => Type::Float,   // 5 -> 5.0
-            (Type::Colour, Type::Int) => Type::Int,    // 0xRRGGBB -> 0xRR + 0xGG + 0xBB
+            (Type::Colour, Type::Int) => Type::Int,    // #RRGGBB -> 0xRRGGBB
             (Type::Bool, Type::Int) => Type::Int,      // false -> 0, true -> 1
-            (Type::Int, Type::Colour) => Type::Colour, // 0xRR + 0xGG + 0xBB -> 0xRRGGBB
+            (Type::Int, Type::Colour) => Type::Colour, // 0xRRGGBB -> #RRGGBB, clamped to #000000..#ffffff
             (Type::Bool, Type::Float) => Type::Float,  // false -> 0.0, true -> 1.0
             _ => {
                 self.results.add_error(SemanticError::InvalidCast(from, to));
@@ -241,34 +481,48 @@ impl SemAnalyzer {
     }
 }
 
-impl Visitor<Type> for SemAnalyzer {
-    fn visit(&mut self, node: &AstNode) -> Type {
+impl Visitor<Node> for SemAnalyzer {
+    fn visit(&mut self, node: &AstNode) -> Node {
         match node {
             AstNode::Program { statements } => {
                 self.push_scope();
-                for statement in statements {
-                    self.visit(statement);
-                }
+                let statements = statements
+                    .iter()
+                    .map(|statement| self.visit(statement))
+                    .collect();
                 self.pop_scope();
 
-                Type::Void
+                // Indirect calls can't pass a function its copies of globals.
+                // Whether a function needs them is only known once its body is
+                // analyzed, and the body may refer to the function itself
+                for (function, token) in std::mem::take(&mut self.function_values) {
+                    if self.global_users.contains(&function) {
+                        self.results
+                            .add_error(SemanticError::FunctionValueUsesGlobals(token));
+                    }
+                }
+
+                Node::void(NodeKind::Block(statements))
             }
 
             AstNode::Block { statements } => {
                 self.push_scope();
+                let mut nodes = vec![];
                 let mut last = Type::Void;
                 for statement in statements {
                     // if the statement is a return statement, we don't need to
                     // check the rest of the block
-                    if let AstNode::Return { expression } = statement {
-                        last = self.visit(expression);
+                    if let AstNode::Return { .. } = statement {
+                        let node = self.visit(statement);
+                        last = node.ty.clone();
+                        nodes.push(node);
                         break;
                     } else {
-                        self.visit(statement);
+                        nodes.push(self.visit(statement));
                     }
                 }
                 self.pop_scope();
-                last
+                Node::new(NodeKind::Block(nodes), last)
             }
 
             AstNode::FunctionDecl {
@@ -278,45 +532,58 @@ impl Visitor<Type> for SemAnalyzer {
                 block,
             } => {
                 // Check that function name isn't already defined
-                if self.check_scope(identifier) {
+                let function = if self.check_scope(identifier) {
                     self.results
                         .add_error(SemanticError::FunctionAlreadyDefined(identifier.clone()));
+                    self.find_symbol(identifier).unwrap().id
                 } else {
                     // Add the function to the symbol table in the current scope
                     self.add_symbol(
                         identifier,
                         &SymbolType::Function(Signature::new(Type::Unknown)),
-                    );
-                }
+                    )
+                };
 
                 self.push_scope();
 
                 // all the parameters are added to the symbol table
                 // now we add them to the function signature
-                let mut signature =
-                    Signature::new(self.current_scope().token_to_type(&return_type.span.lexeme));
+                let return_type = match return_type.as_slice() {
+                    [] => Type::Void,
+                    [return_type] => self.resolve_type(return_type),
+                    types => Type::Tuple(types.iter().map(|t| self.resolve_type(t)).collect()),
+                };
+                let mut signature = Signature::new(return_type);
+                let mut param_ids = vec![];
 
-                for param in params.iter().rev() {
-                    let param_type = self.visit(param);
+                for param in params {
+                    let param_node = self.visit(param);
 
                     match param {
                         AstNode::FormalParam { identifier, .. } => {
                             signature
                                 .parameters
-                                .push((param_type, identifier.span.lexeme.clone()));
+                                .push((param_node.ty, identifier.span.lexeme.clone()));
                         }
                         _ => unreachable!(),
                     }
+                    if let NodeKind::Variable(id) = param_node.kind {
+                        param_ids.push(id);
+                    }
                 }
 
-                self.find_symbol_mut(identifier).unwrap().symbol_type =
+                self.symbol_table.symbol_mut(function).symbol_type =
                     SymbolType::Function(signature.clone());
 
                 self.inside_function = true;
-                self.scope_peek_limit = self.symbol_table.len() - 1;
-                let block_return_type = self.visit_unscoped_block(block);
+                self.scope_peek_limit = self.symbol_table.depth() - 1;
+                let (body, block_return_type) = self.visit_unscoped_block(block);
                 self.inside_function = false;
 
+                if self.uses_globals(&body) {
+                    self.global_users.insert(function);
+                }
+
                 if signature.return_type != block_return_type {
                     self.results
                         .add_error(SemanticError::FunctionReturnTypeMismatch(
@@ -328,29 +595,69 @@ impl Visitor<Type> for SemAnalyzer {
 
                 self.pop_scope();
 
-                Type::Void
+                Node::void(NodeKind::FunctionDecl {
+                    function,
+                    params: param_ids,
+                    body,
+                })
             }
 
             AstNode::Identifier { token } => {
-                if self.inside_function {
-                    if !self.check_up_to_scope(token) {
-                        self.results
-                            .add_error(SemanticError::VarUndefinedInFunc(token.clone()));
-                    }
-                } else if self.find_symbol(token).is_none() {
-                    self.results
-                        .add_error(SemanticError::UndefinedVariable(token.clone()));
+                self.check_defined(token);
+
+                // Constants are replaced by their value
+                let kind = match self.find_symbol(token) {
+                    Some(Symbol {
+                        symbol_type: SymbolType::Constant(_, Some(value)),
+                        ..
+                    }) => NodeKind::Constant(*value),
+                    Some(Symbol {
+                        symbol_type: SymbolType::Function(_),
+                        id,
+                        ..
+                    }) => NodeKind::Function(*id),
+                    Some(Symbol {
+                        symbol_type: SymbolType::Variable(_),
+                        id,
+                        ..
+                    }) => NodeKind::Variable(*id),
+                    _ => NodeKind::Error,
+                };
+
+                if let NodeKind::Function(function) = kind {
+                    self.function_values.push((function, token.clone()));
                 }
 
-                self.get_symbol_type(token)
+                Node::new(kind, self.get_symbol_type(token))
             }
 
             AstNode::VarDec {
                 identifier,
                 r#type: var_type,
                 expression,
+            }
+            | AstNode::ConstDec {
+                identifier,
+                r#type: var_type,
+                expression,
             } => {
-                let expr_type = self.visit(expression);
+                let expression_node = self.visit(expression);
+                let expr_type = expression_node.ty.clone();
+
+                let is_void = var_type.as_ref().is_some_and(TypeAnnotation::is_void);
+                if is_void {
+                    self.results
+                        .add_error(SemanticError::VoidVariable(identifier.clone()));
+                }
+
+                // Tuples only exist to be destructured, an annotated type
+                // already fails to match one
+                if let (None, Type::Tuple(types)) = (var_type, &expr_type) {
+                    self.results.add_error(SemanticError::TupleVariable(
+                        identifier.clone(),
+                        types.len(),
+                    ));
+                }
 
                 if self.check_scope(identifier) {
                     // get old type of the variable
@@ -360,31 +667,138 @@ impl Visitor<Type> for SemAnalyzer {
                         self.results.add_error(SemanticError::TypeMismatch(
                             identifier.span.lexeme.clone(),
                             old_type,
-                            expr_type,
+                            expr_type.clone(),
                         ));
                     } else {
                         self.results
                             .add_warning(SemanticError::VariableRedaclaration(identifier.clone()));
                     }
                 } else {
-                    self.add_symbol(
-                        identifier,
-                        &SymbolType::Variable(
-                            self.current_scope().token_to_type(&var_type.span.lexeme),
-                        ),
-                    );
+                    let var_type = match var_type {
+                        // The variable can't be used, and was already reported
+                        Some(_) if is_void => Type::Unknown,
+                        Some(var_type) => self.resolve_type(var_type),
+                        None => {
+                            if expr_type == Type::Unknown {
+                                self.results
+                                    .add_error(SemanticError::CannotInferType(identifier.clone()));
+                            }
+                            self.results
+                                .inferred_types
+                                .push((identifier.clone(), expr_type.clone()));
+                            expr_type.clone()
+                        }
+                    };
+                    let symbol_type = match node {
+                        AstNode::ConstDec { .. } => {
+                            let value = self.evaluate_constant(expression);
+                            let error = match value {
+                                // Type errors in the initializer are already reported
+                                Err(ConstError::NotConstant) if expr_type == Type::Unknown => None,
+                                Err(ConstError::NotConstant) => {
+                                    Some(SemanticError::NonConstantInitializer(identifier.clone()))
+                                }
+                                Err(ConstError::DivisionByZero) => {
+                                    Some(SemanticError::ConstantDivisionByZero(identifier.clone()))
+                                }
+                                Err(ConstError::Overflow) => {
+                                    Some(SemanticError::ConstantOverflow(identifier.clone()))
+                                }
+                                Ok(_) => None,
+                            };
+                            if let Some(error) = error {
+                                self.results.add_error(error);
+                            }
+                            SymbolType::Constant(var_type, value.ok())
+                        }
+                        _ => SymbolType::Variable(var_type),
+                    };
+                    self.add_symbol(identifier, &symbol_type);
                 }
 
-                self.assert_type(
-                    &identifier.span.lexeme,
-                    &self.current_scope().token_to_type(&var_type.span.lexeme),
-                    &expr_type,
-                );
+                if let (Some(var_type), false) = (var_type, is_void) {
+                    let declared_type = Type::of_annotation(var_type);
+                    self.assert_type(&identifier.span.lexeme, &declared_type, &expr_type);
+                }
+
+                match (node, self.resolve_in_scope(identifier)) {
+                    (AstNode::ConstDec { .. }, _) => Node::void(NodeKind::Empty),
+                    (_, Some(variable)) => Node::void(NodeKind::VarDec {
+                        variable,
+                        expression: Box::new(expression_node),
+                    }),
+                    (_, None) => Node::error(),
+                }
+            }
+
+            AstNode::TupleDec {
+                identifiers,
+                expression,
+            } => {
+                let expression = self.visit(expression);
+
+                let element_types = match &expression.ty {
+                    Type::Tuple(types) if types.len() == identifiers.len() => types.to_vec(),
+                    expr_type => {
+                        // Unknown types were already reported
+                        if *expr_type != Type::Unknown {
+                            self.results.add_error(SemanticError::DestructureMismatch(
+                                identifiers[0].clone(),
+                                identifiers.len(),
+                            ));
+                        }
+                        vec![Type::Unknown; identifiers.len()]
+                    }
+                };
+
+                let mut variables = vec![];
+                for (identifier, element_type) in identifiers.iter().zip(element_types) {
+                    if identifier.kind == TokenKind::Underscore {
+                        variables.push(None);
+                        continue;
+                    }
 
-                Type::Void
+                    if self.check_scope(identifier) {
+                        let old_type = self.get_symbol_type(identifier);
+                        if old_type != element_type {
+                            self.results.add_error(SemanticError::TypeMismatch(
+                                identifier.span.lexeme.clone(),
+                                old_type,
+                                element_type,
+                            ));
+                        } else {
+                            self.results
+                                .add_warning(SemanticError::VariableRedaclaration(
+                                    identifier.clone(),
+                                ));
+                        }
+                    } else {
+                        self.results
+                            .inferred_types
+                            .push((identifier.clone(), element_type.clone()));
+                        self.add_symbol(identifier, &SymbolType::Variable(element_type));
+                    }
+
+                    variables.push(self.resolve_in_scope(identifier));
+                }
+
+                Node::void(NodeKind::TupleDec {
+                    variables,
+                    expression: Box::new(expression),
+                })
+            }
+
+            AstNode::Tuple { elements } => {
+                let elements: Vec<Node> =
+                    elements.iter().map(|element| self.visit(element)).collect();
+                let tuple_type = Type::Tuple(elements.iter().map(|e| e.ty.clone()).collect());
+
+                Node::new(NodeKind::Tuple(elements), tuple_type)
             }
 
             AstNode::FunctionCall { identifier, args } => {
+                let is_statement = std::mem::take(&mut self.discard_call_result);
+
                 if self.find_symbol(identifier).is_none() {
                     self.results
                         .add_error(SemanticError::UndefinedFunction(identifier.clone()))
@@ -393,55 +807,92 @@ impl Visitor<Type> for SemAnalyzer {
                 let signature = self.get_signature(identifier);
 
                 if signature.return_type == Type::Unknown {
-                    return Type::Unknown;
+                    return Node::error();
                 }
 
-                let arg_types = args
-                    .iter()
-                    .map(|arg| self.visit(arg))
-                    .collect::<Vec<Type>>();
+                let args: Vec<Node> = args.iter().map(|arg| self.visit(arg)).collect();
+                let arg_types: Vec<Type> = args.iter().map(|arg| arg.ty.clone()).collect();
 
                 if signature.parameters.is_empty() && !arg_types.is_empty() {
                     self.results.add_error(SemanticError::FunctionCallNoParams(
                         identifier.span.lexeme.clone(),
                         arg_types,
                     ));
+                } else if signature.parameters.len() != arg_types.len() {
+                    self.results.add_error(SemanticError::ArgumentCountMismatch(
+                        identifier.clone(),
+                        signature.parameters.len(),
+                        arg_types.len(),
+                    ));
+                } else {
+                    // Make sure each argument is of the correct type
+                    for ((param_type, param), arg_type) in
+                        signature.parameters.iter().zip(&arg_types)
+                    {
+                        self.assert_type(param, param_type, arg_type);
+                    }
                 }
 
-                // Make sure each argument is of the correct type
-                for (idx, b) in args.iter().rev().enumerate() {
-                    let arg_type = self.visit(b);
-
-                    self.assert_type(
-                        &signature.parameters[idx].1,
-                        &signature.parameters[idx].0,
-                        &arg_type,
-                    );
+                if signature.return_type == Type::Void && !is_statement {
+                    self.results
+                        .add_error(SemanticError::VoidValue(identifier.clone()));
                 }
 
-                self.get_symbol_type(identifier)
+                let kind = match self.find_symbol(identifier) {
+                    Some(Symbol {
+                        symbol_type: SymbolType::Function(_),
+                        id,
+                        ..
+                    }) => NodeKind::Call {
+                        function: *id,
+                        args,
+                    },
+                    Some(Symbol { id, .. }) => NodeKind::IndirectCall {
+                        variable: *id,
+                        args,
+                    },
+                    None => NodeKind::Error,
+                };
+
+                Node::new(kind, signature.return_type)
+            }
+
+            AstNode::CallStatement { call } => {
+                self.discard_call_result = true;
+                let call = self.visit(call);
+
+                Node::void(NodeKind::CallStatement(Box::new(call)))
             }
 
             AstNode::FormalParam {
                 identifier,
                 param_type,
             } => {
-                self.add_symbol(
-                    identifier,
-                    &SymbolType::Variable(
-                        self.current_scope().token_to_type(&param_type.span.lexeme),
-                    ),
-                );
+                if param_type.is_void() {
+                    self.results
+                        .add_error(SemanticError::VoidVariable(identifier.clone()));
+                }
+
+                let param_type = self.resolve_type(param_type);
+                if self.check_scope(identifier) {
+                    self.results
+                        .add_error(SemanticError::VariableAlreadyDefined(identifier.clone()));
+                    return Node::new(NodeKind::Empty, param_type);
+                }
 
-                self.current_scope().token_to_type(&param_type.span.lexeme)
+                let id = self.add_symbol(identifier, &SymbolType::Variable(param_type.clone()));
+                Node::new(NodeKind::Variable(id), param_type)
             }
 
             AstNode::Expression { casted_type, expr } => {
-                let expr_type = self.visit(expr);
+                let expression = self.visit(expr);
 
                 match casted_type {
-                    Some(casted_type) => self.check_cast(casted_type, expr_type),
-                    None => expr_type,
+                    Some(casted_type) => {
+                        let cast_type = self.check_cast(casted_type, expression.ty.clone());
+                        Node::new(NodeKind::Cast(Box::new(expression)), cast_type)
+                    }
+                    None => expression,
                 }
             }
 
@@ -451,20 +902,245 @@ impl Visitor<Type> for SemAnalyzer {
                 identifier,
                 expression,
             } => {
-                if self.inside_function {
-                    if !self.check_up_to_scope(identifier) {
+                self.check_assignable(identifier);
+
+                let identifier_type = self.get_symbol_type(identifier);
+                let expression = self.visit(expression);
+
+                let result_type =
+                    self.assert_type(&identifier.span.lexeme, &identifier_type, &expression.ty);
+
+                match self.resolve(identifier) {
+                    Some(variable) => Node::new(
+                        NodeKind::Assignment {
+                            target: Place {
+                                variable,
+                                fields: vec![],
+                                ty: identifier_type,
+                            },
+                            expression: Box::new(expression),
+                        },
+                        result_type,
+                    ),
+                    None => Node::error(),
+                }
+            }
+
+            AstNode::CompoundAssignment {
+                identifier,
+                operator,
+                expression,
+            } => {
+                self.check_assignable(identifier);
+
+                let identifier_type = self.get_symbol_type(identifier);
+
+                let (expression, result_type) = self.check_compound_assignment(
+                    &identifier.span.lexeme,
+                    &identifier_type,
+                    operator,
+                    expression,
+                );
+
+                match self.resolve(identifier) {
+                    Some(variable) => Node::new(
+                        NodeKind::CompoundAssignment {
+                            target: Place {
+                                variable,
+                                fields: vec![],
+                                ty: identifier_type,
+                            },
+                            operator: operator.kind.arithmetic_operator().unwrap(),
+                            expression,
+                        },
+                        result_type,
+                    ),
+                    None => Node::error(),
+                }
+            }
+
+            AstNode::StructDecl { identifier, fields } => {
+                let mut field_types: Vec<(String, Type)> = vec![];
+
+                for field in fields {
+                    let AstNode::FormalParam {
+                        identifier: field,
+                        param_type,
+                    } = field
+                    else {
+                        unreachable!()
+                    };
+
+                    if param_type.is_void() {
+                        self.results
+                            .add_error(SemanticError::VoidVariable(field.clone()));
+                    }
+
+                    // A struct isn't declared until its closing brace, so it
+                    // can't contain itself
+                    let field_type = self.resolve_type(param_type);
+
+                    if field_types
+                        .iter()
+                        .any(|(name, _)| *name == field.span.lexeme)
+                    {
                         self.results
-                            .add_error(SemanticError::VarUndefinedInFunc(identifier.clone()));
+                            .add_error(SemanticError::DuplicateField(field.clone()));
+                    } else {
+                        field_types.push((field.span.lexeme.clone(), field_type));
                     }
-                } else if self.find_symbol(identifier).is_none() {
+                }
+
+                if self.check_scope(identifier) {
                     self.results
-                        .add_error(SemanticError::UndefinedVariable(identifier.clone()));
+                        .add_error(SemanticError::TypeAlreadyDefined(identifier.clone()));
+                } else {
+                    self.structs
+                        .insert(identifier.span.lexeme.clone(), field_types.clone());
+                    self.add_symbol(identifier, &SymbolType::Struct(field_types));
                 }
 
-                let identifier_type = self.get_symbol_type(identifier);
-                let expression_type = self.visit(expression);
+                Node::void(NodeKind::Empty)
+            }
+
+            AstNode::StructLiteral { identifier, fields } => {
+                let Some(struct_fields) = self.get_struct_fields(&identifier.span.lexeme) else {
+                    self.results
+                        .add_error(SemanticError::UndefinedType(identifier.clone()));
+                    return Node::error();
+                };
+                let struct_type = Type::Struct(identifier.span.lexeme.clone());
+
+                let mut given: Vec<&str> = vec![];
+                let mut values: Vec<Node> = vec![];
+                for (field, value) in fields {
+                    let value = self.visit(value);
+
+                    if given.contains(&field.span.lexeme.as_str()) {
+                        self.results
+                            .add_error(SemanticError::DuplicateField(field.clone()));
+                    }
+                    given.push(&field.span.lexeme);
+
+                    match struct_fields
+                        .iter()
+                        .find(|(name, _)| *name == field.span.lexeme)
+                    {
+                        Some((name, field_type)) => {
+                            self.assert_type(name, field_type, &value.ty);
+                        }
+                        None => self.results.add_error(SemanticError::UnknownField(
+                            field.clone(),
+                            struct_type.clone(),
+                        )),
+                    }
+
+                    values.push(value);
+                }
+
+                let mut ordered = vec![];
+                for (name, _) in &struct_fields {
+                    match given.iter().position(|field| field == name) {
+                        Some(index) => ordered.push(values[index].clone()),
+                        None => self.results.add_error(SemanticError::MissingField(
+                            identifier.clone(),
+                            name.clone(),
+                        )),
+                    }
+                }
+
+                Node::new(NodeKind::StructLiteral(ordered), struct_type)
+            }
+
+            AstNode::FieldAccess { identifier, fields } => {
+                self.check_defined(identifier);
+
+                // Looking at what holds the last field tells a channel of a
+                // colour apart from a struct field
+                let base_type = self.get_symbol_type(identifier);
+                let (last, path) = fields.split_last().unwrap();
+                let parent_type = self.get_field_type(base_type, path);
+                let field_type =
+                    self.get_field_type(parent_type.clone(), std::slice::from_ref(last));
+
+                let lexemes =
+                    |fields: &[Token]| fields.iter().map(|f| f.span.lexeme.clone()).collect();
+                let kind = match self.find_symbol(identifier) {
+                    Some(Symbol {
+                        symbol_type: SymbolType::Constant(_, Some(value)),
+                        ..
+                    }) => const_eval::evaluate(node, &|_| Some(*value))
+                        .map_or(NodeKind::Error, NodeKind::Constant),
+                    Some(Symbol { id, .. }) => match channel_scale(&last.span.lexeme) {
+                        Some(scale) if parent_type == Type::Colour => NodeKind::Channel {
+                            variable: *id,
+                            fields: lexemes(path),
+                            scale,
+                        },
+                        _ => NodeKind::Field {
+                            variable: *id,
+                            fields: lexemes(fields),
+                        },
+                    },
+                    None => NodeKind::Error,
+                };
+
+                Node::new(kind, field_type)
+            }
 
-                self.assert_type(&identifier.span.lexeme, &identifier_type, &expression_type)
+            AstNode::FieldAssignment {
+                identifier,
+                fields,
+                operator,
+                expression,
+            } => {
+                self.check_assignable(identifier);
+
+                // Colour channels are read-only, so look at what holds the
+                // last field before following it
+                let base_type = self.get_symbol_type(identifier);
+                let (last, path) = fields.split_last().unwrap();
+                let parent_type = self.get_field_type(base_type, path);
+                if parent_type == Type::Colour {
+                    self.results
+                        .add_error(SemanticError::ChannelAssignment(last.clone()));
+                }
+                let field_type = self.get_field_type(parent_type, std::slice::from_ref(last));
+                let target = std::iter::once(identifier)
+                    .chain(fields)
+                    .map(|t| t.span.lexeme.as_str())
+                    .collect::<Vec<_>>()
+                    .join(".");
+
+                let (expression, result_type) = match (operator.kind, expression) {
+                    (TokenKind::Equals, Some(expression)) => {
+                        let expression = self.visit(expression);
+                        let result_type = self.assert_type(&target, &field_type, &expression.ty);
+                        (Some(Box::new(expression)), result_type)
+                    }
+                    _ => self.check_compound_assignment(&target, &field_type, operator, expression),
+                };
+
+                let Some(variable) = self.resolve(identifier) else {
+                    return Node::error();
+                };
+                let target = Place {
+                    variable,
+                    fields: fields.iter().map(|f| f.span.lexeme.clone()).collect(),
+                    ty: field_type,
+                };
+                let kind = match (operator.kind, expression) {
+                    (TokenKind::Equals, Some(expression)) => {
+                        NodeKind::Assignment { target, expression }
+                    }
+                    (_, expression) => NodeKind::CompoundAssignment {
+                        target,
+                        operator: operator.kind.arithmetic_operator().unwrap(),
+                        expression,
+                    },
+                };
+
+                Node::new(kind, result_type)
             }
 
             AstNode::BinOp {
@@ -472,89 +1148,166 @@ impl Visitor<Type> for SemAnalyzer {
                 operator,
                 right,
             } => {
-                let left_type = self.visit(left);
-                let right_type = self.visit(right);
+                let left = self.visit(left);
+                let right = self.visit(right);
+
+                let result_type = self.get_bin_op_type(operator, &left.ty, &right.ty);
+                Node::new(
+                    NodeKind::BinOp {
+                        left: Box::new(left),
+                        operator: operator.kind,
+                        right: Box::new(right),
+                    },
+                    result_type,
+                )
+            }
 
-                self.get_bin_op_type(operator, &left_type, &right_type)
+            AstNode::Conditional {
+                condition,
+                if_true,
+                if_false,
+            } => {
+                let condition = self.visit(condition);
+                self.assert_type(&"?: condition".to_string(), &Type::Bool, &condition.ty);
+
+                let if_true = self.visit(if_true);
+                let if_false = self.visit(if_false);
+                self.assert_type(&"?:".to_string(), &if_true.ty, &if_false.ty);
+
+                let true_type = if_true.ty.clone();
+                Node::new(
+                    NodeKind::Conditional {
+                        condition: Box::new(condition),
+                        if_true: Box::new(if_true),
+                        if_false: Box::new(if_false),
+                    },
+                    true_type,
+                )
             }
 
             AstNode::UnaryOp { operator, expr } => {
-                let expr_type = self.visit(expr);
-
-                self.get_unary_op_type(operator, &expr_type)
+                let expression = self.visit(expr);
+
+                let result_type = self.get_unary_op_type(operator, &expression.ty);
+                Node::new(
+                    NodeKind::UnaryOp {
+                        operator: operator.kind,
+                        expression: Box::new(expression),
+                    },
+                    result_type,
+                )
             }
 
-            AstNode::PadWidth => Type::Int,
+            AstNode::PadWidth => Node::new(NodeKind::PadWidth, Type::Int),
+
+            AstNode::Rgb { red, green, blue } => {
+                let [red, green, blue] =
+                    [("red", red), ("green", green), ("blue", blue)].map(|(name, channel)| {
+                        let channel = self.visit(channel);
+                        self.assert_type(&name.to_string(), &Type::Int, &channel.ty);
+                        Box::new(channel)
+                    });
+
+                Node::new(NodeKind::Rgb { red, green, blue }, Type::Colour)
+            }
 
             AstNode::PadRandI { upper_bound } => {
-                let upper_bound_type = self.visit(upper_bound);
+                let upper_bound = self.visit(upper_bound);
 
-                if upper_bound_type != Type::Int {
+                if upper_bound.ty != Type::Int {
                     self.results.add_error(SemanticError::TypeMismatch(
                         "upper_bound".to_string(),
-                        upper_bound_type,
+                        upper_bound.ty.clone(),
                         Type::Int,
                     ));
                 }
 
-                Type::Int
+                Node::new(NodeKind::PadRandI(Box::new(upper_bound)), Type::Int)
             }
 
-            AstNode::PadHeight => Type::Int,
+            AstNode::PadHeight => Node::new(NodeKind::PadHeight, Type::Int),
 
             AstNode::PadRead { x, y } => {
-                let x_type = self.visit(x);
-                let y_type = self.visit(y);
+                let x = self.visit(x);
+                let y = self.visit(y);
 
-                if x_type != Type::Int {
+                if x.ty != Type::Int {
                     self.results.add_error(SemanticError::TypeMismatch(
                         "__read <x>, y".to_string(),
-                        x_type,
+                        x.ty.clone(),
                         Type::Int,
                     ));
                 }
 
-                if y_type != Type::Int {
+                if y.ty != Type::Int {
                     self.results.add_error(SemanticError::TypeMismatch(
                         "__read x, <y>".to_string(),
-                        y_type,
+                        y.ty.clone(),
                         Type::Int,
                     ));
                 }
 
-                Type::Int
+                Node::new(
+                    NodeKind::PadRead {
+                        x: Box::new(x),
+                        y: Box::new(y),
+                    },
+                    Type::Int,
+                )
             }
 
-            AstNode::IntLiteral(_) => Type::Int,
+            AstNode::IntLiteral(literal)
+            | AstNode::FloatLiteral(literal)
+            | AstNode::BoolLiteral(literal)
+            | AstNode::ColourLiteral(literal) => Node::new(
+                const_eval::evaluate(node, &|_| None).map_or(NodeKind::Error, NodeKind::Constant),
+                Type::of_literal(literal),
+            ),
 
-            AstNode::FloatLiteral(_) => Type::Float,
-
-            AstNode::BoolLiteral(_) => Type::Bool,
+            AstNode::StringLiteral(token) => {
+                if !self.inside_print {
+                    self.results
+                        .add_error(SemanticError::StringOutsidePrint(token.clone()));
+                }
 
-            AstNode::ColourLiteral(_) => Type::Colour,
+                Node::new(
+                    token
+                        .string_value()
+                        .map_or(NodeKind::Error, NodeKind::String),
+                    Type::String,
+                )
+            }
 
+            // Never produced by the parser, calls keep their arguments
             AstNode::ActualParams { params } => {
                 for param in params {
                     self.visit(param);
                 }
 
-                Type::Void
+                Node::void(NodeKind::Empty)
             }
             AstNode::Delay { expression } => {
-                let delay_ms_type = self.visit(expression);
+                let delay = self.visit(expression);
 
-                if delay_ms_type != Type::Int {
+                if delay.ty != Type::Int {
                     self.results.add_error(SemanticError::TypeMismatch(
                         "delay".to_string(),
-                        delay_ms_type,
+                        delay.ty.clone(),
                         Type::Int,
                     ));
                 }
 
-                Type::Void
+                Node::void(NodeKind::Delay(Box::new(delay)))
             }
 
-            AstNode::Return { expression } => self.visit(expression),
+            AstNode::Return { expression } => match expression {
+                Some(expression) => {
+                    let expression = self.visit(expression);
+                    let return_type = expression.ty.clone();
+                    Node::new(NodeKind::Return(Some(Box::new(expression))), return_type)
+                }
+                None => Node::void(NodeKind::Return(None)),
+            },
 
             AstNode::PadWriteBox {
                 loc_x,
@@ -563,53 +1316,35 @@ impl Visitor<Type> for SemAnalyzer {
                 height,
                 colour,
             } => {
-                let loc_x_type = self.visit(loc_x);
-                let loc_y_type = self.visit(loc_y);
-                let width_type = self.visit(width);
-                let height_type = self.visit(height);
-                let colour_type = self.visit(colour);
-
-                if loc_x_type != Type::Int {
-                    self.results.add_error(SemanticError::TypeMismatch(
-                        "loc_x".to_string(),
-                        loc_x_type,
-                        Type::Int,
-                    ));
-                }
-
-                if loc_y_type != Type::Int {
-                    self.results.add_error(SemanticError::TypeMismatch(
-                        "loc_y".to_string(),
-                        loc_y_type,
-                        Type::Int,
-                    ));
-                }
-
-                if width_type != Type::Int {
-                    self.results.add_error(SemanticError::TypeMismatch(
-                        "width".to_string(),
-                        width_type,
-                        Type::Int,
-                    ));
-                }
-
-                if height_type != Type::Int {
-                    self.results.add_error(SemanticError::TypeMismatch(
-                        "height".to_string(),
-                        height_type,
-                        Type::Int,
-                    ));
-                }
-
-                if colour_type != Type::Colour {
-                    self.results.add_error(SemanticError::TypeMismatch(
-                        "colour".to_string(),
-                        colour_type,
-                        Type::Colour,
-                    ));
+                let loc_x = self.visit(loc_x);
+                let loc_y = self.visit(loc_y);
+                let width = self.visit(width);
+                let height = self.visit(height);
+                let colour = self.visit(colour);
+
+                for (name, node, expected) in [
+                    ("loc_x", &loc_x, Type::Int),
+                    ("loc_y", &loc_y, Type::Int),
+                    ("width", &width, Type::Int),
+                    ("height", &height, Type::Int),
+                    ("colour", &colour, Type::Colour),
+                ] {
+                    if node.ty != expected {
+                        self.results.add_error(SemanticError::TypeMismatch(
+                            name.to_string(),
+                            node.ty.clone(),
+                            expected,
+                        ));
+                    }
                 }
 
-                Type::Void
+                Node::void(NodeKind::PadWriteBox {
+                    loc_x: Box::new(loc_x),
+                    loc_y: Box::new(loc_y),
+                    width: Box::new(width),
+                    height: Box::new(height),
+                    colour: Box::new(colour),
+                })
             }
 
             AstNode::PadWrite {
@@ -617,57 +1352,63 @@ impl Visitor<Type> for SemAnalyzer {
                 loc_y,
                 colour,
             } => {
-                let loc_x_type = self.visit(loc_x);
-                let loc_y_type = self.visit(loc_y);
-                let colour_type = self.visit(colour);
-
-                if loc_x_type != Type::Int {
-                    self.results.add_error(SemanticError::TypeMismatch(
-                        "loc_x".to_string(),
-                        loc_x_type,
-                        Type::Int,
-                    ));
+                let loc_x = self.visit(loc_x);
+                let loc_y = self.visit(loc_y);
+                let colour = self.visit(colour);
+
+                for (name, node, expected) in [
+                    ("loc_x", &loc_x, Type::Int),
+                    ("loc_y", &loc_y, Type::Int),
+                    ("colour", &colour, Type::Colour),
+                ] {
+                    if node.ty != expected {
+                        self.results.add_error(SemanticError::TypeMismatch(
+                            name.to_string(),
+                            node.ty.clone(),
+                            expected,
+                        ));
+                    }
                 }
 
-                if loc_y_type != Type::Int {
-                    self.results.add_error(SemanticError::TypeMismatch(
-                        "loc_y".to_string(),
-                        loc_y_type,
-                        Type::Int,
-                    ));
-                }
-
-                if colour_type != Type::Colour {
-                    self.results.add_error(SemanticError::TypeMismatch(
-                        "colour".to_string(),
-                        colour_type,
-                        Type::Colour,
-                    ));
-                }
-
-                Type::Void
-            }
+                Node::void(NodeKind::PadWrite {
+                    loc_x: Box::new(loc_x),
+                    loc_y: Box::new(loc_y),
+                    colour: Box::new(colour),
+                })
+            }
 
             AstNode::If {
                 condition,
                 if_true,
                 if_false,
             } => {
-                self.visit(condition);
-                let true_branch_return_type = self.visit_unscoped_block(if_true);
-                if let Some(if_false) = if_false {
-                    let false_branch_return_type = self.visit_unscoped_block(if_false);
-
-                    if true_branch_return_type != false_branch_return_type {
-                        self.results.add_error(SemanticError::TypeMismatch(
-                            "if".to_string(),
-                            true_branch_return_type,
-                            false_branch_return_type,
-                        ));
+                let condition = self.visit(condition);
+                let (if_true, true_branch_return_type) = self.visit_unscoped_block(if_true);
+                let if_false = match if_false {
+                    Some(if_false) => {
+                        let (if_false, false_branch_return_type) =
+                            self.visit_unscoped_block(if_false);
+
+                        if true_branch_return_type != false_branch_return_type {
+                            self.results.add_error(SemanticError::TypeMismatch(
+                                "if".to_string(),
+                                true_branch_return_type.clone(),
+                                false_branch_return_type,
+                            ));
+                        }
+                        Some(if_false)
                     }
-                }
-
-                true_branch_return_type
+                    None => None,
+                };
+
+                Node::new(
+                    NodeKind::If {
+                        condition: Box::new(condition),
+                        if_true,
+                        if_false,
+                    },
+                    true_branch_return_type,
+                )
             }
 
             AstNode::For {
@@ -678,75 +1419,203 @@ impl Visitor<Type> for SemAnalyzer {
             } => {
                 self.push_scope();
 
-                if let Some(initializer) = initializer {
-                    self.visit(initializer);
-                }
+                let initializer = initializer
+                    .as_ref()
+                    .map(|initializer| Box::new(self.visit(initializer)));
 
-                let condition_type = self.visit(condition);
+                let condition = self.visit(condition);
 
-                if condition_type != Type::Bool {
+                if condition.ty != Type::Bool {
                     self.results.add_error(SemanticError::TypeMismatch(
                         "for condition".to_string(),
-                        condition_type,
+                        condition.ty.clone(),
                         Type::Bool,
                     ));
                 }
 
-                if let Some(increment) = increment {
-                    self.visit(increment);
-                }
+                let increment = increment
+                    .as_ref()
+                    .map(|increment| Box::new(self.visit(increment)));
 
-                let body_type = self.visit_unscoped_block(body);
-                self.symbol_table.pop();
+                let (body, body_type) = self.visit_unscoped_block(body);
+                self.pop_scope();
 
-                body_type
+                Node::new(
+                    NodeKind::For {
+                        initializer,
+                        condition: Box::new(condition),
+                        increment,
+                        body,
+                    },
+                    body_type,
+                )
             }
 
             AstNode::While { condition, body } => {
                 self.push_scope();
-                let condition_type = self.visit(condition);
-                if condition_type != Type::Bool {
+                let condition = self.visit(condition);
+                if condition.ty != Type::Bool {
                     self.results.add_error(SemanticError::TypeMismatch(
                         "while".to_string(),
-                        condition_type,
+                        condition.ty.clone(),
                         Type::Bool,
                     ));
                 }
-                let body_return_type = self.visit_unscoped_block(body);
+                let (body, body_return_type) = self.visit_unscoped_block(body);
                 self.pop_scope();
 
-                body_return_type
+                Node::new(
+                    NodeKind::While {
+                        condition: Box::new(condition),
+                        body,
+                    },
+                    body_return_type,
+                )
             }
 
-            AstNode::Print { expression } => {
-                let print_expr_type = self.visit(expression);
+            AstNode::Match {
+                expression,
+                arms,
+                default,
+            } => {
+                let expression = self.visit(expression);
+                let scrutinee_type = expression.ty.clone();
 
-                if print_expr_type == Type::Void || print_expr_type == Type::Unknown {
+                if !matches!(scrutinee_type, Type::Int | Type::Colour | Type::Bool) {
                     self.results.add_error(SemanticError::TypeMismatchUnion(
-                        "__print <expr>".to_string(),
-                        print_expr_type,
-                        vec![Type::Int, Type::Float, Type::Bool, Type::Colour],
+                        "match <expr>".to_string(),
+                        scrutinee_type.clone(),
+                        vec![Type::Int, Type::Colour, Type::Bool],
                     ));
                 }
 
-                Type::Void
+                let mut covered: Vec<(usize, usize)> = vec![];
+                let mut return_type = Type::Void;
+                let mut match_arms = vec![];
+
+                for arm in arms {
+                    let AstNode::MatchArm { from, to, body } = arm else {
+                        unreachable!()
+                    };
+
+                    let mut pattern_ok = true;
+                    for bound in std::iter::once(from).chain(to) {
+                        let bound_type = Type::of_literal(bound);
+                        if scrutinee_type != Type::Unknown && bound_type != scrutinee_type {
+                            self.assert_type(&bound.span.lexeme, &scrutinee_type, &bound_type);
+                            pattern_ok = false;
+                        }
+                    }
+
+                    // Only read when the pattern is fine, errors skip codegen
+                    let mut bounds = (0, None);
+                    if pattern_ok {
+                        let lower = literal_value(from);
+                        let upper = to.as_ref().map(literal_value).unwrap_or(lower);
+
+                        if let Some(to) = to {
+                            if scrutinee_type == Type::Bool || lower > upper {
+                                self.results
+                                    .add_error(SemanticError::InvalidMatchRange(format!(
+                                        "{}..{}",
+                                        from, to
+                                    )));
+                            }
+                        }
+
+                        if covered.iter().any(|&(lo, hi)| lower <= hi && lo <= upper) {
+                            self.results
+                                .add_error(SemanticError::DuplicateMatchArm(from.clone()));
+                        }
+
+                        covered.push((lower, upper));
+                        bounds = (lower, to.as_ref().map(|_| upper));
+                    }
+
+                    let (body, arm_type) = self.visit_unscoped_block(body);
+                    return_type = self.merge_branch_type("match", return_type, arm_type);
+                    match_arms.push(MatchArm {
+                        from: bounds.0,
+                        to: bounds.1,
+                        body,
+                    });
+                }
+
+                let default = match default {
+                    Some(default) => {
+                        let (default, default_type) = self.visit_unscoped_block(default);
+                        return_type = self.merge_branch_type("match", return_type, default_type);
+                        Some(default)
+                    }
+                    None if scrutinee_type == Type::Bool => {
+                        for (value, name) in [(1, "true"), (0, "false")] {
+                            if !covered.iter().any(|&(lo, hi)| lo <= value && value <= hi) {
+                                self.results.add_warning(SemanticError::NonExhaustiveMatch(
+                                    name.to_string(),
+                                ));
+                            }
+                        }
+                        None
+                    }
+                    None => None,
+                };
+
+                Node::new(
+                    NodeKind::Match {
+                        expression: Box::new(expression),
+                        arms: match_arms,
+                        default,
+                    },
+                    return_type,
+                )
+            }
+
+            AstNode::MatchArm { .. } => unreachable!(), // Handled by `AstNode::Match`
+
+            AstNode::Print { expressions } => {
+                let mut nodes = vec![];
+                for expression in expressions {
+                    self.inside_print = true;
+                    let expression = self.visit(expression);
+                    self.inside_print = false;
+
+                    if matches!(
+                        expression.ty,
+                        Type::Void | Type::Unknown | Type::Struct(_) | Type::Tuple(_)
+                    ) {
+                        self.results.add_error(SemanticError::TypeMismatchUnion(
+                            "__print <expr>".to_string(),
+                            expression.ty.clone(),
+                            vec![
+                                Type::Int,
+                                Type::Float,
+                                Type::Bool,
+                                Type::Colour,
+                                Type::String,
+                            ],
+                        ));
+                    }
+                    nodes.push(expression);
+                }
+
+                Node::void(NodeKind::Print(nodes))
             }
 
             AstNode::PadClear { expr } => {
-                let clear_expr_type = self.visit(expr);
+                let colour = self.visit(expr);
 
-                if clear_expr_type != Type::Colour {
+                if colour.ty != Type::Colour {
                     self.results.add_error(SemanticError::TypeMismatch(
                         "__clear <expr>".to_string(),
-                        clear_expr_type,
+                        colour.ty.clone(),
                         Type::Colour,
                     ));
                 }
 
-                Type::Void
+                Node::void(NodeKind::PadClear(Box::new(colour)))
             }
 
-            AstNode::EndOfFile => Type::Void,
+            AstNode::EndOfFile => Node::void(NodeKind::Empty),
         }
     }
 }
@@ -756,8 +1625,8 @@ mod tests {
     use crate::{
         lexing::Lexer,
         parsing::Parser,
-        semantics::utils::{SymbolType, Type},
-        utils::{errors::Error, SimpleBuffer},
+        semantics::utils::Type,
+        utils::{errors::Error, Utf8Buffer},
     };
 
     use super::*;
@@ -766,7 +1635,7 @@ mod tests {
     use std::path::Path;
 
     fn run_scope_checker(input: &str) -> Result<(), Error> {
-        let mut lexer: Lexer<SimpleBuffer> = Lexer::new(input, Path::new(""), None);
+        let mut lexer: Lexer<Utf8Buffer> = Lexer::new(input, None);
 
         let tokens = lexer.lex().unwrap();
 
@@ -779,28 +1648,18 @@ mod tests {
         Ok(())
     }
 
-    #[rstest]
-    fn test_symbol_table() {
-        let mut symbol_table = SymbolTable::new();
+    fn run_analyzer(input: &str) -> SemanticResult {
+        let mut lexer: Lexer<Utf8Buffer> = Lexer::new(input, None);
 
-        symbol_table.add_symbol("x", &SymbolType::Variable(Type::Int), None);
-        symbol_table.add_symbol("y", &SymbolType::Variable(Type::Float), None);
-        symbol_table.add_symbol("z", &SymbolType::Variable(Type::Bool), None);
+        let tokens = lexer.lex().unwrap();
 
-        assert_matches!(
-            symbol_table.find_symbol("x").unwrap().symbol_type,
-            SymbolType::Variable(Type::Int)
-        );
+        let mut parser = Parser::new(&tokens, Path::new(""));
+        let ast = parser.parse().unwrap();
 
-        assert_matches!(
-            symbol_table.find_symbol("y").unwrap().symbol_type,
-            SymbolType::Variable(Type::Float)
-        );
+        let mut analyzer = SemAnalyzer::new();
+        analyzer.visit(ast);
 
-        assert_matches!(
-            symbol_table.find_symbol("z").unwrap().symbol_type,
-            SymbolType::Variable(Type::Bool)
-        );
+        analyzer.results
     }
 
     #[rstest]
@@ -823,4 +1682,627 @@ mod tests {
 
         assert!(run_scope_checker(input).is_ok());
     }
+
+    #[rstest]
+    fn test_else_if_chain() {
+        let input = r#"
+            let x: int = 5;
+            if (x < 0) {
+                __print 0;
+            } else if (x < 10) {
+                __print 1;
+            } else {
+                __print 2;
+            }
+        "#;
+
+        let result = run_analyzer(input);
+        assert!(!result.has_errors());
+    }
+
+    #[rstest]
+    fn test_match() {
+        let input = r#"
+            let x: int = 5;
+            match (x) {
+                0 => { __print 0; }
+                1..9 => { __print 1; }
+                _ => { __print 2; }
+            }
+
+            let c: colour = #ff0000;
+            match (c) {
+                #ff0000 => { __print 0; }
+                #00ff00..#00ffff => { __print 1; }
+            }
+        "#;
+
+        let result = run_analyzer(input);
+        assert!(!result.has_errors());
+        assert!(!result.has_warnings());
+    }
+
+    #[rstest]
+    fn test_match_errors() {
+        let input = r#"
+            let x: int = 5;
+            match (x) {
+                0..5 => { __print 0; }
+                5 => { __print 1; }
+                true => { __print 2; }
+                9..7 => { __print 3; }
+            }
+        "#;
+
+        let result = run_analyzer(input);
+        assert_matches!(
+            result.errors.as_slice(),
+            [
+                SemanticError::DuplicateMatchArm(_),
+                SemanticError::TypeMismatch(..),
+                SemanticError::InvalidMatchRange(_),
+            ]
+        );
+    }
+
+    #[rstest]
+    fn test_match_bool_exhaustiveness() {
+        let input = r#"
+            let b: bool = true;
+            match (b) {
+                true => { __print 1; }
+            }
+        "#;
+
+        let result = run_analyzer(input);
+        assert!(!result.has_errors());
+        assert_matches!(
+            result.warnings.as_slice(),
+            [SemanticError::NonExhaustiveMatch(missing)] if missing == "false"
+        );
+    }
+
+    #[rstest]
+    fn test_void_functions() {
+        let input = r#"
+            fun draw(x: int) -> void {
+                __write x, x, #ff0000;
+            }
+
+            fun clear() {
+                __clear #000000;
+                return;
+            }
+
+            fun twice(x: int) -> int {
+                return x * 2;
+            }
+
+            draw(1);
+            clear();
+            twice(4);
+        "#;
+
+        let result = run_analyzer(input);
+        assert!(!result.has_errors());
+    }
+
+    #[rstest]
+    fn test_void_used_as_value() {
+        let input = r#"
+            fun clear() {
+                __clear #000000;
+            }
+
+            __print clear();
+        "#;
+
+        let result = run_analyzer(input);
+        assert_matches!(result.errors.first(), Some(SemanticError::VoidValue(_)));
+    }
+
+    #[rstest]
+    fn test_void_variable() {
+        let input = r#"
+            let w: void = 3;
+            const k: void = 4;
+        "#;
+
+        let result = run_analyzer(input);
+        assert_matches!(
+            result.errors.as_slice(),
+            [
+                SemanticError::VoidVariable(w),
+                SemanticError::VoidVariable(k),
+            ] if w.span.lexeme == "w" && k.span.lexeme == "k"
+        );
+    }
+
+    #[rstest]
+    fn test_compound_assignment() {
+        let input = r#"
+            let x: int = 0;
+            let f: float = 1.0;
+            for (let i: int = 0; i < 10; i++) {
+                x += i;
+                x %= 7;
+                f *= 2.0;
+            }
+            x--;
+            x += 1.5;
+            f %= 2.0;
+        "#;
+
+        let result = run_analyzer(input);
+        assert_matches!(
+            result.errors.as_slice(),
+            [
+                SemanticError::TypeMismatch(..),
+                SemanticError::InvalidOperation(_),
+                SemanticError::TypeMismatch(..),
+            ]
+        );
+    }
+
+    #[rstest]
+    fn test_globals_in_functions() {
+        let input = r#"
+            let count: int = 0;
+            const step: int = 2;
+
+            fun bump() -> int {
+                count = count + step;
+                return count;
+            }
+
+            __print bump();
+        "#;
+
+        let result = run_analyzer(input);
+        assert!(!result.has_errors());
+    }
+
+    #[rstest]
+    fn test_function_locals_stay_private() {
+        let input = r#"
+            {
+                let hidden: int = 0;
+                fun peek() -> int {
+                    return hidden;
+                }
+            }
+        "#;
+
+        let result = run_analyzer(input);
+        assert_matches!(
+            result.errors.as_slice(),
+            [SemanticError::VarUndefinedInFunc(_)]
+        );
+    }
+
+    #[rstest]
+    fn test_const_assignment() {
+        let input = r#"
+            const size: int = 10;
+
+            fun grow() {
+                size = size + 1;
+            }
+
+            size += 1;
+        "#;
+
+        let result = run_analyzer(input);
+        assert_matches!(
+            result.errors.as_slice(),
+            [
+                SemanticError::ConstAssignment(_),
+                SemanticError::ConstAssignment(_)
+            ]
+        );
+    }
+
+    #[rstest]
+    fn test_const_initializers() {
+        let input = r#"
+            const width: int = 32;
+            const half: int = (width / 2) - 1;
+            const ratio: float = width / 3 as float;
+            const sky: colour = #87ceeb;
+            const wide: bool = not (half < 8);
+
+            {
+                const inner: int = half * 2;
+                __print inner;
+            }
+        "#;
+
+        let result = run_analyzer(input);
+        assert!(!result.has_errors());
+    }
+
+    #[rstest]
+    fn test_non_constant_initializers() {
+        let input = r#"
+            let x: int = 4;
+
+            fun five() -> int {
+                return 5;
+            }
+
+            const a: int = x + 1;
+            const b: int = five();
+            const c: int = __width;
+        "#;
+
+        let result = run_analyzer(input);
+        assert_matches!(
+            result.errors.as_slice(),
+            [
+                SemanticError::NonConstantInitializer(_),
+                SemanticError::NonConstantInitializer(_),
+                SemanticError::NonConstantInitializer(_),
+            ]
+        );
+    }
+
+    #[rstest]
+    fn test_failing_constant_initializers() {
+        let input = r#"
+            const a: int = 1 / 0;
+            const b: int = 5 % (2 - 2);
+            const c: int = 9223372036854775807 + 1;
+            const d: int = -(-9223372036854775807 - 1);
+            const e: float = 1.0 / 0.0;
+            const f: float = 3.0e38 * 10.0;
+            const g: float = 2.5 / 0.5;
+        "#;
+
+        let result = run_analyzer(input);
+        assert_matches!(
+            result.errors.as_slice(),
+            [
+                SemanticError::ConstantDivisionByZero(a),
+                SemanticError::ConstantDivisionByZero(b),
+                SemanticError::ConstantOverflow(c),
+                SemanticError::ConstantOverflow(d),
+                SemanticError::ConstantDivisionByZero(e),
+                SemanticError::ConstantOverflow(f),
+            ] if [a, b, c, d, e, f].map(|t| t.span.lexeme.as_str()) == ["a", "b", "c", "d", "e", "f"]
+        );
+    }
+
+    #[rstest]
+    fn test_structs() {
+        let input = r#"
+            struct Point { x: int, y: int }
+            struct Rect {
+                pos: Point,
+                w: int,
+                h: int,
+                c: colour,
+            }
+
+            fun area(r: Rect) -> int {
+                return r.w * r.h;
+            }
+
+            fun moved(r: Rect, dx: int) -> Rect {
+                r.pos.x += dx;
+                return r;
+            }
+
+            let r: Rect = Rect { pos: Point { x: 0, y: 0 }, w: 4, h: 2, c: #ff0000 };
+            r = moved(r, 3);
+            r.w++;
+            __write_box r.pos.x, r.pos.y, r.w, r.h, r.c;
+            __print area(r);
+        "#;
+
+        let result = run_analyzer(input);
+        assert!(!result.has_errors(), "{:?}", result.errors);
+    }
+
+    #[rstest]
+    fn test_struct_errors() {
+        let input = r#"
+            struct Point { x: int, x: float }
+            struct Node { next: Node }
+            struct Point { z: int }
+
+            let a: Shape = 1;
+            let p: Point = Point { x: 1, x: 2, y: 3 };
+            let q: Point = Point { };
+            __print p.z;
+            p.x = true;
+        "#;
+
+        let result = run_analyzer(input);
+        assert_matches!(
+            result.errors.as_slice(),
+            [
+                SemanticError::DuplicateField(_),
+                SemanticError::UndefinedType(_),
+                SemanticError::TypeAlreadyDefined(_),
+                SemanticError::UndefinedType(_),
+                SemanticError::TypeMismatch(..),
+                SemanticError::DuplicateField(_),
+                SemanticError::UnknownField(..),
+                SemanticError::MissingField(..),
+                SemanticError::UnknownField(..),
+                SemanticError::TypeMismatchUnion(..),
+                SemanticError::TypeMismatch(..),
+            ]
+        );
+    }
+
+    #[rstest]
+    fn test_strings_only_printed() {
+        let input = r#"
+            let x: int = 4;
+            __print "x = ", x, "\n";
+
+            let s: int = "four";
+            __print "a" + "b";
+        "#;
+
+        let result = run_analyzer(input);
+        assert_matches!(
+            result.errors.as_slice(),
+            [
+                SemanticError::StringOutsidePrint(_),
+                SemanticError::TypeMismatch(..),
+                SemanticError::InvalidOperation(_),
+                SemanticError::TypeMismatchUnion(..),
+            ]
+        );
+    }
+
+    #[rstest]
+    fn test_colour_channels() {
+        let input = r#"
+            let c: colour = rgb(255, 128, 0);
+            let r: int = c.r + c.g + c.b;
+            c = c + rgb(1, 2, 3) / #010101;
+            c -= #000001;
+
+            let x: colour = rgb(1.0, 2, true);
+            c.r = 4;
+            let y: int = c.a;
+        "#;
+
+        let result = run_analyzer(input);
+        assert_matches!(
+            result.errors.as_slice(),
+            [
+                SemanticError::TypeMismatch(..),
+                SemanticError::TypeMismatch(..),
+                SemanticError::ChannelAssignment(_),
+                SemanticError::UnknownField(..),
+                SemanticError::TypeMismatch(..),
+            ]
+        );
+    }
+
+    #[rstest]
+    fn test_let_type_inference() {
+        let input = r#"
+            struct Point { x: int, y: int }
+            fun origin() -> Point { return Point { x: 0, y: 0 }; }
+
+            let n = 4 * 2;
+            let p = origin();
+            const half = 0.5;
+            let c = __read n, p.y;
+            let m: float = half * 2.0;
+            let x = missing;
+        "#;
+
+        let result = run_analyzer(input);
+        assert_matches!(
+            result.errors.as_slice(),
+            [
+                SemanticError::UndefinedVariable(_),
+                SemanticError::CannotInferType(_)
+            ]
+        );
+
+        let inferred: Vec<_> = result
+            .inferred_types
+            .iter()
+            .map(|(token, t)| (token.span.lexeme.as_str(), t.clone()))
+            .collect();
+        assert_eq!(
+            inferred,
+            [
+                ("n", Type::Int),
+                ("p", Type::Struct("Point".to_string())),
+                ("half", Type::Float),
+                ("c", Type::Int),
+                ("x", Type::Unknown),
+            ]
+        );
+    }
+
+    #[rstest]
+    fn test_conditional_expressions() {
+        let input = r#"
+            let x: int = 3;
+            let c: colour = x > 2 and x < 5 ? #ff0000 : x == 0 ? #00ff00 : #0000ff;
+            const k: int = true ? 1 : 2;
+
+            let y: int = x ? 1 : 2;
+            let z: int = x > 2 ? 1 : 2.0;
+        "#;
+
+        let result = run_analyzer(input);
+        assert_matches!(
+            result.errors.as_slice(),
+            [
+                SemanticError::TypeMismatch(..),
+                SemanticError::TypeMismatch(..),
+            ]
+        );
+    }
+
+    #[rstest]
+    fn test_tuple_returns() {
+        let input = r#"
+            fun centre(w: int, h: int) -> (int, int) {
+                return (w / 2, h / 2);
+            }
+
+            let (x, y) = centre(__width, __height);
+            let (_, half) = (1, 0.5);
+            let sum: int = x + y;
+
+            fun wrong() -> (int, float) { return (1, 2); }
+            let (a, b, c) = centre(1, 2);
+            let (d, e) = 4;
+            __print centre(1, 2);
+        "#;
+
+        let result = run_analyzer(input);
+        assert_matches!(
+            result.errors.as_slice(),
+            [
+                SemanticError::FunctionReturnTypeMismatch(..),
+                SemanticError::DestructureMismatch(_, 3),
+                SemanticError::DestructureMismatch(_, 2),
+                SemanticError::TypeMismatchUnion(..),
+            ]
+        );
+    }
+
+    #[rstest]
+    fn test_tuple_variables() {
+        let input = r#"
+            fun centre(w: int, h: int) -> (int, int) {
+                return (w / 2, h / 2);
+            }
+
+            let pair = centre(1, 2);
+            let triple = (1, 2.0, true);
+            let x: int = centre(1, 2);
+        "#;
+
+        let result = run_analyzer(input);
+        assert_matches!(
+            result.errors.as_slice(),
+            [
+                SemanticError::TupleVariable(pair, 2),
+                SemanticError::TupleVariable(triple, 3),
+                SemanticError::TypeMismatch(x, ..),
+            ] if pair.span.lexeme == "pair" && triple.span.lexeme == "triple" && x == "x"
+        );
+    }
+
+    #[rstest]
+    fn test_function_references() {
+        let input = r#"
+            let tint: colour = #ff0000;
+
+            fun stripes(x: int, y: int) -> colour { return x % 2 == 0 ? #ffffff : #000000; }
+            fun tinted(x: int, y: int) -> colour { return tint; }
+            fun fill_with(shader: fun(int, int) -> colour) -> colour { return shader(0, 0); }
+
+            let a: colour = fill_with(stripes);
+            let s = stripes;
+            let b: colour = s(1, 2);
+
+            let c: colour = fill_with(tinted);
+            let d: int = stripes;
+            let e: colour = s(1);
+            let f: int = tint(1);
+        "#;
+
+        let result = run_analyzer(input);
+        assert_matches!(
+            result.errors.as_slice(),
+            [
+                SemanticError::TypeMismatch(..),
+                SemanticError::ArgumentCountMismatch(_, 2, 1),
+                SemanticError::NotCallable(..),
+                SemanticError::TypeMismatch(..),
+                SemanticError::FunctionValueUsesGlobals(token),
+            ] if token.span.lexeme == "tinted"
+        );
+    }
+
+    #[rstest]
+    fn test_function_values_shadowing_globals() {
+        let input = r#"
+            let x: int = 1;
+            fun f(x: int) -> int { return x; }
+            fun g(y: int) -> int { let x: int = y; return x; }
+            let a: fun(int) -> int = f;
+            let b: fun(int) -> int = g;
+
+            fun h(y: int) -> int { let self: fun(int) -> int = h; return x; }
+        "#;
+
+        let result = run_analyzer(input);
+        assert_matches!(
+            result.errors.as_slice(),
+            [SemanticError::FunctionValueUsesGlobals(token)] if token.span.from_line == 8
+        );
+    }
+
+    #[rstest]
+    fn test_duplicate_parameters() {
+        let input = r#"
+            fun f(x: int, x: int) -> int { return x; }
+            let y: int = f(1, 2);
+        "#;
+
+        let result = run_analyzer(input);
+        assert_matches!(
+            result.errors.as_slice(),
+            [SemanticError::VariableAlreadyDefined(token)] if token.span.from_col == 27
+        );
+    }
+
+    #[rstest]
+    fn test_shadowing_warning() {
+        let input = r#"
+            let x: int = 1;
+            fun f(x: int) -> int { return x; }
+        "#;
+
+        let result = run_analyzer(input);
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        assert_matches!(
+            result.warnings.as_slice(),
+            [SemanticError::Shadowing(token, 2, 17)] if token.span.from_line == 3
+        );
+    }
+
+    #[rstest]
+    fn test_parameter_named_like_function() {
+        let input = r#"
+            fun f(f: int) -> int { return f; }
+            let x: int = f(2);
+        "#;
+
+        let result = run_analyzer(input);
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+    }
+
+    #[rstest]
+    fn test_function_type_annotations() {
+        let input = r#"
+            fun each(g: fun(int)) { g(1); }
+            fun print(x: int) { __print x; }
+            each(print);
+
+            fun broken(g: fun(int, Missing) -> int) -> int { return 0; }
+        "#;
+
+        let result = run_analyzer(input);
+        assert_matches!(
+            result.errors.as_slice(),
+            [SemanticError::UndefinedType(token)]
+                if token.span.lexeme == "Missing" && token.span.from_col == 36
+        );
+    }
 }
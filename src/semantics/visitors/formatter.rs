@@ -4,7 +4,7 @@ use std::{
     path::Path,
 };
 
-use crate::parsing::ast::{AstNode, Visitor};
+use crate::parsing::ast::{AstNode, TypeAnnotation, Visitor};
 use crate::utils::Result;
 
 pub struct Formatter {
@@ -23,13 +23,27 @@ impl Formatter {
     }
 }
 
+/// A single return type as-is, or the types of a tuple in parentheses.
+fn format_return_type(return_type: &[TypeAnnotation]) -> String {
+    let types: Vec<String> = return_type.iter().map(|t| t.to_string()).collect();
+
+    match types.as_slice() {
+        [] => "void".to_string(),
+        [single] => single.clone(),
+        _ => format!("({})", types.join(", ")),
+    }
+}
+
 impl Visitor<Result<()>> for Formatter {
     fn visit(&mut self, node: &AstNode) -> Result<()> {
         match node {
             AstNode::Program { statements } => {
                 for statement in statements {
                     self.visit(statement)?;
-                    if let AstNode::Assignment { .. } = statement {
+                    if let AstNode::Assignment { .. }
+                    | AstNode::CompoundAssignment { .. }
+                    | AstNode::FieldAssignment { .. } = statement
+                    {
                         writeln!(self.buff, ";")?;
                     }
                     writeln!(self.buff, "\n")?;
@@ -43,7 +57,24 @@ impl Visitor<Result<()>> for Formatter {
                 expression,
             } => {
                 write!(self.buff, "let {}", identifier.span.lexeme)?;
-                write!(self.buff, ": {}", var_type.span.lexeme)?;
+                if let Some(var_type) = var_type {
+                    write!(self.buff, ": {}", var_type)?;
+                }
+                write!(self.buff, " = ")?;
+                self.visit(expression)?;
+                write!(self.buff, ";")?;
+                Ok(())
+            }
+
+            AstNode::ConstDec {
+                identifier,
+                r#type: var_type,
+                expression,
+            } => {
+                write!(self.buff, "const {}", identifier.span.lexeme)?;
+                if let Some(var_type) = var_type {
+                    write!(self.buff, ": {}", var_type)?;
+                }
                 write!(self.buff, " = ")?;
                 self.visit(expression)?;
                 write!(self.buff, ";")?;
@@ -57,9 +88,14 @@ impl Visitor<Result<()>> for Formatter {
                 Ok(())
             }
 
-            AstNode::Print { expression } => {
+            AstNode::Print { expressions } => {
                 write!(self.buff, "__print ")?;
-                self.visit(expression)?;
+                for (i, expression) in expressions.iter().enumerate() {
+                    if i > 0 {
+                        write!(self.buff, ", ")?;
+                    }
+                    self.visit(expression)?;
+                }
                 write!(self.buff, ";")?;
                 Ok(())
             }
@@ -76,8 +112,18 @@ impl Visitor<Result<()>> for Formatter {
                 self.visit(if_true)?;
                 self.tab_level = tmp_tab_level;
                 if let Some(if_false) = if_false {
-                    write!(self.buff, " else")?;
-                    self.visit(if_false)?;
+                    match if_false.as_ref() {
+                        AstNode::Block { statements }
+                            if matches!(statements.as_slice(), [AstNode::If { .. }]) =>
+                        {
+                            write!(self.buff, " else ")?;
+                            self.visit(&statements[0])?;
+                        }
+                        _ => {
+                            write!(self.buff, " else")?;
+                            self.visit(if_false)?;
+                        }
+                    }
                 }
 
                 Ok(())
@@ -92,6 +138,76 @@ impl Visitor<Result<()>> for Formatter {
                 Ok(())
             }
 
+            AstNode::CompoundAssignment {
+                identifier,
+                operator,
+                expression,
+            } => {
+                write!(self.buff, "{}", identifier.span.lexeme)?;
+                match expression {
+                    Some(expression) => {
+                        write!(self.buff, " {} ", operator.span.lexeme)?;
+                        self.visit(expression)?;
+                    }
+                    None => write!(self.buff, "{}", operator.span.lexeme)?,
+                }
+                Ok(())
+            }
+
+            AstNode::StructDecl { identifier, fields } => {
+                writeln!(self.buff, "struct {} {{", identifier.span.lexeme)?;
+                self.tab_level += 1;
+                for field in fields {
+                    write!(self.buff, "{}", "\t".repeat(self.tab_level))?;
+                    self.visit(field)?;
+                    writeln!(self.buff, ",")?;
+                }
+                self.tab_level -= 1;
+                write!(self.buff, "{}}}", "\t".repeat(self.tab_level))?;
+                Ok(())
+            }
+
+            AstNode::StructLiteral { identifier, fields } => {
+                write!(self.buff, "{} {{ ", identifier.span.lexeme)?;
+                for (i, (field, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(self.buff, ", ")?;
+                    }
+                    write!(self.buff, "{}: ", field.span.lexeme)?;
+                    self.visit(value)?;
+                }
+                write!(self.buff, " }}")?;
+                Ok(())
+            }
+
+            AstNode::FieldAccess { identifier, fields } => {
+                write!(self.buff, "{}", identifier.span.lexeme)?;
+                for field in fields {
+                    write!(self.buff, ".{}", field.span.lexeme)?;
+                }
+                Ok(())
+            }
+
+            AstNode::FieldAssignment {
+                identifier,
+                fields,
+                operator,
+                expression,
+            } => {
+                write!(self.buff, "{}", identifier.span.lexeme)?;
+                for field in fields {
+                    write!(self.buff, ".{}", field.span.lexeme)?;
+                }
+                match expression {
+                    Some(expression) => {
+                        write!(self.buff, " {} ", operator.span.lexeme)?;
+                        self.visit(expression)?;
+                    }
+                    None => write!(self.buff, "{}", operator.span.lexeme)?,
+                }
+                Ok(())
+            }
+
             AstNode::For {
                 initializer,
                 condition,
@@ -109,7 +225,10 @@ impl Visitor<Result<()>> for Formatter {
                     } = node.as_ref()
                     {
                         write!(self.buff, "let {}", identifier.span.lexeme)?;
-                        write!(self.buff, ": {} = ", var_type.span.lexeme)?;
+                        if let Some(var_type) = var_type {
+                            write!(self.buff, ": {}", var_type)?;
+                        }
+                        write!(self.buff, " = ")?;
                         self.visit(expression)?;
                     }
                 }
@@ -128,8 +247,17 @@ impl Visitor<Result<()>> for Formatter {
             }
 
             AstNode::Return { expression } => {
-                write!(self.buff, "return ")?;
-                self.visit(expression)?;
+                write!(self.buff, "return")?;
+                if let Some(expression) = expression {
+                    write!(self.buff, " ")?;
+                    self.visit(expression)?;
+                }
+                write!(self.buff, ";")?;
+                Ok(())
+            }
+
+            AstNode::CallStatement { call } => {
+                self.visit(call)?;
                 write!(self.buff, ";")?;
                 Ok(())
             }
@@ -140,7 +268,10 @@ impl Visitor<Result<()>> for Formatter {
                 for statement in statements {
                     write!(self.buff, "{}", "\t".repeat(self.tab_level))?;
                     self.visit(statement)?;
-                    if let AstNode::Assignment { .. } = statement {
+                    if let AstNode::Assignment { .. }
+                    | AstNode::CompoundAssignment { .. }
+                    | AstNode::FieldAssignment { .. } = statement
+                    {
                         writeln!(self.buff, ";")?;
                     } else {
                         writeln!(self.buff)?;
@@ -157,11 +288,8 @@ impl Visitor<Result<()>> for Formatter {
                 expr: bin_op,
             } => {
                 self.visit(bin_op)?;
-                match casted_type {
-                    Some(casted_type) => {
-                        write!(self.buff, " as {}", casted_type.span.lexeme)?;
-                    }
-                    None => {}
+                if let Some(casted_type) = casted_type {
+                    write!(self.buff, " as {}", casted_type.span.lexeme)?;
                 }
                 Ok(())
             }
@@ -183,7 +311,7 @@ impl Visitor<Result<()>> for Formatter {
                 write!(self.buff, "(")?;
 
                 if params.is_empty() {
-                    write!(self.buff, ") -> {}", return_type.span.lexeme)?;
+                    write!(self.buff, ") -> {}", format_return_type(return_type))?;
                     self.visit(block)?;
                     return Ok(());
                 } else {
@@ -197,7 +325,7 @@ impl Visitor<Result<()>> for Formatter {
                     }
                 }
 
-                write!(self.buff, ") -> {}", return_type.span.lexeme)?;
+                write!(self.buff, ") -> {}", format_return_type(return_type))?;
                 self.visit(block)?;
                 Ok(())
             }
@@ -213,6 +341,40 @@ impl Visitor<Result<()>> for Formatter {
                 Ok(())
             }
 
+            AstNode::Match {
+                expression,
+                arms,
+                default,
+            } => {
+                write!(self.buff, "match (")?;
+                self.visit(expression)?;
+                writeln!(self.buff, ") {{")?;
+                self.tab_level += 1;
+                for arm in arms {
+                    write!(self.buff, "{}", "\t".repeat(self.tab_level))?;
+                    self.visit(arm)?;
+                    writeln!(self.buff)?;
+                }
+                if let Some(default) = default {
+                    write!(self.buff, "{}_ =>", "\t".repeat(self.tab_level))?;
+                    self.visit(default)?;
+                    writeln!(self.buff)?;
+                }
+                self.tab_level -= 1;
+                write!(self.buff, "{}}}", "\t".repeat(self.tab_level))?;
+                Ok(())
+            }
+
+            AstNode::MatchArm { from, to, body } => {
+                write!(self.buff, "{}", from.span.lexeme)?;
+                if let Some(to) = to {
+                    write!(self.buff, "..{}", to.span.lexeme)?;
+                }
+                write!(self.buff, " =>")?;
+                self.visit(body)?;
+                Ok(())
+            }
+
             AstNode::PadWrite {
                 loc_x,
                 loc_y,
@@ -288,11 +450,55 @@ impl Visitor<Result<()>> for Formatter {
                 identifier,
                 param_type,
             } => {
-                write!(
-                    self.buff,
-                    "{}: {}",
-                    identifier.span.lexeme, param_type.span.lexeme
-                )?;
+                write!(self.buff, "{}: {}", identifier.span.lexeme, param_type)?;
+                Ok(())
+            }
+
+            AstNode::Tuple { elements } => {
+                write!(self.buff, "(")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(self.buff, ", ")?;
+                    }
+                    self.visit(element)?;
+                }
+                write!(self.buff, ")")?;
+                Ok(())
+            }
+
+            AstNode::TupleDec {
+                identifiers,
+                expression,
+            } => {
+                let identifiers: Vec<&str> =
+                    identifiers.iter().map(|i| i.span.lexeme.as_str()).collect();
+                write!(self.buff, "let ({}) = ", identifiers.join(", "))?;
+                self.visit(expression)?;
+                write!(self.buff, ";")?;
+                Ok(())
+            }
+
+            AstNode::Conditional {
+                condition,
+                if_true,
+                if_false,
+            } => {
+                self.visit(condition)?;
+                write!(self.buff, " ? ")?;
+                self.visit(if_true)?;
+                write!(self.buff, " : ")?;
+                self.visit(if_false)?;
+                Ok(())
+            }
+
+            AstNode::Rgb { red, green, blue } => {
+                write!(self.buff, "rgb(")?;
+                self.visit(red)?;
+                write!(self.buff, ", ")?;
+                self.visit(green)?;
+                write!(self.buff, ", ")?;
+                self.visit(blue)?;
+                write!(self.buff, ")")?;
                 Ok(())
             }
 
@@ -345,6 +551,10 @@ impl Visitor<Result<()>> for Formatter {
                 write!(self.buff, "{}", token.span.lexeme)?;
                 Ok(())
             }
+            AstNode::StringLiteral(token) => {
+                write!(self.buff, "{}", token.span.lexeme)?;
+                Ok(())
+            }
             AstNode::PadWidth => {
                 write!(self.buff, "__width")?;
                 Ok(())
@@ -1,13 +1,20 @@
+use crate::core::Token;
 use crate::parsing::ast::{AstNode, Visitor};
+use crate::semantics::utils::Type;
 use crate::utils::Result;
 
 pub struct TreePrinter {
     tab_level: usize,
+    /// Types inferred by the analyzer for declarations without one
+    inferred_types: Vec<(Token, Type)>,
 }
 
 impl TreePrinter {
-    pub fn new() -> Self {
-        Self { tab_level: 0 }
+    pub fn new(inferred_types: Vec<(Token, Type)>) -> Self {
+        Self {
+            tab_level: 0,
+            inferred_types,
+        }
     }
 
     fn tab(&self) -> String {
@@ -38,13 +45,31 @@ impl Visitor<Result<()>> for TreePrinter {
                 identifier,
                 r#type: var_type,
                 expression,
+            }
+            | AstNode::ConstDec {
+                identifier,
+                r#type: var_type,
+                expression,
             } => {
-                println!("VarDec");
+                match node {
+                    AstNode::ConstDec { .. } => println!("ConstDec"),
+                    _ => println!("VarDec"),
+                }
                 self.tab_level += 1;
                 self.print_tab();
                 println!("Identifier: {}", identifier);
                 self.print_tab();
-                println!("Type: {}", var_type);
+                match var_type {
+                    Some(var_type) => println!("Type: {}", var_type),
+                    None => match self
+                        .inferred_types
+                        .iter()
+                        .find(|(t, _)| t.span == identifier.span)
+                    {
+                        Some((_, inferred)) => println!("Type: {} (inferred)", inferred),
+                        None => println!("Type: inferred"),
+                    },
+                }
                 self.print_tab();
                 print!("Expression: ");
                 self.visit(expression)?;
@@ -62,12 +87,15 @@ impl Visitor<Result<()>> for TreePrinter {
                 Ok(())
             }
 
-            AstNode::Print { expression } => {
+            AstNode::Print { expressions } => {
                 println!("Print");
                 self.tab_level += 1;
-                self.print_tab();
-                print!("Expression: ");
-                self.visit(expression)?;
+                for expression in expressions {
+                    self.print_tab();
+                    print!("Expression: ");
+                    self.visit(expression)?;
+                    println!();
+                }
                 self.tab_level -= 1;
                 Ok(())
             }
@@ -110,6 +138,89 @@ impl Visitor<Result<()>> for TreePrinter {
                 Ok(())
             }
 
+            AstNode::CompoundAssignment {
+                identifier,
+                operator,
+                expression,
+            } => {
+                println!("CompoundAssignment");
+                self.tab_level += 1;
+                self.print_tab();
+                println!("Identifier: {}", identifier);
+                self.print_tab();
+                print!("Operator: {}", operator);
+                if let Some(expression) = expression {
+                    println!();
+                    self.print_tab();
+                    print!("Expression: ");
+                    self.visit(expression)?;
+                }
+                self.tab_level -= 1;
+                Ok(())
+            }
+
+            AstNode::StructDecl { identifier, fields } => {
+                println!("StructDecl");
+                self.tab_level += 1;
+                self.print_tab();
+                println!("Identifier: {}", identifier);
+                self.print_tab();
+                print!("Fields: ");
+                for field in fields {
+                    self.visit(field)?;
+                    print!(", ");
+                }
+                self.tab_level -= 1;
+                Ok(())
+            }
+
+            AstNode::StructLiteral { identifier, fields } => {
+                print!("{} {{ ", identifier.span.lexeme);
+                for (i, (field, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        print!(", ");
+                    }
+                    print!("{}: ", field.span.lexeme);
+                    self.visit(value)?;
+                }
+                print!(" }}");
+                Ok(())
+            }
+
+            AstNode::FieldAccess { identifier, fields } => {
+                print!("{}", identifier.span.lexeme);
+                for field in fields {
+                    print!(".{}", field.span.lexeme);
+                }
+                Ok(())
+            }
+
+            AstNode::FieldAssignment {
+                identifier,
+                fields,
+                operator,
+                expression,
+            } => {
+                println!("FieldAssignment");
+                self.tab_level += 1;
+                self.print_tab();
+                print!("Field: {}", identifier);
+                for field in fields {
+                    print!(".{}", field.span.lexeme);
+                }
+                println!();
+                self.print_tab();
+                print!("Operator: {}", operator);
+                if let Some(expression) = expression {
+                    println!();
+                    self.print_tab();
+                    print!("Expression: ");
+                    self.visit(expression)?;
+                }
+                self.tab_level -= 1;
+                Ok(())
+            }
+
             AstNode::For {
                 initializer,
                 condition,
@@ -151,10 +262,22 @@ impl Visitor<Result<()>> for TreePrinter {
 
             AstNode::Return { expression } => {
                 println!("Return");
+                if let Some(expression) = expression {
+                    self.tab_level += 1;
+                    self.print_tab();
+                    print!("Expression: ");
+                    self.visit(expression)?;
+                    self.tab_level -= 1;
+                }
+                Ok(())
+            }
+
+            AstNode::CallStatement { call } => {
+                println!("CallStatement");
                 self.tab_level += 1;
                 self.print_tab();
-                print!("Expression: ");
-                self.visit(expression)?;
+                print!("Call: ");
+                self.visit(call)?;
                 self.tab_level -= 1;
                 Ok(())
             }
@@ -209,7 +332,11 @@ impl Visitor<Result<()>> for TreePrinter {
                 }
                 println!();
                 self.print_tab();
-                println!("Return Type: {}", return_type);
+                let return_type: Vec<String> = return_type.iter().map(|t| t.to_string()).collect();
+                match return_type.as_slice() {
+                    [] => println!("Return Type: void"),
+                    types => println!("Return Type: {}", types.join(", ")),
+                }
                 self.print_tab();
                 print!("Block: ");
                 self.visit(block)?;
@@ -230,6 +357,40 @@ impl Visitor<Result<()>> for TreePrinter {
                 Ok(())
             }
 
+            AstNode::Match {
+                expression,
+                arms,
+                default,
+            } => {
+                println!("Match");
+                self.tab_level += 1;
+                self.print_tab();
+                print!("Expression: ");
+                self.visit(expression)?;
+                println!();
+                for arm in arms {
+                    self.print_tab();
+                    self.visit(arm)?;
+                }
+                if let Some(default) = default {
+                    self.print_tab();
+                    print!("Default: ");
+                    self.visit(default)?;
+                }
+                self.tab_level -= 1;
+                Ok(())
+            }
+
+            AstNode::MatchArm { from, to, body } => {
+                print!("Arm {}", from);
+                if let Some(to) = to {
+                    print!("..{}", to);
+                }
+                print!(": ");
+                self.visit(body)?;
+                Ok(())
+            }
+
             AstNode::PadWrite {
                 loc_x,
                 loc_y,
@@ -305,7 +466,70 @@ impl Visitor<Result<()>> for TreePrinter {
                 identifier,
                 param_type,
             } => {
-                print!("{}: {}", identifier.span.lexeme, param_type.span.lexeme);
+                print!("{}: {}", identifier.span.lexeme, param_type);
+                Ok(())
+            }
+
+            AstNode::Tuple { elements } => {
+                print!("(");
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        print!(", ");
+                    }
+                    self.visit(element)?;
+                }
+                print!(")");
+                Ok(())
+            }
+
+            AstNode::TupleDec {
+                identifiers,
+                expression,
+            } => {
+                println!("TupleDec");
+                self.tab_level += 1;
+                for identifier in identifiers {
+                    self.print_tab();
+                    print!("Identifier: {}", identifier);
+                    match self
+                        .inferred_types
+                        .iter()
+                        .find(|(t, _)| t.span == identifier.span)
+                    {
+                        Some((_, inferred)) => println!(" ({} inferred)", inferred),
+                        None => println!(),
+                    }
+                }
+                self.print_tab();
+                print!("Expression: ");
+                self.visit(expression)?;
+                self.tab_level -= 1;
+                Ok(())
+            }
+
+            AstNode::Conditional {
+                condition,
+                if_true,
+                if_false,
+            } => {
+                print!("(");
+                self.visit(condition)?;
+                print!(" ? ");
+                self.visit(if_true)?;
+                print!(" : ");
+                self.visit(if_false)?;
+                print!(")");
+                Ok(())
+            }
+
+            AstNode::Rgb { red, green, blue } => {
+                print!("rgb(");
+                self.visit(red)?;
+                print!(", ");
+                self.visit(green)?;
+                print!(", ");
+                self.visit(blue)?;
+                print!(")");
                 Ok(())
             }
 
@@ -318,15 +542,11 @@ impl Visitor<Result<()>> for TreePrinter {
             AstNode::FunctionCall { identifier, args } => {
                 print!("{}(", identifier.span.lexeme);
 
-                let (args, last) = args.split_at(args.len() - 1);
-
-                for arg in args {
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        print!(", ");
+                    }
                     self.visit(arg)?;
-                    print!(", ");
-                }
-
-                if let Some(last) = last.first() {
-                    self.visit(last)?;
                 }
 
                 print!(")");
@@ -357,6 +577,11 @@ impl Visitor<Result<()>> for TreePrinter {
                 print!("{}", token.span.lexeme);
                 Ok(())
             }
+
+            AstNode::StringLiteral(token) => {
+                print!("{}", token.span.lexeme);
+                Ok(())
+            }
             AstNode::PadWidth => {
                 print!("__width");
                 Ok(())
@@ -1,89 +1,322 @@
-use std::path::{Path, PathBuf};
+use std::io::{Cursor, ErrorKind, Read};
 
-// const BUFFER_SIZE: usize = 4096;
+const CHUNK_SIZE: usize = 4096;
 const EOF: char = 0 as char;
-// const N2: usize = BUFFER_SIZE;
-// const N: usize = N2 / 2;
 
 pub trait Stream {
-    fn new(input: &str, path: &Path) -> Self
+    fn new(input: &str) -> Self
     where
         Self: Sized;
     fn rollback(&mut self);
     fn next_char(&mut self) -> char;
     fn get_line(&self) -> usize;
     fn get_col(&self) -> usize;
-    fn get_input_pointer(&self) -> usize;
+    /// The byte offset of the next character in the input.
+    fn get_offset(&self) -> usize;
     fn is_eof(&self) -> bool;
+    #[cfg(test)]
     fn current_char(&self) -> char;
-    fn file_path(&self) -> &str;
+    /// Marks the start of a token. The lexer never rolls back past the mark,
+    /// so the input before it can be dropped.
+    fn mark(&mut self) {}
 }
 
+/// A buffer that walks its input by UTF-8 character, so that columns count
+/// characters while offsets count bytes. A leading byte order mark is skipped
+/// and `\r\n` is read as a single `\n`.
 #[derive(Clone)]
-pub struct SimpleBuffer {
+pub struct Utf8Buffer {
     input: String,
-    input_pointer: usize,
-    file: PathBuf,
+    start: usize,
+    offset: usize,
     line: usize,
     col: usize,
 }
 
-impl Stream for SimpleBuffer {
-    fn new(input: &str, path: &Path) -> SimpleBuffer {
-        SimpleBuffer {
-            line: 1,
-            col: 1,
-            file: path.to_path_buf(),
-            input: input.to_string(),
-            input_pointer: 0,
+impl Utf8Buffer {
+    /// The character at `offset` and its length in bytes. Reading past the
+    /// end gives one byte of EOF, so that the lexer can roll it back.
+    fn char_at(&self, offset: usize) -> (char, usize) {
+        match self
+            .input
+            .get(offset..)
+            .and_then(|rest| rest.chars().next())
+        {
+            Some('\r') if self.input[offset + 1..].starts_with('\n') => ('\n', 2),
+            Some(c) => (c, c.len_utf8()),
+            None => (EOF, 1),
+        }
+    }
+
+    /// The character before `offset` and its length in bytes.
+    fn char_before(&self, offset: usize) -> (char, usize) {
+        if offset > self.input.len() {
+            return (EOF, 1);
+        }
+
+        match self.input[..offset].chars().next_back() {
+            Some('\n') if self.input[..offset - 1].ends_with('\r') => ('\n', 2),
+            Some(c) => (c, c.len_utf8()),
+            None => (EOF, 0),
         }
     }
+}
+
+impl Stream for Utf8Buffer {
+    fn new(input: &str) -> Utf8Buffer {
+        let start = match input.starts_with('\u{feff}') {
+            true => '\u{feff}'.len_utf8(),
+            false => 0,
+        };
 
-    fn file_path(&self) -> &str {
-        self.file.to_str().unwrap()
+        Utf8Buffer {
+            input: input.to_string(),
+            start,
+            offset: start,
+            line: 1,
+            col: 1,
+        }
     }
 
     fn rollback(&mut self) {
-        if self.input_pointer == 0 {
+        if self.offset == self.start {
             panic!("Cannot rollback past the beginning of the input");
         }
 
-        if self
-            .input
-            .chars()
-            .nth(self.input_pointer - 1)
-            .unwrap_or(EOF)
-            == '\n'
-        {
+        let (c, len) = self.char_before(self.offset);
+        self.offset -= len;
+
+        if c == '\n' {
+            // The column is only known by counting back to the previous line
+            let line_start = self.input[self.start..self.offset]
+                .rfind('\n')
+                .map_or(self.start, |i| self.start + i + 1);
             self.line -= 1;
-        } else if self.col > 1 {
-            self.col -= 1;
+            self.col = self.input[line_start..self.offset]
+                .trim_end_matches('\r')
+                .chars()
+                .count()
+                + 1;
         } else {
-            self.line -= 1;
+            self.col -= 1;
+        }
+    }
+
+    fn next_char(&mut self) -> char {
+        let (c, len) = self.char_at(self.offset);
+        if c == '\n' {
+            self.line += 1;
             self.col = 1;
+        } else {
+            self.col += 1;
         }
+        self.offset += len;
+        c
+    }
 
-        self.input_pointer -= 1;
+    fn get_offset(&self) -> usize {
+        self.offset
+    }
+
+    fn is_eof(&self) -> bool {
+        self.offset >= self.input.len()
+    }
+
+    fn get_col(&self) -> usize {
+        self.col
+    }
+
+    fn get_line(&self) -> usize {
+        self.line
+    }
+
+    #[cfg(test)]
+    fn current_char(&self) -> char {
+        self.char_at(self.offset).0
+    }
+}
+
+/// A buffer that reads its input from any [`Read`] in fixed size chunks, so
+/// that files and pipes are never held in memory all at once. The input from
+/// the last [`Stream::mark`] onwards is kept, so the lexer can roll back across
+/// chunk boundaries. Characters are read the same way as [`Utf8Buffer`] reads
+/// them, and a read error ends the input.
+pub struct ReadBuffer {
+    reader: Box<dyn Read>,
+    chunk_size: usize,
+    exhausted: bool,
+    window: Vec<u8>,
+    /// The offset of the first byte in `window`.
+    window_start: usize,
+    offset: usize,
+    mark: usize,
+    line: usize,
+    col: usize,
+    /// The column each line read since the mark ended on, so that rolling
+    /// back over a newline can restore it.
+    line_ends: Vec<usize>,
+}
+
+impl ReadBuffer {
+    pub fn from_reader<R: Read + 'static>(reader: R) -> ReadBuffer {
+        ReadBuffer::with_chunk_size(reader, CHUNK_SIZE)
+    }
+
+    pub fn with_chunk_size<R: Read + 'static>(reader: R, chunk_size: usize) -> ReadBuffer {
+        let mut buffer = ReadBuffer {
+            reader: Box::new(reader),
+            chunk_size,
+            exhausted: false,
+            window: Vec::new(),
+            window_start: 0,
+            offset: 0,
+            mark: 0,
+            line: 1,
+            col: 1,
+            line_ends: Vec::new(),
+        };
+
+        let bom = "\u{feff}".as_bytes();
+        buffer.ensure(bom.len());
+        if buffer.window.starts_with(bom) {
+            buffer.offset = bom.len();
+            buffer.mark = bom.len();
+        }
+
+        buffer
+    }
+
+    fn window_end(&self) -> usize {
+        self.window_start + self.window.len()
+    }
+
+    /// Reads chunks until there are at least `bytes` bytes after the offset,
+    /// or the input runs out.
+    fn ensure(&mut self, bytes: usize) {
+        while !self.exhausted && self.window_end() < self.offset + bytes {
+            self.fill();
+        }
+    }
+
+    /// Drops the input before the mark and reads the next chunk.
+    fn fill(&mut self) {
+        let dropped = self.mark - self.window_start;
+        self.window.drain(..dropped);
+        self.window_start += dropped;
+
+        let len = self.window.len();
+        self.window.resize(len + self.chunk_size, 0);
+
+        let mut read = 0;
+        while read < self.chunk_size {
+            match self.reader.read(&mut self.window[len + read..]) {
+                Ok(0) => {
+                    self.exhausted = true;
+                    break;
+                }
+                Ok(n) => read += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(_) => {
+                    self.exhausted = true;
+                    break;
+                }
+            }
+        }
+
+        self.window.truncate(len + read);
+    }
+
+    /// The character at `offset` and its length in bytes, which must already
+    /// be in the window. Invalid UTF-8 is read one byte at a time as U+FFFD.
+    fn char_at(&self, offset: usize) -> (char, usize) {
+        let i = offset - self.window_start;
+        let width = match self.window.get(i) {
+            None => return (EOF, 1),
+            Some(b'\r') if self.window.get(i + 1) == Some(&b'\n') => return ('\n', 2),
+            Some(0x00..=0x7F) => 1,
+            Some(0xC0..=0xDF) => 2,
+            Some(0xE0..=0xEF) => 3,
+            Some(0xF0..=0xF7) => 4,
+            Some(_) => 1,
+        };
+
+        match self
+            .window
+            .get(i..i + width)
+            .and_then(|bytes| std::str::from_utf8(bytes).ok())
+        {
+            Some(s) => (s.chars().next().unwrap(), width),
+            None => (char::REPLACEMENT_CHARACTER, 1),
+        }
+    }
+
+    /// The character before `offset` and its length in bytes.
+    fn char_before(&self, offset: usize) -> (char, usize) {
+        if offset > self.window_end() {
+            return (EOF, 1);
+        }
+
+        let i = offset - self.window_start;
+        if i >= 2 && self.window[i - 2..i] == *b"\r\n" {
+            return ('\n', 2);
+        }
+
+        // Walk back over continuation bytes to the start of the character
+        let mut start = i - 1;
+        while start > 0 && i - start < 4 && self.window[start] & 0xC0 == 0x80 {
+            start -= 1;
+        }
+
+        match self.char_at(self.window_start + start) {
+            (c, width) if width == i - start => (c, width),
+            _ => (char::REPLACEMENT_CHARACTER, 1),
+        }
+    }
+}
+
+impl Stream for ReadBuffer {
+    fn new(input: &str) -> ReadBuffer {
+        ReadBuffer::from_reader(Cursor::new(input.as_bytes().to_vec()))
+    }
+
+    fn rollback(&mut self) {
+        if self.offset == self.mark {
+            panic!("Cannot rollback past the mark");
+        }
+
+        let (c, len) = self.char_before(self.offset);
+        self.offset -= len;
+
+        if c == '\n' {
+            self.line -= 1;
+            self.col = self.line_ends.pop().unwrap();
+        } else {
+            self.col -= 1;
+        }
     }
 
     fn next_char(&mut self) -> char {
-        let char = self.input.chars().nth(self.input_pointer).unwrap_or(EOF);
-        if char == '\n' {
+        // Enough for the longest character, or a `\r\n`
+        self.ensure(4);
+
+        let (c, len) = self.char_at(self.offset);
+        if c == '\n' {
+            self.line_ends.push(self.col);
             self.line += 1;
             self.col = 1;
         } else {
             self.col += 1;
         }
-        self.input_pointer += 1;
-        char
+        self.offset += len;
+        c
     }
 
-    fn get_input_pointer(&self) -> usize {
-        self.input_pointer
+    fn get_offset(&self) -> usize {
+        self.offset
     }
 
     fn is_eof(&self) -> bool {
-        self.input_pointer >= self.input.len()
+        self.exhausted && self.offset >= self.window_end()
     }
 
     fn get_col(&self) -> usize {
@@ -94,77 +327,121 @@ impl Stream for SimpleBuffer {
         self.line
     }
 
+    #[cfg(test)]
     fn current_char(&self) -> char {
-        self.input.chars().nth(self.input_pointer).unwrap_or(EOF)
+        match self.offset < self.window_end() {
+            true => self.char_at(self.offset).0,
+            false => EOF,
+        }
+    }
+
+    fn mark(&mut self) {
+        self.mark = self.offset;
+        self.line_ends.clear();
     }
 }
 
-// pub struct Buffer {
-//     pub input: String,
-//     pub buffer: [char; N2],
-//     pub input_pointer: usize,
-//     pub fence: usize,
-// }
-
-// impl Stream for Buffer {
-//     fn new(input: &str) -> Buffer {
-//         let mut buffer = [0 as char; N2];
-
-//         for (i, c) in buffer.iter_mut().enumerate().take(N) {
-//             *c = input.chars().nth(i).unwrap_or(EOF);
-//         }
-
-//         Buffer {
-//             input: input.to_string(),
-//             buffer,
-//             input_pointer: 0,
-//             fence: 0,
-//         }
-//     }
-
-//     fn rollback(&mut self) {
-//         if self.input_pointer == self.fence {
-//             panic!("Cannot rollback past the fence");
-//         }
-
-//         self.input_pointer = (self.input_pointer - 1) % (N2);
-//     }
-
-//     fn next_char(&mut self) -> char {
-//         let char = self.buffer[self.input_pointer];
-
-//         if char != EOF {
-//             self.input_pointer = (self.input_pointer + 1) % N2;
-
-//             if self.input_pointer % N == 0 {
-//                 for (i, c) in self
-//                     .buffer
-//                     .iter_mut()
-//                     .enumerate()
-//                     .skip(self.input_pointer)
-//                     .take(self.input_pointer + N2)
-//                 {
-//                     *c = self.input.chars().nth(i).unwrap_or(EOF);
-//                 }
-//                 self.fence = (self.input_pointer + N) % N2;
-//             }
-//         }
-
-//         char
-//     }
-
-//     fn get_input_pointer(&self) -> usize {
-//         self.input_pointer
-//     }
-// }
-
-// impl Default for Buffer {
-//     fn default() -> Self {
-//         Buffer {
-//             input: String::new(),
-//             buffer: [0 as char; BUFFER_SIZE],
-//             input_pointer: 0,
-//             fence: 0,
-//         }
-//     }
-// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    fn test_utf8_positions() {
+        let mut buffer = Utf8Buffer::new("é€x");
+
+        assert_eq!(buffer.next_char(), 'é');
+        assert_eq!((buffer.get_col(), buffer.get_offset()), (2, 2));
+        assert_eq!(buffer.next_char(), '€');
+        assert_eq!((buffer.get_col(), buffer.get_offset()), (3, 5));
+        assert_eq!(buffer.next_char(), 'x');
+        assert!(buffer.is_eof());
+
+        buffer.rollback();
+        buffer.rollback();
+        assert_eq!((buffer.get_col(), buffer.get_offset()), (2, 2));
+        assert_eq!(buffer.current_char(), '€');
+    }
+
+    #[rstest]
+    fn test_crlf_and_bom() {
+        let mut buffer = Utf8Buffer::new("\u{feff}ab\r\nc");
+
+        assert_eq!(buffer.get_offset(), 3);
+        assert_eq!(buffer.next_char(), 'a');
+        assert_eq!(buffer.next_char(), 'b');
+        assert_eq!(buffer.next_char(), '\n');
+        assert_eq!(
+            (buffer.get_line(), buffer.get_col(), buffer.get_offset()),
+            (2, 1, 7)
+        );
+
+        buffer.rollback();
+        assert_eq!(
+            (buffer.get_line(), buffer.get_col(), buffer.get_offset()),
+            (1, 3, 5)
+        );
+        assert_eq!(buffer.current_char(), '\n');
+    }
+
+    #[rstest]
+    fn test_eof_rollback() {
+        let mut buffer = Utf8Buffer::new("a");
+
+        assert_eq!(buffer.next_char(), 'a');
+        assert_eq!(buffer.next_char(), EOF);
+        buffer.rollback();
+        assert_eq!((buffer.get_col(), buffer.get_offset()), (2, 1));
+    }
+
+    #[rstest]
+    #[case(1)]
+    #[case(2)]
+    #[case(3)]
+    fn test_read_buffer_chunk_boundaries(#[case] chunk_size: usize) {
+        let input = "\u{feff}é€\r\nxy";
+        let mut buffer = ReadBuffer::with_chunk_size(Cursor::new(input.as_bytes()), chunk_size);
+
+        assert_eq!(buffer.next_char(), 'é');
+        assert_eq!(buffer.next_char(), '€');
+        assert_eq!(buffer.next_char(), '\n');
+        assert_eq!(buffer.next_char(), 'x');
+        assert_eq!(
+            (buffer.get_line(), buffer.get_col(), buffer.get_offset()),
+            (2, 2, 11)
+        );
+
+        buffer.rollback();
+        buffer.rollback();
+        buffer.rollback();
+        assert_eq!(
+            (buffer.get_line(), buffer.get_col(), buffer.get_offset()),
+            (1, 2, 5)
+        );
+        assert_eq!(buffer.current_char(), '€');
+
+        buffer.mark();
+        assert_eq!(buffer.next_char(), '€');
+        assert_eq!(buffer.next_char(), '\n');
+        assert_eq!(buffer.next_char(), 'x');
+        assert_eq!(buffer.next_char(), 'y');
+        assert_eq!(buffer.next_char(), EOF);
+        assert!(buffer.is_eof());
+
+        buffer.rollback();
+        assert_eq!(buffer.get_offset(), 12);
+        assert_eq!(buffer.next_char(), EOF);
+    }
+
+    #[rstest]
+    fn test_read_buffer_invalid_utf8() {
+        let mut buffer = ReadBuffer::from_reader(Cursor::new(b"a\xffb"));
+
+        assert_eq!(buffer.next_char(), 'a');
+        assert_eq!(buffer.next_char(), char::REPLACEMENT_CHARACTER);
+        assert_eq!(buffer.next_char(), 'b');
+        buffer.rollback();
+        buffer.rollback();
+        assert_eq!(buffer.get_offset(), 1);
+    }
+}
@@ -1,10 +1,10 @@
 use std::path::PathBuf;
 
-use clap::error;
 use thiserror::Error;
 
 use crate::{
     core::{TextSpan, Token, TokenKind},
+    lexing::dfsa::Category,
     semantics::utils::Type,
 };
 
@@ -14,6 +14,8 @@ pub enum Error {
     Lexical(#[from] LexicalError),
     #[error("Parse error: {0}")]
     Parse(#[from] ParseError),
+    #[error("Import error: {0}")]
+    Import(#[from] AstImportError),
     #[error("Semantic error: {0}")]
     Semantic(#[from] SemanticError),
     #[error("IO error: {0}")]
@@ -24,6 +26,48 @@ pub enum Error {
 pub enum LexicalError {
     #[error("Unrecognized character '{}' found at {}:{}", .0.lexeme, .0.from_line, .0.from_col)]
     InvalidCharacter(TextSpan),
+    #[error("Unknown escape sequence in string {} found at {}:{}", .0.lexeme, .0.from_line, .0.from_col)]
+    InvalidEscape(TextSpan),
+    #[error("Number literal {} found at {}:{} is out of range", .0.lexeme, .0.from_line, .0.from_col)]
+    LiteralOutOfRange(TextSpan),
+    #[error("Unterminated block comment starting at {}:{}", .0.from_line, .0.from_col)]
+    UnterminatedComment(TextSpan),
+    #[error("Unterminated string {} found at {}:{}", .0.lexeme, .0.from_line, .0.from_col)]
+    UnterminatedString(TextSpan),
+    #[error("Malformed number literal {} found at {}:{}", .0.lexeme, .0.from_line, .0.from_col)]
+    MalformedNumber(TextSpan),
+    #[error("Malformed colour literal {} found at {}:{}, expected '#' and 6 hex digits", .0.lexeme, .0.from_line, .0.from_col)]
+    MalformedColour(TextSpan),
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum RegexError {
+    #[error("Unexpected end of pattern `{0}`")]
+    UnexpectedEnd(String),
+    #[error("Unexpected '{1}' in pattern `{0}`")]
+    UnexpectedCharacter(String, char),
+    #[error("Pattern `{0}` matches only some of the characters in {1:?}")]
+    SplitsCategory(String, Category),
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum AstImportError {
+    #[error("Unexpected end of input")]
+    UnexpectedEnd,
+    #[error("Unexpected '{1}' at byte {0}")]
+    UnexpectedCharacter(usize, char),
+    #[error("Unknown node `{0}`")]
+    UnknownNode(String),
+    #[error("Unknown token kind `{0}`")]
+    UnknownTokenKind(String),
+    #[error("Node `{0}` is missing field `{1}`")]
+    MissingField(String, String),
+    #[error("Field `{1}` of node `{0}` has the wrong shape")]
+    InvalidField(String, String),
+    #[error("Malformed token")]
+    InvalidToken,
+    #[error("Expected a node, a token, a list or null")]
+    InvalidValue,
 }
 
 #[derive(Debug, Error)]
@@ -50,6 +94,10 @@ pub enum SemanticError {
     UndefinedVariable(Token),
     #[error("Variable '{}' is already defined.", .0.span.lexeme)]
     VariableRedaclaration(Token),
+    #[error("Parameter '{}' is already defined.", .0.span.lexeme)]
+    VariableAlreadyDefined(Token),
+    #[error("'{}' shadows the declaration at {1}:{2}.", .0.span.lexeme)]
+    Shadowing(Token, usize, usize),
     #[error("Function '{}' is not defined.", .0.span.lexeme)]
     UndefinedFunction(Token),
     #[error("Function '{}' is already defined.", .0.span.lexeme)]
@@ -68,8 +116,52 @@ pub enum SemanticError {
     FunctionReturnTypeMismatch(Token, Type, Type),
     #[error("Function '{}' takes no parameters, supplied {:?}.", .0, .1)]
     FunctionCallNoParams(String, Vec<Type>),
+    #[error("Function '{}' takes {1} arguments, supplied {2}.", .0.span.lexeme)]
+    ArgumentCountMismatch(Token, usize, usize),
+    #[error("'{}' is of type {1} and can't be called.", .0.span.lexeme)]
+    NotCallable(Token, Type),
+    #[error("Function '{}' uses globals, so it can't be used as a value.", .0.span.lexeme)]
+    FunctionValueUsesGlobals(Token),
     #[error("Variable '{}' was not defined in the current scope.", .0.span.lexeme)]
     VarUndefinedInFunc(Token),
+    #[error("Cannot assign to constant '{}'.", .0.span.lexeme)]
+    ConstAssignment(Token),
+    #[error("Function '{}' returns void and can't be used as a value.", .0.span.lexeme)]
+    VoidValue(Token),
+    #[error("Variable '{}' can't be of type void.", .0.span.lexeme)]
+    VoidVariable(Token),
+    #[error("Can't infer the type of '{}' from its initializer.", .0.span.lexeme)]
+    CannotInferType(Token),
+    #[error("Expected a tuple of {1} values to destructure into '{}', ...", .0.span.lexeme)]
+    DestructureMismatch(Token, usize),
+    #[error("'{}' can't hold a tuple of {1} values, destructure it with 'let (a, b, ...) = ...' instead.", .0.span.lexeme)]
+    TupleVariable(Token, usize),
+    #[error("Match arm '{}' overlaps with a previous arm.", .0.span.lexeme)]
+    DuplicateMatchArm(Token),
+    #[error("Invalid match range '{}'.", .0)]
+    InvalidMatchRange(String),
+    #[error("Match is not exhaustive, '{}' is not covered.", .0)]
+    NonExhaustiveMatch(String),
+    #[error("Constant '{}' must be initialized with a constant expression.", .0.span.lexeme)]
+    NonConstantInitializer(Token),
+    #[error("Constant '{}' divides by zero.", .0.span.lexeme)]
+    ConstantDivisionByZero(Token),
+    #[error("Constant '{}' overflows its type.", .0.span.lexeme)]
+    ConstantOverflow(Token),
+    #[error("Type '{}' is not defined.", .0.span.lexeme)]
+    UndefinedType(Token),
+    #[error("Type '{}' is already defined.", .0.span.lexeme)]
+    TypeAlreadyDefined(Token),
+    #[error("Type '{}' has no field '{}'.", .1, .0.span.lexeme)]
+    UnknownField(Token, Type),
+    #[error("Field '{}' is given more than once.", .0.span.lexeme)]
+    DuplicateField(Token),
+    #[error("Struct literal '{}' is missing field '{}'.", .0.span.lexeme, .1)]
+    MissingField(Token, String),
+    #[error("String {} can only be printed.", .0.span.lexeme)]
+    StringOutsidePrint(Token),
+    #[error("Colour channel '{}' is read-only.", .0.span.lexeme)]
+    ChannelAssignment(Token),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -1,6 +1,7 @@
 pub mod buffer;
 pub mod errors;
 
-pub use buffer::SimpleBuffer;
+pub use buffer::ReadBuffer;
 pub use buffer::Stream;
+pub use buffer::Utf8Buffer;
 pub use errors::Result;